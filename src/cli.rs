@@ -0,0 +1,361 @@
+// Date: Tue Nov 21 22:04:33 2023
+// Mail: lunar_ubuntu@qq.com
+// Author: https://github.com/xiaoqixian
+
+/*
+ * Subcommands that run without the ui, `tmusic <command> ...`
+ *
+ *   fix-tags [--write] <path>...
+ *       Tell which ID3 tags in legacy encodings would be
+ *       rewritten as UTF-8, and rewrite them with --write.
+ *       Directories are walked for audio files.
+ *
+ *   tag [--<field> <value>]... <path>...
+ *       Set the fields of the tags, an empty value clears
+ *       one. Without any field the tags are printed. The
+ *       fields are title, artist, album, album-artist, track,
+ *       disc, year, genre, composer and comment.
+ *
+ *   guess [--write] <path>...
+ *       Tell the fields missing in the tags that would be
+ *       filled by what the paths tell, and write them with
+ *       --write. The patterns are in the config.
+ *
+ *   export-data [<file>]
+ *       Write the ratings, loved songs and play counts as
+ *       JSON to the file, or print them.
+ *
+ *   export-listens [<file>]
+ *       Write the plays in the scrobble log as a ListenBrainz
+ *       import to the file, or print them.
+ *
+ *   stats [--period week|month|year|all] [--json]
+ *       Print the listening statistics of the history in the
+ *       period, the last week by default, and what the library
+ *       is made of. --json prints all the dashboard shows.
+ */
+
+use std::path::{Path, PathBuf};
+
+use crate::{
+    xdg,
+    config::config,
+    library::{
+        self,
+        Library,
+        stats::StatsBook,
+        report::{Period, Report}
+    },
+    playback::{
+        scrobble,
+        history::{History, unix_now},
+        metadata::{
+            read_tags,
+            fix::fix_tags,
+            guess::guess_tags,
+            edit::{Field, Edit, write_tags}
+        }
+    }
+};
+
+const USAGE: &str = "usage: tmusic [fix-tags [--write] <path>... | \
+    tag [--<field> <value>]... <path>... | guess [--write] <path>... | export-data [<file>] | export-listens [<file>] | \
+    stats [--period week|month|year|all] [--json]]";
+
+/// Expand the directories among `paths` into their audio files.
+fn files_of(paths: &[String]) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for path in paths {
+        match Path::new(path).is_dir() {
+            true => library::walk(Path::new(path), &mut files),
+            false => files.push(PathBuf::from(path))
+        }
+    }
+    files
+}
+
+fn fix_tags_command(args: &[String]) -> i32 {
+    let write = args.iter().any(|arg| arg == "--write");
+    let paths = args.iter()
+        .filter(|arg| *arg != "--write")
+        .cloned()
+        .collect::<Vec<String>>();
+
+    if paths.is_empty() {
+        eprintln!("{}", USAGE);
+        return 2;
+    }
+
+    let mut library = Library::open_default();
+    let mut status = 0;
+    for file in files_of(&paths) {
+        match fix_tags(&file, write) {
+            Ok(fixes) if fixes.is_empty() => {},
+            Ok(fixes) => {
+                if write {
+                    let _ = library.refresh(&file);
+                }
+                println!("{}", file.display());
+                for fix in fixes {
+                    match fix.from {
+                        Some(from) => println!("  {}: {} -> {}", fix.frame, from, fix.to),
+                        None => println!("  {}: + {}", fix.frame, fix.to)
+                    }
+                }
+            },
+            Err(e) => {
+                eprintln!("{}: {:?}", file.display(), e);
+                status = 1;
+            }
+        }
+    }
+
+    match write {
+        true => if let Err(e) = library.save() {
+            eprintln!("saving the library failed: {}", e);
+        },
+        false => println!("nothing is written without --write")
+    }
+    status
+}
+
+fn print_tags(file: &Path) {
+    let tags = read_tags(file);
+    println!("{}", file.display());
+    for field in Field::ALL {
+        if let Some(value) = field.get(&tags) {
+            println!("  {}: {}", field.name(), value);
+        }
+    }
+}
+
+fn tag_command(args: &[String]) -> i32 {
+    let mut edits: Vec<Edit> = Vec::new();
+    let mut paths = Vec::new();
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        let Some(name) = arg.strip_prefix("--") else {
+            paths.push(arg.clone());
+            continue;
+        };
+        match (Field::by_name(name), args.next()) {
+            (Some(field), Some(value)) => edits.push((field, Some(value.clone()).filter(|v| !v.is_empty()))),
+            _ => {
+                eprintln!("{}", USAGE);
+                return 2;
+            }
+        }
+    }
+
+    if paths.is_empty() {
+        eprintln!("{}", USAGE);
+        return 2;
+    }
+
+    let files = files_of(&paths);
+    if edits.is_empty() {
+        files.iter().for_each(|file| print_tags(file));
+        return 0;
+    }
+
+    let mut library = Library::open_default();
+    let mut status = 0;
+    for file in files {
+        match write_tags(&file, &edits) {
+            Ok(_) => {
+                let _ = library.refresh(&file);
+            },
+            Err(e) => {
+                eprintln!("{}: {:?}", file.display(), e);
+                status = 1;
+            }
+        }
+    }
+
+    if let Err(e) = library.save() {
+        eprintln!("saving the library failed: {}", e);
+    }
+    status
+}
+
+/// The fields missing in the tags of a file that its path tells.
+fn guessed_edits(file: &Path) -> Vec<Edit> {
+    let (tags, guessed) = (read_tags(file), guess_tags(file));
+    Field::ALL
+        .into_iter()
+        .filter(|field| field.get(&tags).is_none())
+        .filter_map(|field| field.get(&guessed).map(|value| (field, Some(value))))
+        .collect()
+}
+
+fn guess_command(args: &[String]) -> i32 {
+    let write = args.iter().any(|arg| arg == "--write");
+    let paths = args.iter()
+        .filter(|arg| *arg != "--write")
+        .cloned()
+        .collect::<Vec<String>>();
+
+    if paths.is_empty() {
+        eprintln!("{}", USAGE);
+        return 2;
+    }
+
+    let mut library = Library::open_default();
+    let mut status = 0;
+    for file in files_of(&paths) {
+        let edits = guessed_edits(&file);
+        if edits.is_empty() {
+            continue;
+        }
+
+        println!("{}", file.display());
+        for (field, value) in edits.iter() {
+            println!("  {}: + {}", field.name(), value.as_deref().unwrap_or_default());
+        }
+
+        if write {
+            match write_tags(&file, &edits) {
+                Ok(_) => {
+                    let _ = library.refresh(&file);
+                },
+                Err(e) => {
+                    eprintln!("{}: {:?}", file.display(), e);
+                    status = 1;
+                }
+            }
+        }
+    }
+
+    match write {
+        true => if let Err(e) = library.save() {
+            eprintln!("saving the library failed: {}", e);
+        },
+        false => println!("nothing is written without --write")
+    }
+    status
+}
+
+fn export_data_command(args: &[String]) -> i32 {
+    if args.len() > 1 {
+        eprintln!("{}", USAGE);
+        return 2;
+    }
+
+    let history = History::open(xdg::state_file("history.jsonl"));
+    let exported = StatsBook::open_default(history.entries()).export();
+    match args.first() {
+        None => {
+            println!("{}", exported);
+            0
+        },
+        Some(file) => match std::fs::write(file, exported) {
+            Ok(_) => 0,
+            Err(e) => {
+                eprintln!("{}: {}", file, e);
+                1
+            }
+        }
+    }
+}
+
+fn export_listens_command(args: &[String]) -> i32 {
+    if args.len() > 1 {
+        eprintln!("{}", USAGE);
+        return 2;
+    }
+
+    let log = match config().scrobble.log_path() {
+        Some(log) => log,
+        None => {
+            eprintln!("no data directory for the scrobble log");
+            return 1;
+        }
+    };
+    let scrobbles = match scrobble::read_log(&log) {
+        Ok(scrobbles) => scrobbles,
+        Err(e) => {
+            eprintln!("{}: {}", log.display(), e);
+            return 1;
+        }
+    };
+
+    let exported = scrobble::listenbrainz(&scrobbles);
+    match args.first() {
+        None => {
+            println!("{}", exported);
+            0
+        },
+        Some(file) => match std::fs::write(file, exported) {
+            Ok(_) => 0,
+            Err(e) => {
+                eprintln!("{}: {}", file, e);
+                1
+            }
+        }
+    }
+}
+
+fn stats_command(args: &[String]) -> i32 {
+    let mut period = Period::Week;
+    let mut json = false;
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => json = true,
+            "--period" => match args.next().and_then(|name| Period::by_name(name)) {
+                Some(p) => period = p,
+                None => {
+                    eprintln!("{}", USAGE);
+                    return 2;
+                }
+            },
+            _ => {
+                eprintln!("{}", USAGE);
+                return 2;
+            }
+        }
+    }
+
+    let history = History::open(xdg::state_file("history.jsonl"));
+    let offset = config().stats.utc_offset_minutes as i64 * 60;
+    let report = Report::new(period, history.entries(), &Library::open_default(), unix_now(), offset);
+
+    if json {
+        match serde_json::to_string_pretty(&report) {
+            Ok(json) => println!("{}", json),
+            Err(e) => {
+                eprintln!("{}", e);
+                return 1;
+            }
+        }
+        return 0;
+    }
+
+    println!("{} played, {} skipped, {}h{:02}m listened",
+        report.plays, report.skips, report.listened_secs / 3600, report.listened_secs / 60 % 60);
+    for (title, tops) in [("artists", &report.top_artists), ("albums", &report.top_albums), ("tracks", &report.top_tracks)] {
+        println!("top {}:", title);
+        for (i, top) in tops.iter().enumerate() {
+            println!("  {:>2}. {} ({})", i + 1, top.name, top.plays);
+        }
+    }
+    0
+}
+
+/// Run a subcommand, returns the exit status.
+pub fn run(args: &[String]) -> i32 {
+    match args[0].as_str() {
+        "fix-tags" => fix_tags_command(&args[1..]),
+        "tag" => tag_command(&args[1..]),
+        "guess" => guess_command(&args[1..]),
+        "export-data" => export_data_command(&args[1..]),
+        "export-listens" => export_listens_command(&args[1..]),
+        "stats" => stats_command(&args[1..]),
+        _ => {
+            eprintln!("{}", USAGE);
+            2
+        }
+    }
+}
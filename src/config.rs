@@ -0,0 +1,170 @@
+// Date: Wed Nov  8 19:31:27 2023
+// Mail: lunar_ubuntu@qq.com
+// Author: https://github.com/xiaoqixian
+
+/*
+ * User configuration, read from $XDG_CONFIG_HOME/tmusic/config.toml
+ * Every field has a default, so the file and any of its
+ * sections can be left out.
+ */
+
+use std::{
+    fs,
+    path::PathBuf,
+    sync::OnceLock
+};
+
+use serde::Deserialize;
+
+use super::xdg;
+
+pub const CONFIG_FILE: &str = "config.toml";
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub resume: ResumeConfig,
+    pub library: LibraryConfig,
+    pub tags: TagsConfig,
+    pub guess: GuessConfig,
+    pub browser: BrowserConfig,
+    pub stats: StatsConfig,
+    pub scrobble: ScrobbleConfig
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ResumeConfig {
+    /// Only files at least this long remember
+    /// where they were stopped.
+    pub min_duration_secs: u64
+}
+
+impl Default for ResumeConfig {
+    fn default() -> Self {
+        Self {
+            min_duration_secs: 20 * 60
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LibraryConfig {
+    /// Directories scanned for songs, `~` is
+    /// expanded to the home directory.
+    pub roots: Vec<String>
+}
+
+impl Default for LibraryConfig {
+    fn default() -> Self {
+        Self {
+            roots: vec![String::from("~/Music")]
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct TagsConfig {
+    /// Encoding of legacy tags the detection is not sure
+    /// about, a WHATWG label like "gbk" or "big5".
+    pub fallback_encoding: Option<String>
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct GuessConfig {
+    /// Patterns the tags of untagged files are guessed by,
+    /// tried in order, see playback/metadata/guess.rs
+    pub patterns: Vec<String>
+}
+
+impl Default for GuessConfig {
+    fn default() -> Self {
+        Self {
+            patterns: [
+                "{artist}/{album}/{track} - {title}",
+                "{artist}/{album}/{track}. {title}",
+                "{artist} - {title}",
+                "{title}-{artist}"
+            ]
+            .into_iter()
+            .map(String::from)
+            .collect()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct BrowserConfig {
+    /// Where the folder browser starts, the working
+    /// directory if it is not set.
+    pub root: Option<String>
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct StatsConfig {
+    /// The local time zone of the listening statistics,
+    /// 480 for UTC+8. There is no time zone database to
+    /// ask, so it is UTC unless set.
+    pub utc_offset_minutes: i32
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ScrobbleConfig {
+    /// Where the plays are logged for scrobbling, in
+    /// the data directory if it is not set.
+    pub log: Option<String>
+}
+
+/// Expand a leading `~` to $HOME.
+pub fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix('~'), std::env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest.trim_start_matches('/')),
+        _ => PathBuf::from(path)
+    }
+}
+
+impl LibraryConfig {
+    pub fn root_paths(&self) -> Vec<PathBuf> {
+        self.roots.iter().map(|root| expand_home(root)).collect()
+    }
+}
+
+impl BrowserConfig {
+    pub fn root_path(&self) -> PathBuf {
+        match self.root {
+            Some(ref root) => expand_home(root),
+            None => std::env::current_dir().unwrap_or_else(|_| PathBuf::from("/"))
+        }
+    }
+}
+
+impl ScrobbleConfig {
+    pub fn log_path(&self) -> Option<PathBuf> {
+        match self.log {
+            Some(ref log) => Some(expand_home(log)),
+            None => xdg::data_dir().map(|dir| dir.join(".scrobbler.log"))
+        }
+    }
+}
+
+impl Config {
+    /// A broken config file is ignored as a whole.
+    pub fn load() -> Self {
+        xdg::config_dir()
+            .and_then(|dir| fs::read_to_string(dir.join(CONFIG_FILE)).ok())
+            .and_then(|content| toml::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+}
+
+/// The config is loaded once, at the first access.
+pub fn config() -> &'static Config {
+    static CONFIG: OnceLock<Config> = OnceLock::new();
+    CONFIG.get_or_init(Config::load)
+}
@@ -0,0 +1,199 @@
+// Date: Fri Dec  1 20:17:43 2023
+// Mail: lunar_ubuntu@qq.com
+// Author: https://github.com/xiaoqixian
+
+/*
+ * The library arranged for browsing: artists, their albums
+ * and the tracks of those, narrowed by a genre or a year.
+ *
+ * Tracks are grouped into albums by their album tag and
+ * their directory, so two albums of the same name stay
+ * apart. An album belongs to its album artist, or to the
+ * only artist of its tracks, and an album of several
+ * artists without an album artist is a compilation, which
+ * goes under VARIOUS_ARTISTS.
+ *
+ * Albums are sorted by year, the ones without a year go
+ * last, and tracks by disc and track number.
+ */
+
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet},
+    path::Path
+};
+
+use super::{Library, track::Track};
+
+pub const VARIOUS_ARTISTS: &str = "Various Artists";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Facet {
+    All,
+    Genre(String),
+    Year(u32)
+}
+
+impl Facet {
+    pub fn matches(&self, track: &Track) -> bool {
+        match self {
+            Facet::All => true,
+            Facet::Genre(genre) => track.tags.genre.as_ref()
+                .is_some_and(|g| g.eq_ignore_ascii_case(genre)),
+            Facet::Year(year) => track.tags.year == Some(*year)
+        }
+    }
+}
+
+/// Genres of the library, sorted.
+#[inline]
+pub fn genres(library: &Library) -> Vec<Facet> {
+    library.values(|track| track.tags.genre.as_deref())
+        .into_iter()
+        .map(Facet::Genre)
+        .collect()
+}
+
+/// Years of the library, the latest first.
+pub fn years(library: &Library) -> Vec<Facet> {
+    library.tracks()
+        .filter_map(|track| track.tags.year)
+        .collect::<BTreeSet<u32>>()
+        .into_iter()
+        .rev()
+        .map(Facet::Year)
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Album {
+    /// empty if nobody is known
+    pub artist: String,
+    /// empty if the tracks have no album tag
+    pub title: String,
+    pub year: Option<u32>,
+    pub tracks: Vec<Track>
+}
+
+impl Album {
+    fn new(title: String, mut tracks: Vec<Track>) -> Self {
+        let artists = tracks.iter()
+            .filter_map(|track| track.tags.artist.as_deref())
+            .collect::<BTreeSet<&str>>();
+
+        let artist = match tracks.iter().find_map(|track| track.tags.album_artist.clone()) {
+            Some(album_artist) => album_artist,
+            None if artists.len() > 1 => String::from(VARIOUS_ARTISTS),
+            None => artists.into_iter().next().map(String::from).unwrap_or_default()
+        };
+
+        tracks.sort_by(|a, b| {
+            let key = |track: &Track| (track.tags.disc.unwrap_or(1), track.tags.track.unwrap_or(u32::MAX));
+            key(a).cmp(&key(b)).then_with(|| a.path.cmp(&b.path))
+        });
+
+        Self {
+            artist,
+            title,
+            year: tracks.iter().filter_map(|track| track.tags.year).min(),
+            tracks
+        }
+    }
+
+    #[inline]
+    pub fn paths(&self) -> impl Iterator<Item = String> + '_ {
+        self.tracks.iter().map(|track| track.path.clone())
+    }
+}
+
+/// Albums without a year go after the ones with.
+fn by_year(a: Option<u32>, b: Option<u32>) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal
+    }
+}
+
+/// The albums of the tracks passing a facet, sorted by
+/// artist, then year, then title.
+pub fn albums(library: &Library, facet: &Facet) -> Vec<Album> {
+    let mut groups = BTreeMap::<(String, String), Vec<Track>>::new();
+
+    for track in library.tracks().filter(|track| facet.matches(track)) {
+        let dir = Path::new(&track.path)
+            .parent()
+            .map(|dir| dir.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let title = track.tags.album.clone().unwrap_or_default();
+        groups.entry((title, dir)).or_default().push(track.clone());
+    }
+
+    let mut albums = groups
+        .into_iter()
+        .map(|((title, _), tracks)| Album::new(title, tracks))
+        .collect::<Vec<Album>>();
+
+    albums.sort_by(|a, b| a.artist.to_lowercase().cmp(&b.artist.to_lowercase())
+        .then_with(|| by_year(a.year, b.year))
+        .then_with(|| a.title.cmp(&b.title)));
+    albums
+}
+
+/// The artists of some albums, in the order of the albums.
+pub fn artists(albums: &[Album]) -> Vec<String> {
+    let mut artists = albums.iter()
+        .map(|album| album.artist.clone())
+        .collect::<Vec<String>>();
+    artists.dedup();
+    artists
+}
+
+#[test]
+fn test_browse_albums() {
+    use super::track::Tags;
+
+    let mut library = Library::open(None);
+    let tracks = [
+        ("/m/fishmans/long season/01.flac", "Fishmans", None, "Long Season", Some(1996), Some(1), Some(1)),
+        ("/m/fishmans/kuchu/02.flac", "Fishmans", None, "空中キャンプ", Some(1996), Some(1), Some(2)),
+        ("/m/fishmans/kuchu/01.flac", "Fishmans", None, "空中キャンプ", Some(1996), Some(1), Some(1)),
+        ("/m/fishmans/orange/01.flac", "Fishmans", None, "Orange", Some(1994), None, None),
+        ("/m/fishmans/demo/01.flac", "Fishmans", None, "Demo", None, None, None),
+        ("/m/mix/01.flac", "A", None, "Mix", Some(2000), Some(2), Some(1)),
+        ("/m/mix/02.flac", "B", None, "Mix", Some(2000), Some(1), Some(5)),
+        ("/m/tribute/01.flac", "C", Some("Fishmans"), "Tribute", Some(2005), None, Some(1))
+    ];
+    for (path, artist, album_artist, album, year, disc, track) in tracks {
+        library.insert(Track {
+            path: String::from(path),
+            tags: Tags {
+                artist: Some(String::from(artist)),
+                album_artist: album_artist.map(String::from),
+                album: Some(String::from(album)),
+                year,
+                disc,
+                track,
+                ..Tags::default()
+            },
+            ..Track::default()
+        });
+    }
+
+    let all = albums(&library, &Facet::All);
+    assert_eq!(artists(&all), vec!["Fishmans", VARIOUS_ARTISTS]);
+
+    let fishmans = all.iter()
+        .filter(|album| album.artist == "Fishmans")
+        .map(|album| album.title.as_str())
+        .collect::<Vec<&str>>();
+    assert_eq!(fishmans, vec!["Orange", "Long Season", "空中キャンプ", "Tribute", "Demo"]);
+
+    let kuchu = all.iter().find(|album| album.title == "空中キャンプ").unwrap();
+    assert_eq!(kuchu.paths().collect::<Vec<String>>(), vec!["/m/fishmans/kuchu/01.flac", "/m/fishmans/kuchu/02.flac"]);
+    let mix = all.iter().find(|album| album.title == "Mix").unwrap();
+    assert_eq!(mix.tracks[0].path, "/m/mix/02.flac");
+
+    assert_eq!(albums(&library, &Facet::Year(1994)).len(), 1);
+}
@@ -0,0 +1,155 @@
+// Date: Fri Nov 17 20:11:08 2023
+// Mail: lunar_ubuntu@qq.com
+// Author: https://github.com/xiaoqixian
+
+/*
+ * The library is an index of every song under the root
+ * directories in the config, with its tags and codec info.
+ * The index is kept in $XDG_DATA_HOME/tmusic/library.json,
+ * and rescans only probe the files whose mtime or size
+ * changed since they were indexed.
+ *
+ * The roots are watched from launch on and the changes are
+ * applied as they come, see watch.rs. The changes made while
+ * the launch scan runs are applied once it is done.
+ *
+ * The library is searched fuzzily, by pinyin as well for
+ * CJK text, see search/mod.rs
+ */
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    io,
+    path::PathBuf,
+    sync::{Arc, RwLock}
+};
+
+use crate::{xdg, config::config};
+
+mod probe;
+mod scan;
+mod watch;
+pub mod track;
+pub mod search;
+pub mod stats;
+pub mod smart;
+pub mod browse;
+pub mod report;
+
+pub use scan::{walk, is_audio};
+use track::Track;
+
+pub const LIBRARY_FILE: &str = "library.json";
+
+/// Extensions of the files that are indexed, those are
+/// the ones the player decodes or the tag editor writes.
+/// Opus is only tagged, symphonia does not decode it yet.
+/// APE is left out, neither of them handles it.
+pub const AUDIO_EXTENSIONS: [&str; 12] = [
+    "mp3", "flac", "ogg", "oga", "opus", "wav", "m4a", "m4b", "mp4", "aac", "mka", "webm"
+];
+
+#[derive(Debug, Clone, Default)]
+pub struct Library {
+    /// tracks by their paths
+    tracks: BTreeMap<String, Track>,
+    file: Option<PathBuf>,
+    /// bumped on every change, so the views can
+    /// tell when to refresh what they show
+    generation: u64
+}
+
+impl Library {
+    pub fn open(file: Option<PathBuf>) -> Self {
+        let tracks = file.as_ref()
+            .and_then(|file| fs::read_to_string(file).ok())
+            .and_then(|content| serde_json::from_str::<Vec<Track>>(&content).ok())
+            .unwrap_or_default()
+            .into_iter()
+            .map(|track| (track.path.clone(), track))
+            .collect();
+
+        Self {
+            tracks,
+            file,
+            generation: 0
+        }
+    }
+
+    /// Open the index in the data directory.
+    #[inline]
+    pub fn open_default() -> Self {
+        Self::open(xdg::data_dir().map(|dir| dir.join(LIBRARY_FILE)))
+    }
+
+    /// The index is written to a temporary file first and
+    /// then renamed, so a crash never leaves half an index.
+    pub fn save(&self) -> io::Result<()> {
+        let file = match self.file {
+            None => return Ok(()),
+            Some(ref file) => file
+        };
+
+        let tracks = self.tracks.values().collect::<Vec<&Track>>();
+        let content = serde_json::to_string(&tracks).map_err(io::Error::other)?;
+
+        let tmp = file.with_extension("json.tmp");
+        fs::write(&tmp, content)?;
+        fs::rename(&tmp, file)
+    }
+
+    #[inline]
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    #[inline]
+    pub fn get(&self, path: &str) -> Option<&Track> {
+        self.tracks.get(path)
+    }
+
+    #[inline]
+    pub fn tracks(&self) -> impl Iterator<Item = &Track> {
+        self.tracks.values()
+    }
+
+    /// Distinct values of a field, sorted.
+    pub fn values<F>(&self, field: F) -> Vec<String>
+    where F: Fn(&Track) -> Option<&str>
+    {
+        let mut values = self.tracks
+            .values()
+            .filter_map(field)
+            .map(String::from)
+            .collect::<Vec<String>>();
+
+        values.sort();
+        values.dedup();
+        values
+    }
+}
+
+/// Rescan the configured roots in the background and keep
+/// watching them afterwards. The library stays usable while
+/// the scan runs, see scan::scan. The roots are watched
+/// before the scan starts, so nothing changed meanwhile is
+/// missed.
+///
+/// `on_move` is called with the old and new paths of every
+/// file or directory moved within the roots.
+pub fn spawn<F>(library: Arc<RwLock<Library>>, on_move: F)
+where F: Fn(String, String) + Send + 'static
+{
+    let _ = std::thread::spawn(move || {
+        let roots = config().library.root_paths();
+        let watching = watch::start(&roots);
+
+        let _ = scan::scan(&library, &roots);
+        let _ = library.read().unwrap().save();
+
+        if let Some(watching) = watching {
+            watching.run(&library, on_move);
+        }
+    });
+}
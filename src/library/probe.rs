@@ -0,0 +1,47 @@
+// Date: Fri Nov 17 21:04:56 2023
+// Mail: lunar_ubuntu@qq.com
+// Author: https://github.com/xiaoqixian
+
+/*
+ * Probe a file with symphonia for its tags and codec
+ * parameters, nothing is decoded. The tags are read the
+ * way the player reads them, see metadata::probe.
+ */
+
+use std::path::Path;
+
+use symphonia::core::units::Time;
+
+use crate::playback::metadata::{self, guess::guess_tags};
+
+use super::track::Track;
+
+/// None if the file cannot be opened or is not audio.
+pub fn probe(path: &Path) -> Option<Track> {
+    let (tags, format) = metadata::probe(path)?;
+
+    let mut track = Track {
+        path: path.to_string_lossy().into_owned(),
+        tags,
+        ..Default::default()
+    };
+    // guessed last, the tags in the file always win
+    track.tags.fill(guess_tags(path));
+
+    let params = &format.default_track()?.codec_params;
+    track.codec = symphonia::default::get_codecs()
+        .get_codec(params.codec)
+        .map(|codec| String::from(codec.short_name));
+    track.sample_rate = params.sample_rate;
+    track.channels = params.channels.map(|channels| channels.count() as u16);
+    track.bits_per_sample = params.bits_per_sample;
+    track.duration_ms = match (params.time_base, params.n_frames) {
+        (Some(time_base), Some(n_frames)) => {
+            let Time { seconds, frac } = time_base.calc_time(n_frames);
+            Some(seconds * 1000 + (frac * 1000.0) as u64)
+        },
+        _ => None
+    };
+
+    Some(track)
+}
@@ -0,0 +1,290 @@
+// Date: Mon Dec  4 20:41:09 2023
+// Mail: lunar_ubuntu@qq.com
+// Author: https://github.com/xiaoqixian
+
+/*
+ * Listening statistics over a period of the history, and
+ * what the library is made of. The dashboard draws them,
+ * and `tmusic stats --json` prints them.
+ *
+ * A song counts as played if it was played to the end, as
+ * in stats.rs, and the top artists, albums and tracks are
+ * the ones played the most. The listening time counts the
+ * skipped songs as well, it goes to the hour and the day
+ * the song was started in, local to the offset in the
+ * config.
+ */
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use super::{Library, track::Tags};
+use crate::playback::{
+    display_name,
+    metadata,
+    history::{HistoryEntry, PlayStatus}
+};
+
+/// How many artists, albums and tracks are on the tops.
+pub const TOP: usize = 10;
+
+/// The bitrate ranges in kbps, by their lower ends.
+const BITRATES: [(u64, &str); 6] = [
+    (0, "<128"),
+    (128, "128-191"),
+    (192, "192-255"),
+    (256, "256-319"),
+    (320, "320-499"),
+    (500, "500+")
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Period {
+    Week,
+    Month,
+    Year,
+    All
+}
+
+impl Period {
+    pub fn by_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "week" => Self::Week,
+            "month" => Self::Month,
+            "year" => Self::Year,
+            "all" => Self::All,
+            _ => return None
+        })
+    }
+
+    /// How far back it goes, None for the whole history.
+    pub fn secs(&self) -> Option<u64> {
+        match self {
+            Self::Week => Some(7 * 86400),
+            Self::Month => Some(30 * 86400),
+            Self::Year => Some(365 * 86400),
+            Self::All => None
+        }
+    }
+
+    pub fn next(&self) -> Self {
+        match self {
+            Self::Week => Self::Month,
+            Self::Month => Self::Year,
+            Self::Year => Self::All,
+            Self::All => Self::Week
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Top {
+    pub name: String,
+    pub plays: u32,
+    /// seconds listened, skipped songs included
+    pub secs: u64
+}
+
+/// How many tracks of the library fall into something.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Share {
+    pub name: String,
+    pub tracks: usize
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    pub period: Period,
+    pub plays: u32,
+    pub skips: u32,
+    pub listened_secs: u64,
+    pub top_artists: Vec<Top>,
+    pub top_albums: Vec<Top>,
+    pub top_tracks: Vec<Top>,
+    /// seconds listened in each hour of the day
+    pub by_hour: [u64; 24],
+    /// seconds listened on each day of the week, Monday first
+    pub by_weekday: [u64; 7],
+    pub formats: Vec<Share>,
+    /// in the order of the ranges, the empty ones left out
+    pub bitrates: Vec<Share>,
+    pub genres: Vec<Share>
+}
+
+/// Add a play to the counts of a name.
+fn count(counts: &mut HashMap<String, Top>, name: String, entry: &HistoryEntry) {
+    let top = counts.entry(name.clone()).or_insert_with(|| Top {
+        name,
+        ..Top::default()
+    });
+    top.plays += (entry.status == PlayStatus::Completed) as u32;
+    top.secs += entry.played_secs;
+}
+
+/// The names played the most, by time listened if
+/// they were played as often.
+fn top(counts: HashMap<String, Top>) -> Vec<Top> {
+    let mut tops = counts
+        .into_values()
+        .filter(|top| top.plays > 0)
+        .collect::<Vec<Top>>();
+    tops.sort_by(|a, b| b.plays.cmp(&a.plays)
+        .then(b.secs.cmp(&a.secs))
+        .then_with(|| a.name.cmp(&b.name)));
+    tops.truncate(TOP);
+    tops
+}
+
+/// The largest shares first.
+fn shares(counts: HashMap<String, usize>) -> Vec<Share> {
+    let mut shares = counts
+        .into_iter()
+        .map(|(name, tracks)| Share { name, tracks })
+        .collect::<Vec<Share>>();
+    shares.sort_by(|a, b| b.tracks.cmp(&a.tracks).then_with(|| a.name.cmp(&b.name)));
+    shares
+}
+
+impl Report {
+    /// The report of the entries started in the period up to
+    /// `now`, local to `utc_offset` seconds.
+    pub fn new(
+        period: Period,
+        entries: &[HistoryEntry],
+        library: &Library,
+        now: u64,
+        utc_offset: i64
+    ) -> Self {
+        let since = period.secs().map_or(0, |secs| now.saturating_sub(secs));
+        let mut report = Self {
+            period,
+            plays: 0,
+            skips: 0,
+            listened_secs: 0,
+            top_artists: Vec::new(),
+            top_albums: Vec::new(),
+            top_tracks: Vec::new(),
+            by_hour: [0; 24],
+            by_weekday: [0; 7],
+            formats: Vec::new(),
+            bitrates: Vec::new(),
+            genres: Vec::new()
+        };
+
+        let (mut artists, mut albums, mut tracks) = (HashMap::new(), HashMap::new(), HashMap::new());
+        for entry in entries.iter().filter(|entry| entry.started_at >= since) {
+            match entry.status {
+                PlayStatus::Completed => report.plays += 1,
+                PlayStatus::Skipped => report.skips += 1,
+                PlayStatus::Playing => continue
+            }
+
+            report.listened_secs += entry.played_secs;
+            let local = (entry.started_at as i64 + utc_offset).max(0) as u64;
+            report.by_hour[(local / 3600 % 24) as usize] += entry.played_secs;
+            // the epoch was a Thursday
+            report.by_weekday[((local / 86400 + 3) % 7) as usize] += entry.played_secs;
+
+            let tags = library.get(&entry.path)
+                .map(|track| track.tags.clone())
+                .or_else(|| metadata::cached_info(&entry.path).map(|info| info.tags))
+                .unwrap_or_default();
+            let Tags { ref artist, ref album_artist, ref album, .. } = tags;
+
+            if let Some(artist) = artist.clone() {
+                count(&mut artists, artist, entry);
+            }
+            if let (Some(album), Some(artist)) = (album, album_artist.as_ref().or(artist.as_ref())) {
+                count(&mut albums, format!("{} - {}", artist, album), entry);
+            }
+            count(&mut tracks, display_name(&entry.path), entry);
+        }
+
+        report.top_artists = top(artists);
+        report.top_albums = top(albums);
+        report.top_tracks = top(tracks);
+
+        let (mut formats, mut bitrates, mut genres) = (HashMap::new(), [0; BITRATES.len()], HashMap::new());
+        for track in library.tracks() {
+            *formats.entry(track.format().to_lowercase()).or_default() += 1;
+            if let Some(kbps) = track.bitrate() {
+                let range = BITRATES.iter().rposition(|(low, _)| kbps >= *low).unwrap_or(0);
+                bitrates[range] += 1;
+            }
+            if let Some(genre) = track.tags.genre.clone() {
+                *genres.entry(genre).or_default() += 1;
+            }
+        }
+
+        report.formats = shares(formats);
+        report.bitrates = BITRATES.iter()
+            .zip(bitrates)
+            .filter(|(_, tracks)| *tracks > 0)
+            .map(|((_, name), tracks)| Share { name: String::from(*name), tracks })
+            .collect();
+        report.genres = shares(genres);
+        report
+    }
+}
+
+#[test]
+fn test_report() {
+    use super::track::Track;
+    use crate::playback::history::PlaySource;
+
+    let mut library = Library::open(None);
+    for (path, artist, album, size) in [
+        ("/m/a/1.flac", "Fishmans", "Orange", 40_000_000),
+        ("/m/a/2.mp3", "Fishmans", "Orange", 8_000_000),
+        ("/m/b/1.mp3", "Sakanaction", "Kikuuiki", 12_000_000)
+    ] {
+        library.insert(Track {
+            path: String::from(path),
+            size,
+            duration_ms: Some(300_000),
+            tags: Tags {
+                title: Some(String::from(path)),
+                artist: Some(String::from(artist)),
+                album: Some(String::from(album)),
+                genre: Some(String::from("Rock")),
+                ..Tags::default()
+            },
+            ..Track::default()
+        });
+    }
+
+    // 1970-01-05 was a Monday
+    let monday = 4 * 86400;
+    let entry = |path: &str, started_at: u64, status| HistoryEntry {
+        path: String::from(path),
+        started_at,
+        played_secs: 100,
+        duration_secs: None,
+        status,
+        source: PlaySource::Context
+    };
+    let entries = [
+        entry("/m/a/1.flac", monday + 3600, PlayStatus::Completed),
+        entry("/m/a/2.mp3", monday + 7200, PlayStatus::Completed),
+        entry("/m/b/1.mp3", monday + 7200, PlayStatus::Completed),
+        entry("/m/b/1.mp3", 10 * 86400, PlayStatus::Skipped),
+        entry("/m/a/1.flac", 20 * 86400, PlayStatus::Playing)
+    ];
+
+    let report = Report::new(Period::All, &entries, &library, 20 * 86400, 0);
+    assert_eq!((report.plays, report.skips, report.listened_secs), (3, 1, 400));
+    assert_eq!(report.top_artists[0], Top { name: String::from("Fishmans"), plays: 2, secs: 200 });
+    assert_eq!(report.top_albums[1].name, "Sakanaction - Kikuuiki");
+    assert_eq!((report.by_hour[1], report.by_hour[2]), (100, 200));
+    assert_eq!(report.by_weekday[0], 300);
+    assert_eq!(report.formats[0], Share { name: String::from("mp3"), tracks: 2 });
+    assert_eq!(report.bitrates.iter().map(|share| share.name.as_str()).collect::<Vec<&str>>(), vec!["192-255", "320-499", "500+"]);
+
+    // a week back from day 15 only has the song skipped on day 10
+    let report = Report::new(Period::Week, &entries, &library, 15 * 86400, 8 * 3600);
+    assert_eq!((report.plays, report.skips), (0, 1));
+    assert!(report.top_tracks.is_empty());
+    assert_eq!(report.by_hour[8], 100);
+}
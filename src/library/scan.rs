@@ -0,0 +1,256 @@
+// Date: Fri Nov 17 22:15:40 2023
+// Mail: lunar_ubuntu@qq.com
+// Author: https://github.com/xiaoqixian
+
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    sync::RwLock,
+    time::UNIX_EPOCH
+};
+
+use super::{Library, AUDIO_EXTENSIONS, probe::probe, track::Track};
+use crate::playback::history::unix_now;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ScanStats {
+    pub added: usize,
+    pub updated: usize,
+    pub removed: usize,
+    pub unchanged: usize
+}
+
+#[inline]
+pub fn is_audio(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_ascii_lowercase())
+        .is_some_and(|ext| AUDIO_EXTENSIONS.contains(&ext.as_str()))
+}
+
+/// The mtime in seconds and the size of a file.
+pub fn file_stamp(path: &Path) -> Option<(u64, u64)> {
+    let meta = fs::metadata(path).ok()?;
+    let mtime = meta.modified()
+        .ok()?
+        .duration_since(UNIX_EPOCH)
+        .ok()?
+        .as_secs();
+    Some((mtime, meta.len()))
+}
+
+/// Collect the audio files under `dir`, hidden entries are
+/// skipped and symlinked directories are not followed.
+pub fn walk(dir: &Path, files: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return
+    };
+
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+
+        let path = entry.path();
+        match entry.file_type() {
+            Ok(t) if t.is_dir() => walk(&path, files),
+            Ok(_) if is_audio(&path) && path.is_file() => files.push(path),
+            _ => {}
+        }
+    }
+}
+
+/// Probe a file along with its mtime and size.
+pub fn probe_file(path: &Path) -> Option<Track> {
+    let (mtime, size) = file_stamp(path)?;
+    let mut track = probe(path)?;
+    track.mtime = mtime;
+    track.size = size;
+    Some(track)
+}
+
+impl Library {
+    /// Whether a file changed since it was indexed.
+    pub fn is_stale(&self, path: &Path) -> bool {
+        let track = match self.tracks.get(path.to_string_lossy().as_ref()) {
+            None => return true,
+            Some(track) => track
+        };
+        file_stamp(path) != Some((track.mtime, track.size))
+    }
+
+    /// Returns whether the track was indexed before, a track
+    /// indexed again keeps when it was added.
+    pub fn insert(&mut self, mut track: Track) -> bool {
+        self.generation += 1;
+        track.added = match self.tracks.get(&track.path) {
+            Some(old) => old.added,
+            None if track.added == 0 => unix_now(),
+            None => track.added
+        };
+        self.tracks.insert(track.path.clone(), track).is_some()
+    }
+
+    /// Probe an indexed file again even if it looks unchanged,
+    /// as its tags were just written. Returns false if the
+    /// file is not in the library.
+    pub fn refresh(&mut self, path: &Path) -> bool {
+        if !self.tracks.contains_key(path.to_string_lossy().as_ref()) {
+            return false;
+        }
+        match probe_file(path) {
+            Some(track) => self.insert(track),
+            None => false
+        }
+    }
+
+    /// Forget a file, or everything under a directory.
+    pub fn remove(&mut self, path: &Path) -> usize {
+        let before = self.tracks.len();
+        let dir = path.to_string_lossy().trim_end_matches('/').to_string() + "/";
+        let file = path.to_string_lossy();
+
+        self.tracks.retain(|key, _| *key != file && !key.starts_with(&dir));
+
+        let removed = before - self.tracks.len();
+        if removed > 0 {
+            self.generation += 1;
+        }
+        removed
+    }
+
+    /// Move the tracks of a file, or of everything under a
+    /// directory, to where it was moved. Their tags stay, the
+    /// number of tracks moved is returned.
+    pub fn rename(&mut self, from: &Path, to: &Path) -> usize {
+        let (from, to) = (from.to_string_lossy(), to.to_string_lossy());
+        let dir = from.trim_end_matches('/').to_string() + "/";
+
+        let keys = self.tracks
+            .keys()
+            .filter(|key| **key == from || key.starts_with(&dir))
+            .cloned()
+            .collect::<Vec<String>>();
+
+        for key in keys.iter() {
+            let mut track = self.tracks.remove(key).unwrap();
+            track.path = format!("{}{}", to, &key[from.len()..]);
+            self.tracks.insert(track.path.clone(), track);
+        }
+
+        if !keys.is_empty() {
+            self.generation += 1;
+        }
+        keys.len()
+    }
+}
+
+/// Scan the roots recursively, files that did not change
+/// are not probed again, and the tracks no longer found
+/// under any root are dropped.
+///
+/// The library is only locked to look the files up and to
+/// merge what was probed, so it stays usable while the files
+/// are probed. A file indexed by someone else meanwhile, as
+/// its tags were edited or it was just created, keeps what
+/// they indexed.
+pub fn scan(library: &RwLock<Library>, roots: &[PathBuf]) -> ScanStats {
+    let mut files = Vec::new();
+    roots.iter().for_each(|root| walk(root, &mut files));
+
+    let (known, stale) = {
+        let library = library.read().unwrap();
+        let stale = files.iter()
+            .filter(|path| library.is_stale(path))
+            .cloned()
+            .collect::<Vec<PathBuf>>();
+        (library.tracks.keys().cloned().collect::<HashSet<String>>(), stale)
+    };
+
+    let mut stats = ScanStats {
+        unchanged: files.len() - stale.len(),
+        ..Default::default()
+    };
+    let probed = stale.into_iter()
+        .map(|path| {
+            let track = probe_file(&path);
+            (path, track)
+        })
+        .collect::<Vec<_>>();
+
+    let mut found = files.iter()
+        .map(|path| path.to_string_lossy().into_owned())
+        .collect::<HashSet<String>>();
+
+    let mut library = library.write().unwrap();
+    for (path, track) in probed {
+        match track {
+            // no longer decodable, dropped with the missing ones
+            None => {
+                found.remove(path.to_string_lossy().as_ref());
+            },
+            Some(track) if library.is_stale(&path) => match library.insert(track) {
+                true => stats.updated += 1,
+                false => stats.added += 1
+            },
+            Some(_) => stats.unchanged += 1
+        }
+    }
+
+    // the tracks indexed after the lookup are not known to be gone
+    let before = library.tracks.len();
+    library.tracks.retain(|key, _| !known.contains(key) || found.contains(key));
+    stats.removed = before - library.tracks.len();
+    if stats.removed > 0 {
+        library.generation += 1;
+    }
+
+    stats
+}
+
+#[test]
+fn test_incremental_scan() {
+    use crate::playback::test_wav;
+
+    let root = std::env::temp_dir().join("tmusic_test_scan");
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(root.join("album")).unwrap();
+    test_wav(&root.join("album/01.wav"), 1.0);
+    fs::write(root.join("album/cover.jpg"), b"not audio").unwrap();
+
+    let library = RwLock::new(Library::open(None));
+    let roots = [root.clone()];
+
+    let stats = scan(&library, &roots);
+    assert_eq!((stats.added, stats.unchanged), (1, 0));
+    let track = library.read().unwrap().tracks().next().cloned().unwrap();
+    assert_eq!(track.sample_rate, Some(8000));
+    assert_eq!(track.duration_ms, Some(1000));
+
+    let stats = scan(&library, &roots);
+    assert_eq!((stats.added, stats.unchanged), (0, 1));
+
+    // a track no longer under any root is dropped
+    let extra = root.join("extra.wav");
+    test_wav(&extra, 1.0);
+    library.write().unwrap().insert(probe_file(&extra).unwrap());
+    assert_eq!(scan(&library, &[root.join("album")]).removed, 1);
+    fs::remove_file(&extra).unwrap();
+
+    let mut library = library.into_inner().unwrap();
+
+    // a moved directory takes its tracks along without probing
+    fs::rename(root.join("album"), root.join("moved")).unwrap();
+    assert_eq!(library.rename(&root.join("album"), &root.join("moved")), 1);
+    assert!(!library.is_stale(&root.join("moved/01.wav")));
+    fs::rename(root.join("moved"), root.join("album")).unwrap();
+    library.rename(&root.join("moved"), &root.join("album"));
+
+    fs::remove_file(root.join("album/01.wav")).unwrap();
+    let library = RwLock::new(library);
+    assert_eq!(scan(&library, &roots).removed, 1);
+    assert_eq!(library.read().unwrap().tracks().count(), 0);
+
+    let _ = fs::remove_dir_all(&root);
+}
@@ -0,0 +1,230 @@
+// Date: Sat Nov 25 15:47:12 2023
+// Mail: lunar_ubuntu@qq.com
+// Author: https://github.com/xiaoqixian
+
+/*
+ * Fuzzy search over the library. Every track is matched by
+ * its title, artist and album in three forms: the text itself,
+ * its full pinyin and the initials of its pinyin, so `jmnn`
+ * and `jimonannai` both find 寂寞难耐. A track is ranked by
+ * the best score of its forms.
+ *
+ * Traditional characters are simplified in both the texts
+ * and the query, so 周杰倫 and 周杰伦 match each other.
+ *
+ * The positions matched in a form are mapped back to the
+ * characters of the text they came from, for highlighting.
+ *
+ * What is typed is parsed as a query first, which may have
+ * field terms and boolean operators, see query.rs
+ */
+
+use fuzzy_matcher::{FuzzyMatcher, skim::SkimMatcherV2};
+
+use super::{Library, track::Track, stats::StatsBook};
+
+pub mod pinyin;
+pub mod query;
+
+use query::{Expr, ParseError, Subject};
+
+/// A text as it is matched, with the index in the original
+/// text of every character in it.
+#[derive(Debug, Clone, Default)]
+struct Form {
+    text: String,
+    origin: Vec<usize>
+}
+
+impl Form {
+    fn push(&mut self, s: &str, origin: usize) {
+        s.chars().for_each(|c| {
+            self.text.push(c);
+            self.origin.push(origin);
+        });
+    }
+}
+
+/// A text along with the forms it is matched in.
+#[derive(Debug, Clone)]
+pub struct Searchable {
+    pub text: String,
+    forms: [Form; 3]
+}
+
+/// Simplified and lowercased, as texts are compared.
+pub fn normalize(text: &str) -> String {
+    text.chars()
+        .map(pinyin::simplify)
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+impl Searchable {
+    pub fn new(text: String) -> Self {
+        let (mut plain, mut full, mut initials) = (Form::default(), Form::default(), Form::default());
+
+        for (i, c) in text.chars().enumerate() {
+            let c = pinyin::simplify(c);
+            let lower = c.to_lowercase().collect::<String>();
+            plain.push(&lower, i);
+
+            match pinyin::syllable(c) {
+                Some(syllable) => {
+                    full.push(syllable, i);
+                    initials.push(&syllable[..1], i);
+                },
+                None => {
+                    full.push(&lower, i);
+                    initials.push(&lower, i);
+                }
+            }
+        }
+
+        Self {
+            text,
+            forms: [plain, full, initials]
+        }
+    }
+
+    /// Whether any form contains a normalized word.
+    pub fn contains(&self, word: &str) -> bool {
+        self.forms.iter().any(|form| form.text.contains(word))
+    }
+
+    /// Whether the text contains a normalized phrase.
+    #[inline]
+    pub fn contains_plain(&self, phrase: &str) -> bool {
+        self.forms[0].text.contains(phrase)
+    }
+
+    /// The best score over the forms, with the positions of
+    /// the matched characters of the text, in order.
+    pub fn fuzzy_match(&self, matcher: &SkimMatcherV2, query: &str) -> Option<(i64, Vec<usize>)> {
+        self.forms
+            .iter()
+            .filter_map(|form| {
+                let (score, indices) = matcher.fuzzy_indices(&form.text, query)?;
+                let mut positions = indices
+                    .into_iter()
+                    .filter_map(|i| form.origin.get(i).copied())
+                    .collect::<Vec<usize>>();
+                positions.dedup();
+                Some((score, positions))
+            })
+            // the first form wins a tie, it is what was typed
+            .fold(None, |best: Option<(i64, Vec<usize>)>, (score, positions)| match best {
+                Some((best_score, _)) if best_score >= score => best,
+                _ => Some((score, positions))
+            })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hit {
+    pub path: String,
+    pub text: String,
+    pub score: i64,
+    /// indices of the matched characters of the text
+    pub positions: Vec<usize>
+}
+
+/// The text a track is searched by: title, artist, album.
+pub fn text_of(track: &Track) -> String {
+    let title = track.tags.title.clone().unwrap_or_else(|| {
+        std::path::Path::new(&track.path)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    });
+
+    [Some(title), track.tags.artist.clone(), track.tags.album.clone()]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<String>>()
+        .join("  ")
+}
+
+/// The searchable texts of the library, built again when
+/// the generation of the library moves on.
+pub struct SearchIndex {
+    entries: Vec<(Track, Searchable)>,
+    generation: u64,
+    matcher: SkimMatcherV2
+}
+
+impl SearchIndex {
+    pub fn build(library: &Library) -> Self {
+        Self {
+            entries: library.tracks()
+                .map(|track| (track.clone(), Searchable::new(text_of(track))))
+                .collect(),
+            generation: library.generation(),
+            matcher: SkimMatcherV2::default().ignore_case()
+        }
+    }
+
+    #[inline]
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// The tracks matching a query along with how well
+    /// each matched, in the order of their paths.
+    pub fn select<'a>(&'a self, expr: &Expr, stats: &StatsBook) -> Vec<(&'a Track, &'a Searchable, query::Match)> {
+        self.entries
+            .iter()
+            .filter_map(|(track, searchable)| {
+                let subject = Subject {
+                    track,
+                    stats: stats.get(&track.path),
+                    searchable
+                };
+                Some((track, searchable, expr.eval(&subject, &self.matcher)?))
+            })
+            .collect()
+    }
+
+    /// The best `limit` hits of a query, the best first.
+    /// Nothing is found by an empty query.
+    pub fn search(&self, query: &str, stats: &StatsBook, limit: usize) -> Result<Vec<Hit>, ParseError> {
+        let expr = query::parse(query)?;
+        if expr.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut hits = self.select(&expr, stats)
+            .into_iter()
+            .map(|(track, searchable, m)| Hit {
+                path: track.path.clone(),
+                text: searchable.text.clone(),
+                score: m.score,
+                positions: m.positions
+            })
+            .collect::<Vec<Hit>>();
+
+        hits.sort_by(|a, b| b.score.cmp(&a.score).then_with(|| a.text.cmp(&b.text)));
+        hits.truncate(limit);
+        Ok(hits)
+    }
+}
+
+#[test]
+fn test_pinyin_search() {
+    let matcher = SkimMatcherV2::default().ignore_case();
+    let song = Searchable::new(String::from("寂寞难耐  李宗盛"));
+    let other = Searchable::new(String::from("山丘  李宗盛"));
+
+    for query in ["jmnn", "jimonannai", "寂寞", "lzs"] {
+        assert!(song.fuzzy_match(&matcher, query).is_some(), "{}", query);
+    }
+    assert!(other.fuzzy_match(&matcher, "jmnn").is_none());
+
+    // the positions are of the characters of the text
+    let (_, positions) = song.fuzzy_match(&matcher, "jmnn").unwrap();
+    assert_eq!(positions, vec![0, 1, 2, 3]);
+
+    let traditional = Searchable::new(String::from("晴天  周杰倫"));
+    assert!(traditional.fuzzy_match(&matcher, &normalize("周杰伦")).is_some());
+    assert!(traditional.fuzzy_match(&matcher, "zjl").is_some());
+}
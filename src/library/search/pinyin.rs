@@ -0,0 +1,56 @@
+// Date: Sat Nov 25 16:21:40 2023
+// Mail: lunar_ubuntu@qq.com
+// Author: https://github.com/xiaoqixian
+
+/*
+ * The readings of CJK characters and their simplified
+ * forms, from the tables next to this file. Polyphonic
+ * characters only have their most common reading.
+ */
+
+use std::{collections::HashMap, sync::OnceLock};
+
+static PINYIN: &str = include_str!("pinyin.txt");
+static T2S: &str = include_str!("t2s.txt");
+
+fn lines(table: &'static str) -> impl Iterator<Item = &'static str> {
+    table.lines().filter(|line| !line.is_empty() && !line.starts_with('#'))
+}
+
+fn readings() -> &'static HashMap<char, &'static str> {
+    static READINGS: OnceLock<HashMap<char, &'static str>> = OnceLock::new();
+    READINGS.get_or_init(|| {
+        let mut readings = HashMap::new();
+        for line in lines(PINYIN) {
+            if let Some((syllable, chars)) = line.split_once(' ') {
+                chars.chars().for_each(|c| {
+                    readings.insert(c, syllable);
+                });
+            }
+        }
+        readings
+    })
+}
+
+fn simplified() -> &'static HashMap<char, char> {
+    static SIMPLIFIED: OnceLock<HashMap<char, char>> = OnceLock::new();
+    SIMPLIFIED.get_or_init(|| lines(T2S)
+        .filter_map(|line| {
+            let mut chars = line.chars();
+            Some((chars.next()?, chars.next()?))
+        })
+        .collect())
+}
+
+/// The pinyin of a character without its tone, like "ji".
+#[inline]
+pub fn syllable(c: char) -> Option<&'static str> {
+    readings().get(&c).copied()
+}
+
+/// The simplified form of a traditional character,
+/// any other character is kept.
+#[inline]
+pub fn simplify(c: char) -> char {
+    simplified().get(&c).copied().unwrap_or(c)
+}
@@ -0,0 +1,618 @@
+// Date: Mon Nov 27 21:12:36 2023
+// Mail: lunar_ubuntu@qq.com
+// Author: https://github.com/xiaoqixian
+
+/*
+ * The query language of the search box:
+ *
+ *   artist:fishmans year:>1995 genre:rock -live dur:<5m
+ *
+ * Terms next to each other must all match, `OR` or `|`
+ * between them lets either match and binds looser, and
+ * parentheses group. A leading `-` negates a term.
+ *
+ * A bare word is matched fuzzily against the title, artist
+ * and album, by pinyin too. A quoted phrase must appear in
+ * them as it is. Negated words are never matched fuzzily,
+ * `-live` drops the songs whose text contains "live".
+ *
+ * A field term is `field:value`. Text fields contain the
+ * value, or equal it with `field:=value`, and the value
+ * may be quoted. Number fields take `=`, `<`, `<=`, `>`,
+ * `>=` or a range `a..b` with either end left open.
+ * Durations are in seconds, or like `5m`, `4m30s`, `3:20`.
+ *
+ * The play statistics are fields too: `plays` and `skips`
+ * count, `played` and `added` are how long ago the song was
+ * last played to the end and added to the library, so songs
+ * not played in 30 days are `-played:<30d`. So is what the
+ * user keeps about a song, `rating` from 0 for unrated to 5
+ * and `loved`, which is 1 for the loved songs and 0 else.
+ *
+ * Words that look like a field term but name no field,
+ * like `re:zero`, are just words.
+ */
+
+use std::{fmt, ops::Bound};
+
+use fuzzy_matcher::skim::SkimMatcherV2;
+
+use super::{Searchable, normalize};
+use crate::{
+    library::{track::Track, stats::PlayStats},
+    playback::history::unix_now
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub message: String,
+    /// the char index in the query where it went wrong
+    pub position: usize
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (at {})", self.message, self.position + 1)
+    }
+}
+
+#[inline]
+fn error<T>(message: impl Into<String>, position: usize) -> Result<T, ParseError> {
+    Err(ParseError {
+        message: message.into(),
+        position
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Title,
+    Artist,
+    Album,
+    AlbumArtist,
+    Genre,
+    Composer,
+    Comment,
+    Path,
+    Format,
+    Year,
+    Track,
+    Disc,
+    /// seconds
+    Duration,
+    /// kbps
+    Bitrate,
+    /// Hz
+    SampleRate,
+    /// stars, 0 if unrated
+    Rating,
+    /// 1 if loved, 0 else
+    Loved,
+    Plays,
+    Skips,
+    /// seconds since it was last played
+    Played,
+    /// seconds since it was added
+    Added
+}
+
+impl Field {
+    pub fn by_name(name: &str) -> Option<Self> {
+        Some(match name.to_lowercase().as_str() {
+            "title" => Self::Title,
+            "artist" => Self::Artist,
+            "album" => Self::Album,
+            "albumartist" | "album-artist" | "aa" => Self::AlbumArtist,
+            "genre" => Self::Genre,
+            "composer" => Self::Composer,
+            "comment" => Self::Comment,
+            "path" => Self::Path,
+            "format" | "codec" => Self::Format,
+            "year" => Self::Year,
+            "track" => Self::Track,
+            "disc" => Self::Disc,
+            "dur" | "duration" => Self::Duration,
+            "bitrate" => Self::Bitrate,
+            "rate" => Self::SampleRate,
+            "rating" | "stars" => Self::Rating,
+            "loved" | "fav" => Self::Loved,
+            "plays" => Self::Plays,
+            "skips" => Self::Skips,
+            "played" => Self::Played,
+            "added" => Self::Added,
+            _ => return None
+        })
+    }
+
+    #[inline]
+    pub fn is_number(&self) -> bool {
+        !matches!(self,
+            Self::Title | Self::Artist | Self::Album | Self::AlbumArtist | Self::Genre
+            | Self::Composer | Self::Comment | Self::Path | Self::Format
+        )
+    }
+
+    /// Whether the value is an age, given as a duration.
+    #[inline]
+    fn is_duration(&self) -> bool {
+        matches!(self, Self::Duration | Self::Played | Self::Added)
+    }
+
+    pub fn text(&self, track: &Track) -> Option<String> {
+        match self {
+            Self::Title => track.tags.title.clone(),
+            Self::Artist => track.tags.artist.clone(),
+            Self::Album => track.tags.album.clone(),
+            Self::AlbumArtist => track.album_artist().map(String::from),
+            Self::Genre => track.tags.genre.clone(),
+            Self::Composer => track.tags.composer.clone(),
+            Self::Comment => track.tags.comment.clone(),
+            Self::Path => Some(track.path.clone()),
+            Self::Format => Some(track.format()),
+            _ => None
+        }
+    }
+
+    pub fn number(&self, track: &Track, stats: Option<&PlayStats>) -> Option<f64> {
+        let age = |time: u64| unix_now().saturating_sub(time) as f64;
+
+        match self {
+            Self::Year => track.tags.year.map(f64::from),
+            Self::Track => track.tags.track.map(f64::from),
+            Self::Disc => track.tags.disc.map(f64::from),
+            Self::Duration => track.duration_ms.map(|ms| ms as f64 / 1000.0),
+            Self::Bitrate => track.bitrate().map(|kbps| kbps as f64),
+            Self::SampleRate => track.sample_rate.map(f64::from),
+            Self::Rating => Some(stats.map_or(0.0, |stats| stats.rating as f64)),
+            Self::Loved => Some(stats.map_or(0.0, |stats| stats.loved as u8 as f64)),
+            Self::Plays => Some(stats.map_or(0.0, |stats| stats.plays as f64)),
+            Self::Skips => Some(stats.map_or(0.0, |stats| stats.skips as f64)),
+            Self::Played => stats.and_then(|stats| stats.last_played).map(age),
+            Self::Added => Some(age(track.added())),
+            _ => None
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    /// normalized, see super::normalize
+    Contains(String),
+    Equals(String),
+    Within(Bound<f64>, Bound<f64>)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    And(Vec<Expr>),
+    Or(Vec<Expr>),
+    Not(Box<Expr>),
+    /// a bare word, normalized
+    Text(String),
+    /// a quoted phrase, normalized
+    Phrase(String),
+    Field(Field, Condition)
+}
+
+/// A song as it is matched by a query.
+pub struct Subject<'a> {
+    pub track: &'a Track,
+    pub stats: Option<&'a PlayStats>,
+    pub searchable: &'a Searchable
+}
+
+/// How well a song matched, the positions are the chars
+/// of the searched text matched by the words.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Match {
+    pub score: i64,
+    pub positions: Vec<usize>
+}
+
+impl Match {
+    fn merge(mut self, other: Match) -> Match {
+        self.score += other.score;
+        self.positions.extend(other.positions);
+        self
+    }
+}
+
+impl Expr {
+    /// Whether the query has nothing in it.
+    pub fn is_empty(&self) -> bool {
+        matches!(self, Expr::And(exprs) | Expr::Or(exprs) if exprs.is_empty())
+    }
+
+    pub fn eval(&self, subject: &Subject, matcher: &SkimMatcherV2) -> Option<Match> {
+        let mut m = self.eval_in(subject, matcher, false)?;
+        m.positions.sort_unstable();
+        m.positions.dedup();
+        Some(m)
+    }
+
+    /// `strict` is set under a negation, where words
+    /// are matched as they are.
+    fn eval_in(&self, subject: &Subject, matcher: &SkimMatcherV2, strict: bool) -> Option<Match> {
+        let searchable = subject.searchable;
+
+        match self {
+            Expr::And(exprs) => exprs
+                .iter()
+                .try_fold(Match::default(), |m, expr| {
+                    Some(m.merge(expr.eval_in(subject, matcher, strict)?))
+                }),
+            Expr::Or(exprs) => exprs
+                .iter()
+                .filter_map(|expr| expr.eval_in(subject, matcher, strict))
+                .max_by_key(|m| m.score),
+            Expr::Not(expr) => match expr.eval_in(subject, matcher, true) {
+                Some(_) => None,
+                None => Some(Match::default())
+            },
+            Expr::Text(word) if strict => searchable.contains(word).then(Match::default),
+            Expr::Text(word) => searchable
+                .fuzzy_match(matcher, word)
+                .map(|(score, positions)| Match { score, positions }),
+            Expr::Phrase(phrase) => searchable.contains_plain(phrase).then(Match::default),
+            Expr::Field(field, condition) => Self::eval_field(*field, condition, subject).then(Match::default)
+        }
+    }
+
+    fn eval_field(field: Field, condition: &Condition, subject: &Subject) -> bool {
+        let track = subject.track;
+
+        match condition {
+            Condition::Contains(value) => field.text(track)
+                .is_some_and(|text| normalize(&text).contains(value.as_str())),
+            Condition::Equals(value) => field.text(track)
+                .is_some_and(|text| normalize(&text) == *value),
+            Condition::Within(low, high) => field.number(track, subject.stats)
+                .is_some_and(|n| {
+                    let above = match low {
+                        Bound::Included(low) => n >= *low,
+                        Bound::Excluded(low) => n > *low,
+                        Bound::Unbounded => true
+                    };
+                    let below = match high {
+                        Bound::Included(high) => n <= *high,
+                        Bound::Excluded(high) => n < *high,
+                        Bound::Unbounded => true
+                    };
+                    above && below
+                })
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    Or,
+    Not,
+    Word(String),
+    Phrase(String),
+    /// a field, its value, and where the value starts
+    Field(Field, String, usize)
+}
+
+/// Read a quoted string starting at the quote at `start`,
+/// returns it and the index after the closing quote.
+fn quoted(chars: &[char], start: usize) -> Result<(String, usize), ParseError> {
+    match chars[start + 1..].iter().position(|c| *c == '"') {
+        None => error("unclosed quote", start),
+        Some(len) => Ok((chars[start + 1..start + 1 + len].iter().collect(), start + len + 2))
+    }
+}
+
+fn lex(query: &str) -> Result<Vec<(usize, Token)>, ParseError> {
+    let chars = query.chars().collect::<Vec<char>>();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let start = i;
+        let token = match chars[i] {
+            c if c.is_whitespace() => {
+                i += 1;
+                continue;
+            },
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '|' => Token::Or,
+            '-' if chars.get(i + 1).is_some_and(|c| !c.is_whitespace()) => Token::Not,
+            '"' => {
+                let (phrase, end) = quoted(&chars, i)?;
+                tokens.push((start, Token::Phrase(phrase)));
+                i = end;
+                continue;
+            },
+            _ => {
+                while i < chars.len() && !chars[i].is_whitespace() && !"()\"".contains(chars[i]) {
+                    i += 1;
+                }
+                let word = chars[start..i].iter().collect::<String>();
+
+                let field = word.split_once(':')
+                    .and_then(|(name, value)| Some((Field::by_name(name)?, value)));
+
+                let token = match field {
+                    None if word == "OR" => Token::Or,
+                    None => Token::Word(word),
+                    Some((field, value)) => {
+                        let mut value = String::from(value);
+                        let mut value_start = i - value.chars().count();
+
+                        // `field:"a phrase"` or `field:="a phrase"`
+                        if (value.is_empty() || value == "=") && chars.get(i) == Some(&'"') {
+                            let (phrase, end) = quoted(&chars, i)?;
+                            value_start = i;
+                            value.push_str(&phrase);
+                            i = end;
+                        }
+                        Token::Field(field, value, value_start)
+                    }
+                };
+                tokens.push((start, token));
+                continue;
+            }
+        };
+
+        tokens.push((start, token));
+        i += 1;
+    }
+
+    Ok(tokens)
+}
+
+/// A number, or a duration if the field is one.
+fn number(field: Field, text: &str, position: usize) -> Result<f64, ParseError> {
+    let parsed = match field.is_duration() {
+        true => duration(text),
+        false => text.parse::<f64>().ok()
+    };
+
+    match parsed {
+        Some(n) => Ok(n),
+        None if field.is_duration() => error(format!("not a duration: {}", text), position),
+        None => error(format!("not a number: {}", text), position)
+    }
+}
+
+/// Seconds of `90`, `5m`, `4m30s`, `1h2m`, `30d`, `2w` or `3:20`.
+fn duration(text: &str) -> Option<f64> {
+    if let Some((minutes, seconds)) = text.split_once(':') {
+        return Some(minutes.parse::<f64>().ok()? * 60.0 + seconds.parse::<f64>().ok()?);
+    }
+    if let Ok(seconds) = text.parse::<f64>() {
+        return Some(seconds);
+    }
+
+    let mut total = 0.0;
+    let mut digits = String::new();
+    for c in text.chars() {
+        match c {
+            '0'..='9' | '.' => digits.push(c),
+            'w' | 'd' | 'h' | 'm' | 's' if !digits.is_empty() => {
+                let unit = match c {
+                    'w' => 7.0 * 86400.0,
+                    'd' => 86400.0,
+                    'h' => 3600.0,
+                    'm' => 60.0,
+                    _ => 1.0
+                };
+                total += digits.parse::<f64>().ok()? * unit;
+                digits.clear();
+            },
+            _ => return None
+        }
+    }
+    digits.is_empty().then_some(total)
+}
+
+fn condition(field: Field, value: &str, position: usize) -> Result<Condition, ParseError> {
+    if value.is_empty() {
+        return error("missing value", position);
+    }
+
+    if !field.is_number() {
+        return Ok(match value.strip_prefix('=') {
+            Some(value) => Condition::Equals(normalize(value)),
+            None => Condition::Contains(normalize(value))
+        });
+    }
+
+    let (op, rest) = [">=", "<=", ">", "<", "="]
+        .into_iter()
+        .find_map(|op| value.strip_prefix(op).map(|rest| (op, rest)))
+        .unwrap_or(("", value));
+    let at = position + op.len();
+
+    Ok(match op {
+        ">=" => Condition::Within(Bound::Included(number(field, rest, at)?), Bound::Unbounded),
+        ">" => Condition::Within(Bound::Excluded(number(field, rest, at)?), Bound::Unbounded),
+        "<=" => Condition::Within(Bound::Unbounded, Bound::Included(number(field, rest, at)?)),
+        "<" => Condition::Within(Bound::Unbounded, Bound::Excluded(number(field, rest, at)?)),
+        _ => match rest.split_once("..") {
+            Some((low, high)) => {
+                let bound = |text: &str, at: usize| match text {
+                    "" => Ok(Bound::Unbounded),
+                    text => Ok(Bound::Included(number(field, text, at)?))
+                };
+                let (low, high) = (bound(low, at)?, bound(high, at + low.chars().count() + 2)?);
+                if low == Bound::Unbounded && high == Bound::Unbounded {
+                    return error("empty range", at);
+                }
+                Condition::Within(low, high)
+            },
+            None => {
+                let n = number(field, rest, at)?;
+                Condition::Within(Bound::Included(n), Bound::Included(n))
+            }
+        }
+    })
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    next: usize,
+    /// where the query ends, for errors at the end
+    end: usize
+}
+
+impl Parser {
+    #[inline]
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next).map(|(_, token)| token)
+    }
+
+    #[inline]
+    fn position(&self) -> usize {
+        self.tokens.get(self.next).map_or(self.end, |(position, _)| *position)
+    }
+
+    fn or(&mut self) -> Result<Expr, ParseError> {
+        let mut exprs = vec![self.and()?];
+
+        while let Some(Token::Or) = self.peek() {
+            self.next += 1;
+            exprs.push(self.and()?);
+        }
+
+        Ok(match exprs.len() {
+            1 => exprs.pop().unwrap(),
+            _ => Expr::Or(exprs)
+        })
+    }
+
+    fn and(&mut self) -> Result<Expr, ParseError> {
+        let mut exprs = Vec::new();
+
+        while !matches!(self.peek(), None | Some(Token::Or) | Some(Token::Close)) {
+            exprs.push(self.unary()?);
+        }
+
+        Ok(match exprs.len() {
+            0 => return error("expected a term", self.position()),
+            1 => exprs.pop().unwrap(),
+            _ => Expr::And(exprs)
+        })
+    }
+
+    fn unary(&mut self) -> Result<Expr, ParseError> {
+        let (position, token) = match self.tokens.get(self.next) {
+            None => return error("expected a term", self.end),
+            Some((position, token)) => (*position, token.clone())
+        };
+        self.next += 1;
+
+        match token {
+            Token::Not => match self.peek() {
+                None | Some(Token::Or) | Some(Token::Close) => error("nothing to negate", position),
+                _ => Ok(Expr::Not(Box::new(self.unary()?)))
+            },
+            Token::Open => {
+                let expr = self.or()?;
+                match self.peek() {
+                    Some(Token::Close) => {
+                        self.next += 1;
+                        Ok(expr)
+                    },
+                    _ => error("unclosed parenthesis", position)
+                }
+            },
+            Token::Close => error("unexpected )", position),
+            Token::Or => error("expected a term before OR", position),
+            Token::Word(word) => Ok(Expr::Text(normalize(&word))),
+            Token::Phrase(phrase) => Ok(Expr::Phrase(normalize(&phrase))),
+            Token::Field(field, value, value_start) => Ok(Expr::Field(field, condition(field, &value, value_start)?))
+        }
+    }
+}
+
+/// Parse a query, an empty query gives an empty `And`,
+/// which matches every song.
+pub fn parse(query: &str) -> Result<Expr, ParseError> {
+    let tokens = lex(query)?;
+    if tokens.is_empty() {
+        return Ok(Expr::And(Vec::new()));
+    }
+
+    let mut parser = Parser {
+        tokens,
+        next: 0,
+        end: query.chars().count()
+    };
+    let expr = parser.or()?;
+
+    match parser.peek() {
+        None => Ok(expr),
+        Some(_) => error("unexpected )", parser.position())
+    }
+}
+
+#[test]
+fn test_parse_query() {
+    use crate::library::track::Tags;
+
+    let expr = parse("artist:fishmans year:>1995 genre:rock -live dur:<5m").unwrap();
+    assert_eq!(expr, Expr::And(vec![
+        Expr::Field(Field::Artist, Condition::Contains(String::from("fishmans"))),
+        Expr::Field(Field::Year, Condition::Within(Bound::Excluded(1995.0), Bound::Unbounded)),
+        Expr::Field(Field::Genre, Condition::Contains(String::from("rock"))),
+        Expr::Not(Box::new(Expr::Text(String::from("live")))),
+        Expr::Field(Field::Duration, Condition::Within(Bound::Unbounded, Bound::Excluded(300.0)))
+    ]));
+
+    assert_eq!(parse("year:1990..1999 | \"night cruising\"").unwrap(), Expr::Or(vec![
+        Expr::Field(Field::Year, Condition::Within(Bound::Included(1990.0), Bound::Included(1999.0))),
+        Expr::Phrase(String::from("night cruising"))
+    ]));
+    assert_eq!(parse("re:zero").unwrap(), Expr::Text(String::from("re:zero")));
+    assert_eq!(duration("4m30s"), Some(270.0));
+    assert_eq!(duration("3:20"), Some(200.0));
+
+    assert_eq!(parse("(a b").unwrap_err().position, 0);
+    assert_eq!(parse("a \"b").unwrap_err().position, 2);
+    assert_eq!(parse("year:>abc").unwrap_err().position, 6);
+    assert!(parse("a OR").is_err());
+    assert!(parse("a)").is_err());
+
+    let track = Track {
+        path: String::from("/music/fishmans/long season.flac"),
+        tags: Tags {
+            title: Some(String::from("Long Season")),
+            artist: Some(String::from("Fishmans")),
+            year: Some(1996),
+            genre: Some(String::from("Rock")),
+            ..Tags::default()
+        },
+        duration_ms: Some(35 * 60 * 1000),
+        added: unix_now() - 86400,
+        ..Track::default()
+    };
+    let searchable = Searchable::new(String::from("Long Season  Fishmans"));
+    let stats = PlayStats {
+        rating: 4,
+        loved: true,
+        plays: 3,
+        skips: 0,
+        last_played: Some(unix_now() - 40 * 86400)
+    };
+    let subject = Subject {
+        track: &track,
+        stats: Some(&stats),
+        searchable: &searchable
+    };
+    let matcher = SkimMatcherV2::default().ignore_case();
+    let eval = |query: &str| parse(query).unwrap().eval(&subject, &matcher).is_some();
+
+    assert!(eval("artist:fishmans year:>1995 genre:rock -live"));
+    assert!(!eval("artist:fishmans dur:<5m"));
+    assert!(eval("(year:<1990 OR dur:>30m) season"));
+    assert!(eval("artist:=FISHMANS -\"short season\""));
+    assert!(!eval("-season"));
+    assert!(eval("plays:>=3 -played:<30d added:<1w"));
+    assert!(eval("rating:>=4 loved:1"));
+    assert!(!eval("rating:5"));
+}
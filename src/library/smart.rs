@@ -0,0 +1,287 @@
+// Date: Wed Nov 29 19:52:04 2023
+// Mail: lunar_ubuntu@qq.com
+// Author: https://github.com/xiaoqixian
+
+/*
+ * Smart playlists are saved queries, evaluated against the
+ * library and the play statistics whenever they are shown,
+ * so they follow both without being saved again. They are
+ * kept in $XDG_DATA_HOME/tmusic/smart_playlists.json
+ *
+ * Written out, a smart playlist is its query followed by
+ * its options, which may go anywhere among the terms:
+ *
+ *   rating:>=4 -played:<30d sort:-plays limit:50
+ *
+ * `sort:<field>` orders the songs by a field of the query
+ * language, descending with a `-` before the field, and
+ * `limit:<n>` keeps the first n. Songs without the field
+ * go last either way. Without a sort, the songs are in the
+ * order of their paths.
+ */
+
+use std::{
+    cmp::Ordering,
+    fs,
+    path::PathBuf
+};
+
+use serde::{Serialize, Deserialize};
+
+use super::{
+    search::{
+        SearchIndex,
+        normalize,
+        query::{self, Field, ParseError}
+    },
+    stats::StatsBook,
+    track::Track
+};
+use crate::{playback::PlayerError, xdg};
+
+pub const SMART_FILE: &str = "smart_playlists.json";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SmartPlaylist {
+    pub name: String,
+    pub query: String,
+    /// a field name, with a `-` before it for descending
+    #[serde(default)]
+    pub sort: Option<String>,
+    #[serde(default)]
+    pub limit: Option<usize>
+}
+
+#[inline]
+fn error<T>(message: impl Into<String>, position: usize) -> Result<T, ParseError> {
+    Err(ParseError {
+        message: message.into(),
+        position
+    })
+}
+
+/// The field and whether it is descending.
+fn sort_key(sort: &str) -> Option<(Field, bool)> {
+    match sort.strip_prefix('-') {
+        Some(name) => Some((Field::by_name(name)?, true)),
+        None => Some((Field::by_name(sort)?, false))
+    }
+}
+
+/// None goes after anything.
+fn compare<T: PartialOrd>(a: Option<T>, b: Option<T>, descending: bool) -> Ordering {
+    match (a, b) {
+        (None, None) => Ordering::Equal,
+        (None, Some(_)) => Ordering::Greater,
+        (Some(_), None) => Ordering::Less,
+        (Some(a), Some(b)) => {
+            let ordering = a.partial_cmp(&b).unwrap_or(Ordering::Equal);
+            match descending {
+                true => ordering.reverse(),
+                false => ordering
+            }
+        }
+    }
+}
+
+impl SmartPlaylist {
+    /// Parse a smart playlist written out, the positions of
+    /// errors are of `text`.
+    pub fn parse(name: &str, text: &str) -> Result<Self, ParseError> {
+        let mut chars = text.chars().collect::<Vec<char>>();
+        let (mut sort, mut limit) = (None, None);
+
+        // options are whole words outside of quotes, they are
+        // blanked out so the positions in the query stay
+        let mut quoted = false;
+        let mut i = 0;
+        while i < chars.len() {
+            if chars[i] == '"' {
+                quoted = !quoted;
+            }
+            if quoted || chars[i].is_whitespace() || (i > 0 && !chars[i - 1].is_whitespace()) {
+                i += 1;
+                continue;
+            }
+
+            let end = chars[i..]
+                .iter()
+                .position(|c| c.is_whitespace())
+                .map_or(chars.len(), |len| i + len);
+            let word = chars[i..end].iter().collect::<String>();
+
+            if let Some(value) = word.strip_prefix("sort:") {
+                if sort_key(value).is_none() {
+                    return error(format!("cannot sort by: {}", value), i + 5);
+                }
+                sort = Some(String::from(value));
+            } else if let Some(value) = word.strip_prefix("limit:") {
+                limit = match value.parse::<usize>() {
+                    Ok(n) if n > 0 => Some(n),
+                    _ => return error(format!("not a limit: {}", value), i + 6)
+                };
+            } else {
+                i = end;
+                continue;
+            }
+
+            chars[i..end].iter_mut().for_each(|c| *c = ' ');
+            i = end;
+        }
+
+        let query = chars.into_iter().collect::<String>();
+        query::parse(&query)?;
+
+        Ok(Self {
+            name: String::from(name.trim()),
+            query: String::from(query.trim()),
+            sort,
+            limit
+        })
+    }
+
+    /// The playlist written out, as it is parsed.
+    pub fn text(&self) -> String {
+        let options = [
+            self.sort.as_ref().map(|sort| format!("sort:{}", sort)),
+            self.limit.map(|limit| format!("limit:{}", limit))
+        ];
+
+        std::iter::once(self.query.clone())
+            .chain(options.into_iter().flatten())
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+
+    /// The paths of the songs in the playlist now.
+    pub fn evaluate(&self, index: &SearchIndex, stats: &StatsBook) -> Result<Vec<String>, ParseError> {
+        let expr = query::parse(&self.query)?;
+        let mut tracks = index.select(&expr, stats)
+            .into_iter()
+            .map(|(track, _, _)| track)
+            .collect::<Vec<_>>();
+
+        if let Some((field, descending)) = self.sort.as_deref().and_then(sort_key) {
+            // only one half of a key is set, as the field is
+            // either a number or a text
+            let key = |track: &Track| match field.is_number() {
+                true => (field.number(track, stats.get(&track.path)), None),
+                false => (None, field.text(track).map(|text| normalize(&text)))
+            };
+
+            let mut keyed = tracks
+                .into_iter()
+                .map(|track| (key(track), track))
+                .collect::<Vec<_>>();
+            keyed.sort_by(|(a, _), (b, _)| compare(a.0, b.0, descending)
+                .then_with(|| compare(a.1.as_ref(), b.1.as_ref(), descending)));
+            tracks = keyed.into_iter().map(|(_, track)| track).collect();
+        }
+
+        Ok(tracks
+            .into_iter()
+            .take(self.limit.unwrap_or(usize::MAX))
+            .map(|track| track.path.clone())
+            .collect())
+    }
+}
+
+/// The smart playlists are read from the disk every time,
+/// like the named playlists in playback/playlist/store.rs
+#[derive(Debug, Clone)]
+pub struct SmartStore {
+    file: Option<PathBuf>
+}
+
+impl SmartStore {
+    pub fn open() -> Self {
+        Self {
+            file: xdg::data_dir().map(|dir| dir.join(SMART_FILE))
+        }
+    }
+
+    /// All smart playlists, sorted by name.
+    pub fn list(&self) -> Vec<SmartPlaylist> {
+        let mut playlists = self.file
+            .as_ref()
+            .and_then(|file| fs::read_to_string(file).ok())
+            .and_then(|content| serde_json::from_str::<Vec<SmartPlaylist>>(&content).ok())
+            .unwrap_or_default();
+
+        playlists.sort_by(|a, b| a.name.cmp(&b.name));
+        playlists
+    }
+
+    fn save(&self, playlists: &[SmartPlaylist]) -> Result<(), PlayerError> {
+        let file = match self.file {
+            None => return Err(PlayerError::IOError(
+                std::io::Error::new(std::io::ErrorKind::NotFound, "no data directory")
+            )),
+            Some(ref file) => file
+        };
+
+        let content = serde_json::to_string_pretty(playlists)
+            .map_err(|e| PlayerError::IOError(std::io::Error::other(e)))?;
+        let tmp = file.with_extension("json.tmp");
+        fs::write(&tmp, content)
+            .and_then(|_| fs::rename(&tmp, file))
+            .map_err(PlayerError::IOError)
+    }
+
+    /// Save a smart playlist in place of the one named `old`,
+    /// or as a new one if `old` is None.
+    pub fn put(&self, old: Option<&str>, playlist: SmartPlaylist) -> Result<(), PlayerError> {
+        let mut playlists = self.list();
+        playlists.retain(|p| Some(p.name.as_str()) != old);
+
+        if playlist.name.is_empty() || playlists.iter().any(|p| p.name == playlist.name) {
+            return Err(PlayerError::InvalidPlaylistName(playlist.name));
+        }
+
+        playlists.push(playlist);
+        self.save(&playlists)
+    }
+
+    pub fn delete(&self, name: &str) -> Result<(), PlayerError> {
+        let mut playlists = self.list();
+        playlists.retain(|p| p.name != name);
+        self.save(&playlists)
+    }
+}
+
+#[test]
+fn test_smart_playlist() {
+    use super::{Library, track::Tags};
+
+    let playlist = SmartPlaylist::parse("最近", "sort:-year genre:rock \"sort:x\" limit:2").unwrap();
+    assert_eq!(playlist.query, "genre:rock \"sort:x\"");
+    assert_eq!((playlist.sort.as_deref(), playlist.limit), (Some("-year"), Some(2)));
+    assert_eq!(SmartPlaylist::parse("最近", &playlist.text()).unwrap(), playlist);
+
+    assert_eq!(SmartPlaylist::parse("x", "a sort:nothing").unwrap_err().position, 7);
+    assert_eq!(SmartPlaylist::parse("x", "limit:0").unwrap_err().position, 6);
+    assert_eq!(SmartPlaylist::parse("x", "limit:5 (a").unwrap_err().position, 8);
+
+    let mut library = Library::open(None);
+    for (path, year) in [("/a", Some(1996)), ("/b", None), ("/c", Some(2001)), ("/d", Some(1990))] {
+        library.insert(Track {
+            path: String::from(path),
+            tags: Tags {
+                year,
+                genre: Some(String::from("Rock")),
+                ..Tags::default()
+            },
+            ..Track::default()
+        });
+    }
+
+    let index = SearchIndex::build(&library);
+    let stats = StatsBook::default();
+    assert_eq!(playlist.evaluate(&index, &stats).unwrap().len(), 0);
+
+    let playlist = SmartPlaylist::parse("x", "genre:rock sort:-year limit:3").unwrap();
+    assert_eq!(playlist.evaluate(&index, &stats).unwrap(), vec!["/c", "/a", "/d"]);
+    let playlist = SmartPlaylist::parse("x", "sort:year").unwrap();
+    assert_eq!(playlist.evaluate(&index, &stats).unwrap(), vec!["/d", "/a", "/c", "/b"]);
+}
@@ -0,0 +1,250 @@
+// Date: Tue Nov 28 20:36:15 2023
+// Mail: lunar_ubuntu@qq.com
+// Author: https://github.com/xiaoqixian
+
+/*
+ * What the user keeps about each song: its rating, whether
+ * it is loved, and how often it was played to the end or
+ * skipped. It is kept in $XDG_DATA_HOME/tmusic/user_data.json
+ *
+ * The counts follow the notices of the songs finished, a
+ * song that came to its end in go_next is played, one left
+ * before is skipped. The first time the store is opened,
+ * the history so far is counted into it.
+ */
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    io,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+    thread::JoinHandle
+};
+
+use kanal::Receiver;
+
+use serde::{Serialize, Deserialize};
+
+use crate::{
+    xdg,
+    playback::{
+        Notice,
+        history::{HistoryEntry, PlayStatus}
+    }
+};
+
+pub const USER_DATA_FILE: &str = "user_data.json";
+
+/// The highest rating, 0 is unrated.
+pub const MAX_RATING: u8 = 5;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlayStats {
+    /// 1 to MAX_RATING stars, 0 if unrated
+    pub rating: u8,
+    pub loved: bool,
+    /// times played to the end
+    pub plays: u32,
+    pub skips: u32,
+    /// when it was last played to the end, in unix seconds
+    pub last_played: Option<u64>
+}
+
+/// A song in the export, its path along with its data.
+#[derive(Debug, Clone, Serialize)]
+struct Exported<'a> {
+    path: &'a str,
+    #[serde(flatten)]
+    stats: &'a PlayStats
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct StatsBook {
+    stats: HashMap<String, PlayStats>,
+    file: Option<PathBuf>,
+    /// bumped on every change, like Library::generation
+    generation: u64
+}
+
+impl StatsBook {
+    pub fn from_history(entries: &[HistoryEntry]) -> Self {
+        let mut book = Self::default();
+        entries.iter().for_each(|entry| book.record(entry));
+        book
+    }
+
+    /// Open the store at `file`, the history is counted
+    /// into it if it does not exist yet.
+    ///
+    /// A file that does not parse is moved aside, to
+    /// user_data.json.broken, before anything is saved over
+    /// it. A file that cannot be read or moved is never saved
+    /// over, the book is then kept in memory only.
+    pub fn open(file: Option<PathBuf>, history: &[HistoryEntry]) -> Self {
+        let Some(file) = file else {
+            return Self::from_history(history);
+        };
+
+        let stats = match fs::read_to_string(&file) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(_) => return Self::from_history(history),
+            Ok(content) => match serde_json::from_str(&content) {
+                Ok(stats) => Some(stats),
+                Err(_) if fs::rename(&file, file.with_extension("json.broken")).is_ok() => None,
+                Err(_) => return Self::from_history(history)
+            }
+        };
+
+        let mut book = match stats {
+            None => Self::from_history(history),
+            Some(stats) => Self {
+                stats,
+                ..Self::default()
+            }
+        };
+        book.file = Some(file);
+        book
+    }
+
+    /// Open the store in the data directory.
+    #[inline]
+    pub fn open_default(history: &[HistoryEntry]) -> Self {
+        Self::open(xdg::data_dir().map(|dir| dir.join(USER_DATA_FILE)), history)
+    }
+
+    /// Written like Library::save, the songs without
+    /// anything to keep are left out.
+    pub fn save(&self) -> io::Result<()> {
+        let file = match self.file {
+            None => return Ok(()),
+            Some(ref file) => file
+        };
+
+        let stats = self.stats
+            .iter()
+            .filter(|(_, stats)| **stats != PlayStats::default())
+            .collect::<BTreeMap<&String, &PlayStats>>();
+        let content = serde_json::to_string(&stats).map_err(io::Error::other)?;
+
+        let tmp = file.with_extension("json.tmp");
+        fs::write(&tmp, content)?;
+        fs::rename(&tmp, file)
+    }
+
+    pub fn record(&mut self, entry: &HistoryEntry) {
+        let stats = self.stats.entry(entry.path.clone()).or_default();
+
+        match entry.status {
+            PlayStatus::Completed => {
+                stats.plays += 1;
+                stats.last_played = stats.last_played.max(Some(entry.started_at));
+            },
+            PlayStatus::Skipped => stats.skips += 1,
+            PlayStatus::Playing => return
+        }
+        self.generation += 1;
+    }
+
+    /// Rate a song, 0 takes the rating away.
+    pub fn set_rating(&mut self, path: &str, rating: u8) {
+        self.stats.entry(String::from(path)).or_default().rating = rating.min(MAX_RATING);
+        self.generation += 1;
+    }
+
+    /// Returns whether the song is loved now.
+    pub fn toggle_loved(&mut self, path: &str) -> bool {
+        let stats = self.stats.entry(String::from(path)).or_default();
+        stats.loved = !stats.loved;
+        self.generation += 1;
+        stats.loved
+    }
+
+    #[inline]
+    pub fn get(&self, path: &str) -> Option<&PlayStats> {
+        self.stats.get(path)
+    }
+
+    #[inline]
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Everything kept, as a JSON array of the songs
+    /// sorted by their paths.
+    pub fn export(&self) -> String {
+        let mut exported = self.stats
+            .iter()
+            .filter(|(_, stats)| **stats != PlayStats::default())
+            .map(|(path, stats)| Exported { path, stats })
+            .collect::<Vec<Exported>>();
+        exported.sort_by(|a, b| a.path.cmp(b.path));
+
+        serde_json::to_string_pretty(&exported).unwrap_or_default()
+    }
+}
+
+/// Count the songs finished from now on, until the
+/// player lets its subscribers go.
+pub fn spawn(book: Arc<RwLock<StatsBook>>, notices: Receiver<Notice>) -> JoinHandle<()> {
+    std::thread::spawn(move || {
+        while let Ok(notice) = notices.recv() {
+            if let Notice::Played(entry) = notice {
+                let mut book = book.write().unwrap();
+                book.record(&entry);
+                let _ = book.save();
+            }
+        }
+    })
+}
+
+#[test]
+fn test_user_data() {
+    use crate::playback::history::PlaySource;
+
+    let file = std::env::temp_dir().join(format!("tmusic_user_data_{}.json", std::process::id()));
+    let _ = fs::remove_file(&file);
+
+    let entry = |path: &str, status| HistoryEntry {
+        path: String::from(path),
+        started_at: 100,
+        played_secs: 60,
+        duration_secs: None,
+        status,
+        source: PlaySource::Context
+    };
+    let history = [
+        entry("/a", PlayStatus::Completed),
+        entry("/a", PlayStatus::Skipped),
+        entry("/b", PlayStatus::Playing)
+    ];
+
+    let mut book = StatsBook::open(Some(file.clone()), &history);
+    assert_eq!(book.get("/a").map(|stats| (stats.plays, stats.skips)), Some((1, 1)));
+    book.set_rating("/b", 9);
+    assert!(book.toggle_loved("/c"));
+    assert!(!book.toggle_loved("/c"));
+    book.save().unwrap();
+
+    // the history is only counted the first time
+    let book = StatsBook::open(Some(file.clone()), &history);
+    assert_eq!(book.get("/a").unwrap().plays, 1);
+    assert_eq!(book.get("/b").unwrap().rating, MAX_RATING);
+    assert!(book.get("/c").is_none());
+
+    let exported = serde_json::from_str::<serde_json::Value>(&book.export()).unwrap();
+    assert_eq!(exported[0]["path"], "/a");
+    assert_eq!(exported[1]["rating"], 5);
+
+    // a broken file is kept aside, not saved over
+    fs::write(&file, "{\"/a\": ").unwrap();
+    let book = StatsBook::open(Some(file.clone()), &history);
+    assert_eq!(book.get("/a").unwrap().plays, 1);
+    book.save().unwrap();
+    let broken = file.with_extension("json.broken");
+    assert_eq!(fs::read_to_string(&broken).unwrap(), "{\"/a\": ");
+
+    let _ = fs::remove_file(&file);
+    let _ = fs::remove_file(&broken);
+}
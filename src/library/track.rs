@@ -0,0 +1,62 @@
+// Date: Fri Nov 17 20:26:31 2023
+// Mail: lunar_ubuntu@qq.com
+// Author: https://github.com/xiaoqixian
+
+use serde::{Serialize, Deserialize};
+
+pub use crate::playback::metadata::Tags;
+
+/// A song in the library, with its tags and how it is encoded.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Track {
+    pub path: String,
+    /// seconds since the unix epoch
+    pub mtime: u64,
+    pub size: u64,
+    pub tags: Tags,
+    pub codec: Option<String>,
+    pub sample_rate: Option<u32>,
+    pub channels: Option<u16>,
+    pub bits_per_sample: Option<u32>,
+    pub duration_ms: Option<u64>,
+    /// when it was first indexed, in unix seconds, 0 for
+    /// the tracks indexed before this was recorded
+    #[serde(default)]
+    pub added: u64
+}
+
+impl Track {
+    /// The album artist, or the artist if there is none.
+    #[inline]
+    pub fn album_artist(&self) -> Option<&str> {
+        self.tags.album_artist.as_deref().or(self.tags.artist.as_deref())
+    }
+
+    /// The average bitrate in kbps, from the size of the file.
+    /// Cue tracks share their file, so theirs is approximate.
+    #[inline]
+    pub fn bitrate(&self) -> Option<u64> {
+        self.duration_ms
+            .filter(|ms| *ms > 0)
+            .map(|ms| self.size * 8 / ms)
+    }
+
+    /// When it was added, the mtime stands in if unknown.
+    #[inline]
+    pub fn added(&self) -> u64 {
+        match self.added {
+            0 => self.mtime,
+            added => added
+        }
+    }
+
+    /// The codec, or the extension if the codec is unknown.
+    pub fn format(&self) -> String {
+        self.codec.clone().unwrap_or_else(|| {
+            std::path::Path::new(&self.path)
+                .extension()
+                .map(|ext| ext.to_string_lossy().to_lowercase())
+                .unwrap_or_default()
+        })
+    }
+}
@@ -0,0 +1,217 @@
+// Date: Sun Nov 19 16:40:27 2023
+// Mail: lunar_ubuntu@qq.com
+// Author: https://github.com/xiaoqixian
+
+/*
+ * Keep the library up to date by watching its roots, with
+ * inotify on linux. Events come in bursts, a single copy or
+ * tag edit makes many of them, so they are gathered until
+ * the roots are quiet for a moment and applied as a batch.
+ *
+ * A rename within the roots comes as both its old and new
+ * paths, the tracks are then moved in the index rather than
+ * probed again, and whoever holds the old paths is told.
+ */
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::RwLock,
+    time::Duration
+};
+
+use kanal::Receiver;
+
+use notify::{
+    Watcher,
+    RecommendedWatcher,
+    RecursiveMode,
+    Event,
+    EventKind,
+    event::{ModifyKind, RenameMode}
+};
+
+use super::{
+    Library,
+    scan::{is_audio, probe_file, walk}
+};
+
+/// How long the roots must be quiet before a batch is applied.
+const QUIET_PERIOD: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Default)]
+struct Batch {
+    moves: Vec<(PathBuf, PathBuf)>,
+    removed: HashSet<PathBuf>,
+    changed: HashSet<PathBuf>
+}
+
+impl Batch {
+    fn add(&mut self, event: Event) {
+        let mut paths = event.paths.into_iter();
+
+        match event.kind {
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+                if let (Some(from), Some(to)) = (paths.next(), paths.next()) {
+                    // the halves of the rename may have come first
+                    self.removed.remove(&from);
+                    self.changed.remove(&to);
+                    self.moves.push((from, to));
+                }
+            },
+            EventKind::Modify(ModifyKind::Name(RenameMode::From)) | EventKind::Remove(_) =>
+                self.removed.extend(paths),
+            EventKind::Modify(ModifyKind::Name(_)) |
+            EventKind::Modify(_) |
+            EventKind::Create(_) => self.changed.extend(paths),
+            _ => {}
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.moves.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Probe the changed files without holding the lock,
+/// a directory moved in has all of its files probed.
+fn probe_changed(library: &RwLock<Library>, changed: HashSet<PathBuf>) -> Vec<super::track::Track> {
+    let mut files = Vec::new();
+    for path in changed {
+        match path.is_dir() {
+            true => walk(&path, &mut files),
+            false if is_audio(&path) => files.push(path),
+            false => {}
+        }
+    }
+
+    let stale = {
+        let library = library.read().unwrap();
+        files.into_iter()
+            .filter(|path| library.is_stale(path))
+            .collect::<Vec<PathBuf>>()
+    };
+
+    stale.iter().filter_map(|path| probe_file(path)).collect()
+}
+
+fn apply<F>(library: &RwLock<Library>, batch: Batch, on_move: &F)
+where F: Fn(String, String)
+{
+    let mut changed = batch.changed;
+
+    {
+        let mut library = library.write().unwrap();
+        for (from, to) in batch.moves {
+            // moved in from somewhere unknown, index it as new
+            if library.rename(&from, &to) == 0 {
+                changed.insert(to.clone());
+            }
+            on_move(from.to_string_lossy().into_owned(), to.to_string_lossy().into_owned());
+        }
+
+        batch.removed.iter().for_each(|path| {
+            let _ = library.remove(path);
+        });
+    }
+
+    let tracks = probe_changed(library, changed);
+
+    let mut library = library.write().unwrap();
+    tracks.into_iter().for_each(|track| {
+        let _ = library.insert(track);
+    });
+    let _ = library.save();
+}
+
+/// The watcher of the roots, the events wait in the
+/// channel until they are applied.
+pub struct Watching {
+    _watcher: RecommendedWatcher,
+    events: Receiver<Event>
+}
+
+/// Start watching the roots, None if the watcher fails.
+pub fn start(roots: &[PathBuf]) -> Option<Watching> {
+    let (tx, rx) = kanal::unbounded::<Event>();
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<Event>| {
+        if let Ok(event) = res {
+            let _ = tx.send(event);
+        }
+    }).ok()?;
+
+    roots.iter()
+        .filter(|root| Path::new(root).is_dir())
+        .for_each(|root| {
+            let _ = watcher.watch(root, RecursiveMode::Recursive);
+        });
+
+    Some(Watching {
+        _watcher: watcher,
+        events: rx
+    })
+}
+
+impl Watching {
+    /// Apply the changes until the watcher fails, the
+    /// calling thread is taken.
+    pub fn run<F>(self, library: &RwLock<Library>, on_move: F)
+    where F: Fn(String, String)
+    {
+        loop {
+            let mut batch = Batch::default();
+            match self.events.recv() {
+                Err(_) => return,
+                Ok(event) => batch.add(event)
+            }
+
+            while let Ok(event) = self.events.recv_timeout(QUIET_PERIOD) {
+                batch.add(event);
+            }
+
+            if !batch.is_empty() {
+                apply(library, batch, &on_move);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_batch() {
+    use notify::event::{CreateKind, RemoveKind};
+
+    let event = |kind, paths: &[&str]| Event {
+        kind,
+        paths: paths.iter().map(PathBuf::from).collect(),
+        attrs: Default::default()
+    };
+
+    // the halves of a rename may come before the rename itself
+    let mut batch = Batch::default();
+    batch.add(event(EventKind::Modify(ModifyKind::Name(RenameMode::From)), &["/a/01.mp3"]));
+    batch.add(event(EventKind::Modify(ModifyKind::Name(RenameMode::To)), &["/b/01.mp3"]));
+    batch.add(event(EventKind::Modify(ModifyKind::Name(RenameMode::Both)), &["/a/01.mp3", "/b/01.mp3"]));
+    assert_eq!(batch.moves, vec![(PathBuf::from("/a/01.mp3"), PathBuf::from("/b/01.mp3"))]);
+    assert!(batch.removed.is_empty() && batch.changed.is_empty());
+
+    // a file replaced is removed and then indexed again
+    let dir = std::env::temp_dir().join(format!("tmusic_watch_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("01.wav");
+    crate::playback::test_wav(&file, 0.1);
+
+    let path = file.to_string_lossy().into_owned();
+    let mut batch = Batch::default();
+    batch.add(event(EventKind::Remove(RemoveKind::File), &[&path]));
+    batch.add(event(EventKind::Create(CreateKind::File), &[&path]));
+
+    let library = RwLock::new(Library::open(None));
+    apply(&library, batch, &|_, _| {});
+    assert!(library.read().unwrap().get(&path).is_some());
+
+    let mut batch = Batch::default();
+    batch.add(event(EventKind::Remove(RemoveKind::File), &[&path]));
+    apply(&library, batch, &|_, _| {});
+    assert!(library.read().unwrap().get(&path).is_none());
+    let _ = std::fs::remove_dir_all(&dir);
+}
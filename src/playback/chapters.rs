@@ -0,0 +1,548 @@
+// Date: Sat Nov 11 16:27:50 2023
+// Mail: lunar_ubuntu@qq.com
+// Author: https://github.com/xiaoqixian
+
+/*
+ * Read the chapters embedded in a file, supported are:
+ *  1. ID3v2 CHAP frames, used by mp3 audiobooks and podcasts
+ *  2. MP4/M4B, both the Nero `chpl` atom and the QuickTime
+ *     chapter text track referenced by `tref/chap`
+ *  3. Matroska/WebM `Chapters` elements
+ *
+ * Files without chapters, or whose chapters cannot be
+ * parsed, just get an empty chapter list.
+ */
+
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+    time::Duration
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chapter {
+    pub title: String,
+    pub start: Duration,
+    /// None if the file does not tell, it ends where
+    /// the next chapter starts then.
+    pub end: Option<Duration>
+}
+
+/// Read the chapters of a file, sorted by their start.
+pub fn read_chapters(path: &Path) -> Vec<Chapter> {
+    let mut file = match File::open(path) {
+        Ok(f) => f,
+        Err(_) => return Vec::new()
+    };
+
+    let mut magic = [0u8; 8];
+    if file.read_exact(&mut magic).is_err() || file.seek(SeekFrom::Start(0)).is_err() {
+        return Vec::new();
+    }
+
+    let mut chapters = match magic {
+        [b'I', b'D', b'3', ..] => read_id3(path),
+        [_, _, _, _, b'f', b't', b'y', b'p'] => mp4::read(&mut file),
+        [0x1a, 0x45, 0xdf, 0xa3, ..] => mkv::read(&mut file),
+        _ => None
+    }.unwrap_or_default();
+
+    chapters.sort_by_key(|chapter| chapter.start);
+
+    // fill the missing ends with the start of the next chapter
+    let starts = chapters.iter().map(|c| c.start).skip(1).collect::<Vec<Duration>>();
+    chapters.iter_mut().zip(starts).for_each(|(chapter, next_start)| {
+        if chapter.end.is_none() {
+            chapter.end = Some(next_start);
+        }
+    });
+
+    chapters
+}
+
+/// The index of the chapter `position` falls in.
+pub fn chapter_at(chapters: &[Chapter], position: Duration) -> Option<usize> {
+    chapters.iter().rposition(|chapter| chapter.start <= position)
+}
+
+fn read_u32(buf: &[u8]) -> u32 {
+    u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]])
+}
+
+fn read_u64(buf: &[u8]) -> u64 {
+    u64::from_be_bytes([buf[0], buf[1], buf[2], buf[3], buf[4], buf[5], buf[6], buf[7]])
+}
+
+/// Read `size` bytes, sizes are taken from the file so
+/// they are checked against what is left of it first.
+fn read_body(file: &mut File, size: u64) -> Option<Vec<u8>> {
+    let left = file.metadata().ok()?.len().saturating_sub(file.stream_position().ok()?);
+    if size > left {
+        return None;
+    }
+
+    let mut body = vec![0u8; size as usize];
+    file.read_exact(&mut body).ok()?;
+    Some(body)
+}
+
+/// ID3v2 CHAP frames, titled by their TIT2 sub frames.
+fn read_id3(path: &Path) -> Option<Vec<Chapter>> {
+    let tag = id3::Tag::read_from_path(path).ok()?;
+
+    Some(tag.chapters()
+        .map(|chapter| Chapter {
+            title: chapter.frames
+                .iter()
+                .find(|frame| frame.id() == "TIT2")
+                .and_then(|frame| frame.content().text())
+                .map(|title| title.trim_end_matches('\0').to_string())
+                .unwrap_or_else(|| chapter.element_id.clone()),
+            start: Duration::from_millis(chapter.start_time as u64),
+            end: match chapter.end_time {
+                0 | u32::MAX => None,
+                ms => Some(Duration::from_millis(ms as u64))
+            }
+        })
+        .collect())
+}
+
+mod mp4 {
+    use super::*;
+
+    /// An atom read into memory: its type and its body.
+    struct Atom {
+        kind: [u8; 4],
+        body: Vec<u8>
+    }
+
+    /// Find the child atoms of a body as (type, body range).
+    fn children(body: &[u8]) -> Vec<([u8; 4], std::ops::Range<usize>)> {
+        let mut atoms = Vec::new();
+        let mut pos = 0;
+
+        while pos + 8 <= body.len() {
+            let size = read_u32(&body[pos..]) as usize;
+            let kind = [body[pos + 4], body[pos + 5], body[pos + 6], body[pos + 7]];
+            let (header, size) = match size {
+                0 => (8, body.len() - pos),
+                1 if pos + 16 <= body.len() => (16, read_u64(&body[pos + 8..]) as usize),
+                _ => (8, size)
+            };
+
+            if size < header || size > body.len() - pos {
+                break;
+            }
+            atoms.push((kind, pos + header..pos + size));
+            pos += size;
+        }
+
+        atoms
+    }
+
+    fn child<'a>(body: &'a [u8], path: &[&[u8; 4]]) -> Option<&'a [u8]> {
+        let (first, rest) = path.split_first()?;
+        let (_, range) = children(body)
+            .into_iter()
+            .find(|(kind, _)| kind == *first)?;
+
+        match rest.is_empty() {
+            true => Some(&body[range]),
+            false => child(&body[range], rest)
+        }
+    }
+
+    /// Read the top level `moov` atom, the media data is
+    /// skipped over rather than read.
+    fn read_moov(file: &mut File) -> Option<Atom> {
+        loop {
+            let mut header = [0u8; 8];
+            file.read_exact(&mut header).ok()?;
+            let kind = [header[4], header[5], header[6], header[7]];
+
+            let (header_len, size) = match read_u32(&header) {
+                1 => {
+                    let mut large = [0u8; 8];
+                    file.read_exact(&mut large).ok()?;
+                    (16, read_u64(&large))
+                },
+                size => (8, size as u64)
+            };
+
+            if size < header_len {
+                return None;
+            }
+
+            if &kind == b"moov" {
+                let body = read_body(file, size - header_len)?;
+                return Some(Atom { kind, body });
+            }
+
+            let skip = i64::try_from(size - header_len).ok()?;
+            file.seek(SeekFrom::Current(skip)).ok()?;
+        }
+    }
+
+    /// Nero chapters: a list of 100ns start times and titles.
+    fn read_chpl(moov: &[u8]) -> Option<Vec<Chapter>> {
+        let chpl = child(moov, &[b"udta", b"chpl"])?;
+        let version = *chpl.first()?;
+        let mut pos = if version > 0 { 8 } else { 4 };
+        let count = *chpl.get(pos)?;
+        pos += 1;
+
+        let mut chapters = Vec::new();
+        for _ in 0..count {
+            let start = read_u64(chpl.get(pos..pos + 8)?);
+            let len = *chpl.get(pos + 8)? as usize;
+            let title = chpl.get(pos + 9..pos + 9 + len)?;
+            pos += 9 + len;
+
+            chapters.push(Chapter {
+                title: String::from_utf8_lossy(title).into_owned(),
+                start: Duration::from_nanos(start * 100),
+                end: None
+            });
+        }
+
+        Some(chapters)
+    }
+
+    /// QuickTime chapters: the audio track references a text
+    /// track by `tref/chap`, every sample of it is a title.
+    fn read_chapter_track(file: &mut File, moov: &[u8]) -> Option<Vec<Chapter>> {
+        let traks = children(moov)
+            .into_iter()
+            .filter(|(kind, _)| kind == b"trak")
+            .map(|(_, range)| &moov[range])
+            .collect::<Vec<&[u8]>>();
+
+        let chap_id = traks.iter()
+            .find_map(|trak| child(trak, &[b"tref", b"chap"]))
+            .and_then(|chap| chap.get(0..4))
+            .map(read_u32)?;
+
+        let trak = traks.into_iter().find(|trak| {
+            child(trak, &[b"tkhd"])
+                .and_then(|tkhd| {
+                    let offset = if tkhd.first() == Some(&1) { 20 } else { 12 };
+                    tkhd.get(offset..offset + 4)
+                })
+                .map(read_u32) == Some(chap_id)
+        })?;
+
+        let mdhd = child(trak, &[b"mdia", b"mdhd"])?;
+        let timescale = match mdhd.first() {
+            Some(1) => read_u32(mdhd.get(20..24)?),
+            _ => read_u32(mdhd.get(12..16)?)
+        } as u64;
+        if timescale == 0 {
+            return None;
+        }
+
+        let stbl = child(trak, &[b"mdia", b"minf", b"stbl"])?;
+
+        // chunk offsets, chapter tracks have one sample per chunk,
+        // so there are no more samples than there are chunks
+        let offsets = match child(stbl, &[b"stco"]) {
+            Some(stco) => stco.get(8..)?.chunks_exact(4).map(|b| read_u32(b) as u64).collect(),
+            None => {
+                let co64 = child(stbl, &[b"co64"])?;
+                co64.get(8..)?.chunks_exact(8).map(read_u64).collect::<Vec<u64>>()
+            }
+        };
+        let samples = offsets.len();
+
+        // sample durations
+        let stts = child(stbl, &[b"stts"])?;
+        let mut durations = Vec::<u64>::new();
+        for entry in stts.get(8..)?.chunks_exact(8).take(read_u32(stts.get(4..)?) as usize) {
+            let (count, delta) = (read_u32(entry) as usize, read_u32(&entry[4..]) as u64);
+            let count = std::cmp::min(count, samples - durations.len());
+            durations.extend(std::iter::repeat_n(delta, count));
+        }
+
+        // sample sizes
+        let stsz = child(stbl, &[b"stsz"])?;
+        let uniform = read_u32(stsz.get(4..)?);
+        let count = std::cmp::min(read_u32(stsz.get(8..)?) as usize, samples);
+        let sizes = match uniform {
+            0 => stsz.get(12..)?.chunks_exact(4).take(count).map(read_u32).collect(),
+            size => vec![size; count]
+        };
+
+        let mut chapters = Vec::new();
+        let mut start = 0u64;
+        for ((offset, size), duration) in offsets.into_iter().zip(sizes).zip(durations) {
+            file.seek(SeekFrom::Start(offset)).ok()?;
+            let sample = read_body(file, size as u64)?;
+
+            // a text sample is a 16 bits length and the text
+            let len = match sample.get(0..2) {
+                Some(len) => u16::from_be_bytes([len[0], len[1]]) as usize,
+                None => 0
+            };
+            let title = sample.get(2..2 + len).unwrap_or(&[]);
+
+            chapters.push(Chapter {
+                title: String::from_utf8_lossy(title).into_owned(),
+                start: Duration::from_millis(start * 1000 / timescale),
+                end: Some(Duration::from_millis((start + duration) * 1000 / timescale))
+            });
+            start += duration;
+        }
+
+        Some(chapters)
+    }
+
+    pub fn read(file: &mut File) -> Option<Vec<Chapter>> {
+        let moov = read_moov(file)?;
+        debug_assert_eq!(&moov.kind, b"moov");
+
+        match read_chapter_track(file, &moov.body) {
+            Some(chapters) if !chapters.is_empty() => Some(chapters),
+            _ => read_chpl(&moov.body)
+        }
+    }
+}
+
+mod mkv {
+    use super::*;
+
+    const SEGMENT: u32 = 0x18538067;
+    const SEEK_HEAD: u32 = 0x114D9B74;
+    const SEEK: u32 = 0x4DBB;
+    const SEEK_ID: u32 = 0x53AB;
+    const SEEK_POSITION: u32 = 0x53AC;
+    const CHAPTERS: u32 = 0x1043A770;
+    const CLUSTER: u32 = 0x1F43B675;
+    const EDITION_ENTRY: u32 = 0x45B9;
+    const CHAPTER_ATOM: u32 = 0xB6;
+    const CHAPTER_TIME_START: u32 = 0x91;
+    const CHAPTER_TIME_END: u32 = 0x92;
+    const CHAPTER_DISPLAY: u32 = 0x80;
+    const CHAP_STRING: u32 = 0x85;
+
+    /// Read an EBML variable size integer, the id keeps
+    /// its length marker while a size does not.
+    fn read_vint(file: &mut File, keep_marker: bool) -> Option<(u64, usize)> {
+        let mut first = [0u8; 1];
+        file.read_exact(&mut first).ok()?;
+
+        let len = first[0].leading_zeros() as usize + 1;
+        if len > 8 {
+            return None;
+        }
+
+        let mut value = match keep_marker {
+            true => first[0] as u64,
+            false => first[0] as u64 & (0xff >> len)
+        };
+        let mut rest = vec![0u8; len - 1];
+        file.read_exact(&mut rest).ok()?;
+        rest.into_iter().for_each(|b| value = (value << 8) | b as u64);
+
+        Some((value, len))
+    }
+
+    /// Read an element header as (id, size), None as size
+    /// means the size is unknown.
+    fn read_header(file: &mut File) -> Option<(u32, Option<u64>)> {
+        let (id, _) = read_vint(file, true)?;
+        let (size, len) = read_vint(file, false)?;
+        let unknown = size == (1u64 << (7 * len)) - 1;
+        Some((id as u32, if unknown { None } else { Some(size) }))
+    }
+
+    /// Split an element body in memory into (id, body) children.
+    fn children(body: &[u8]) -> Vec<(u32, &[u8])> {
+        let mut elements = Vec::new();
+        let mut pos = 0;
+
+        let vint = |pos: usize, keep_marker: bool| -> Option<(u64, usize)> {
+            let first = *body.get(pos)?;
+            let len = first.leading_zeros() as usize + 1;
+            if len > 8 {
+                return None;
+            }
+            let init = if keep_marker { first as u64 } else { first as u64 & (0xff >> len) };
+            let value = body.get(pos + 1..pos + len)?
+                .iter()
+                .fold(init, |acc, &b| (acc << 8) | b as u64);
+            Some((value, len))
+        };
+
+        while pos < body.len() {
+            let (id, id_len) = match vint(pos, true) {
+                None => break,
+                Some(v) => v
+            };
+            let (size, size_len) = match vint(pos + id_len, false) {
+                None => break,
+                Some(v) => v
+            };
+
+            let start = pos + id_len + size_len;
+            let end = std::cmp::min(start.saturating_add(size as usize), body.len());
+            elements.push((id as u32, &body[start..end]));
+            pos = end;
+        }
+
+        elements
+    }
+
+    fn uint(body: &[u8]) -> u64 {
+        body.iter().fold(0, |acc, &b| (acc << 8) | b as u64)
+    }
+
+    fn parse_chapters(body: &[u8]) -> Vec<Chapter> {
+        children(body)
+            .into_iter()
+            .filter(|(id, _)| *id == EDITION_ENTRY)
+            // only the first edition is used
+            .take(1)
+            .flat_map(|(_, edition)| children(edition))
+            .filter(|(id, _)| *id == CHAPTER_ATOM)
+            .filter_map(|(_, atom)| {
+                let elements = children(atom);
+                let start = elements.iter().find(|(id, _)| *id == CHAPTER_TIME_START)?.1;
+                let end = elements.iter().find(|(id, _)| *id == CHAPTER_TIME_END);
+                let title = elements.iter()
+                    .filter(|(id, _)| *id == CHAPTER_DISPLAY)
+                    .flat_map(|(_, display)| children(display))
+                    .find(|(id, _)| *id == CHAP_STRING)
+                    .map(|(_, s)| String::from_utf8_lossy(s).into_owned())
+                    .unwrap_or_default();
+
+                Some(Chapter {
+                    title,
+                    start: Duration::from_nanos(uint(start)),
+                    end: end.map(|(_, e)| Duration::from_nanos(uint(e)))
+                })
+            })
+            .collect()
+    }
+
+    pub fn read(file: &mut File) -> Option<Vec<Chapter>> {
+        // skip the EBML header
+        let (_, size) = read_header(file)?;
+        file.seek(SeekFrom::Current(i64::try_from(size?).ok()?)).ok()?;
+
+        let (id, segment_size) = read_header(file)?;
+        if id != SEGMENT {
+            return None;
+        }
+        let segment_start = file.stream_position().ok()?;
+        let segment_end = segment_size.map(|size| segment_start + size);
+        let mut chapters_pos = None;
+
+        loop {
+            let pos = file.stream_position().ok()?;
+            if segment_end.is_some_and(|end| pos >= end) {
+                return None;
+            }
+
+            let (id, size) = read_header(file)?;
+
+            match (id, size) {
+                (CHAPTERS, Some(size)) => {
+                    let body = read_body(file, size)?;
+                    return Some(parse_chapters(&body));
+                },
+                (SEEK_HEAD, Some(size)) => {
+                    let body = read_body(file, size)?;
+
+                    chapters_pos = children(&body)
+                        .into_iter()
+                        .filter(|(id, _)| *id == SEEK)
+                        .map(|(_, seek)| children(seek))
+                        .find(|seek| seek.iter().any(|(id, v)| *id == SEEK_ID && uint(v) == CHAPTERS as u64))
+                        .and_then(|seek| seek.into_iter().find(|(id, _)| *id == SEEK_POSITION))
+                        .map(|(_, v)| segment_start + uint(v))
+                        .or(chapters_pos);
+                },
+                // the media data starts, jump to the chapters if
+                // the seek head tells where they are
+                (CLUSTER, _) | (_, None) => {
+                    file.seek(SeekFrom::Start(chapters_pos.take()?)).ok()?;
+                },
+                (_, Some(size)) => {
+                    file.seek(SeekFrom::Current(i64::try_from(size).ok()?)).ok()?;
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_id3_chapters() {
+    // build an ID3v2.3 tag with two CHAP frames, each titled by a TIT2
+    fn frame(id: &[u8], body: &[u8]) -> Vec<u8> {
+        let mut frame = id.to_vec();
+        frame.extend((body.len() as u32).to_be_bytes());
+        frame.extend([0, 0]);
+        frame.extend(body);
+        frame
+    }
+
+    fn chap(id: &[u8], start_ms: u32, end_ms: u32, title: &str) -> Vec<u8> {
+        let mut body = id.to_vec();
+        body.push(0);
+        [start_ms, end_ms, u32::MAX, u32::MAX].iter().for_each(|v| body.extend(v.to_be_bytes()));
+        let mut text = vec![3u8];
+        text.extend(title.as_bytes());
+        body.extend(frame(b"TIT2", &text));
+        frame(b"CHAP", &body)
+    }
+
+    let mut frames = chap(b"ch1", 60_000, 0, "第二章");
+    frames.extend(chap(b"ch0", 0, 60_000, "第一章"));
+
+    let size = frames.len();
+    let mut tag = b"ID3\x03\x00\x00".to_vec();
+    tag.extend((0..4).rev().map(|i| ((size >> (7 * i)) & 0x7f) as u8));
+    tag.extend(frames);
+
+    let path = std::env::temp_dir().join("tmusic_test_chapters.mp3");
+    std::fs::write(&path, &tag).unwrap();
+    let chapters = read_chapters(&path);
+    let _ = std::fs::remove_file(&path);
+
+    assert_eq!(chapters, vec![
+        Chapter {
+            title: String::from("第一章"),
+            start: Duration::ZERO,
+            end: Some(Duration::from_secs(60))
+        },
+        Chapter {
+            title: String::from("第二章"),
+            start: Duration::from_secs(60),
+            end: None
+        }
+    ]);
+    assert_eq!(chapter_at(&chapters, Duration::from_secs(61)), Some(1));
+}
+
+#[test]
+fn test_broken_chapters() {
+    let path = std::env::temp_dir().join(format!("tmusic_broken_chapters_{}", std::process::id()));
+    let read = |content: &[u8]| {
+        std::fs::write(&path, content).unwrap();
+        read_chapters(&path)
+    };
+
+    // an extended header flagged in a tag too short for it
+    assert!(read(b"ID3\x04\x00\x40\x00\x00\x00\x02\x00\x00").is_empty());
+    // a tag larger than the file
+    assert!(read(b"ID3\x03\x00\x00\x7f\x7f\x7f\x7f").is_empty());
+
+    // a moov atom larger than the file
+    let mut mp4 = b"\x00\x00\x00\x10ftypM4A \x00\x00\x00\x00".to_vec();
+    mp4.extend(b"\xff\xff\xff\xf0moov");
+    assert!(read(&mp4).is_empty());
+
+    // a Matroska header of an absurd size
+    assert!(read(b"\x1a\x45\xdf\xa3\x01\x7f\xff\xff\xff\xff\xff\xfe").is_empty());
+    let _ = std::fs::remove_file(&path);
+}
@@ -0,0 +1,294 @@
+// Date: Sun Nov 12 15:38:04 2023
+// Mail: lunar_ubuntu@qq.com
+// Author: https://github.com/xiaoqixian
+
+/*
+ * CUE sheets split one big file into tracks. Every track
+ * of a sheet becomes a virtual entry in the queue, written
+ * as `<sheet path>#<track number>`, so the lanes, the history
+ * and the session keep carrying plain strings.
+ *
+ * A virtual entry is decoded from its backing file, starting
+ * at the track's INDEX 01 and ending where the next track in
+ * the same file starts.
+ *
+ * Only sheets backed by files the decoders take, like FLAC
+ * and WAV rips, are played. APE rips are NOT supported:
+ * symphonia has no Monkey's Audio decoder, so sheets backed
+ * by APE files are refused as a whole with UnsupportedFormat
+ * rather than queued as tracks that would fail one by one.
+ *
+ * Sheets written on Chinese systems are often in GBK or Big5,
+ * they are decoded like the legacy tags, see charset.rs.
+ */
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock},
+    time::{Duration, SystemTime}
+};
+
+use super::{PlayerError, metadata::charset};
+
+/// CUE positions are in mm:ss:ff, with 75 frames a second.
+const FRAMES_PER_SEC: u64 = 75;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CueTrack {
+    pub number: u32,
+    pub title: Option<String>,
+    pub performer: Option<String>,
+    /// the file the track is in, resolved against the sheet
+    pub file: PathBuf,
+    pub start: Duration,
+    /// None for the last track of a file, it plays to the end.
+    pub end: Option<Duration>
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CueSheet {
+    pub title: Option<String>,
+    pub performer: Option<String>,
+    pub tracks: Vec<CueTrack>
+}
+
+#[inline]
+pub fn is_cue(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("cue"))
+}
+
+/// The entry of a track of a sheet.
+#[inline]
+pub fn track_entry(sheet: &str, number: u32) -> String {
+    format!("{}#{}", sheet, number)
+}
+
+/// Split a virtual entry into its sheet path and track number,
+/// None if the entry is a plain file.
+pub fn split_entry(entry: &str) -> Option<(&str, u32)> {
+    let (sheet, number) = entry.rsplit_once('#')?;
+    match is_cue(sheet) {
+        true => number.parse().ok().map(|number| (sheet, number)),
+        false => None
+    }
+}
+
+/// Take the first token of a line, either quoted or not,
+/// and return it along with the rest of the line.
+fn token(line: &str) -> (String, &str) {
+    let line = line.trim_start();
+
+    match line.strip_prefix('"') {
+        Some(quoted) => match quoted.find('"') {
+            Some(end) => (String::from(&quoted[..end]), &quoted[end + 1..]),
+            None => (String::from(quoted), "")
+        },
+        None => match line.find(char::is_whitespace) {
+            Some(end) => (String::from(&line[..end]), &line[end..]),
+            None => (String::from(line), "")
+        }
+    }
+}
+
+fn parse_time(time: &str) -> Option<Duration> {
+    let mut fields = time.split(':').map(|field| field.parse::<u64>().ok());
+    let (min, sec, frames) = (fields.next()??, fields.next()??, fields.next()??);
+    let frames = (min * 60 + sec) * FRAMES_PER_SEC + frames;
+    Some(Duration::from_micros(frames * 1_000_000 / FRAMES_PER_SEC))
+}
+
+impl CueSheet {
+    /// Parse a sheet, the FILE paths are resolved against `dir`.
+    pub fn parse(content: &str, dir: &Path) -> Self {
+        let mut sheet = CueSheet::default();
+        let mut file: Option<PathBuf> = None;
+
+        for line in content.trim_start_matches('\u{feff}').lines() {
+            let (command, rest) = token(line);
+            let (arg, rest) = token(rest);
+
+            match (command.to_ascii_uppercase().as_str(), sheet.tracks.last_mut()) {
+                ("FILE", _) => file = Some(dir.join(arg)),
+                ("TRACK", _) => {
+                    let (Some(number), Some(file)) = (arg.parse().ok(), file.clone()) else {
+                        continue;
+                    };
+                    // only audio tracks are played
+                    if !token(rest).0.eq_ignore_ascii_case("AUDIO") {
+                        continue;
+                    }
+
+                    sheet.tracks.push(CueTrack {
+                        number,
+                        title: None,
+                        performer: None,
+                        file,
+                        start: Duration::ZERO,
+                        end: None
+                    });
+                },
+                ("TITLE", None) => sheet.title = Some(arg),
+                ("TITLE", Some(track)) => track.title = Some(arg),
+                ("PERFORMER", None) => sheet.performer = Some(arg),
+                ("PERFORMER", Some(track)) => track.performer = Some(arg),
+                ("INDEX", Some(track)) if arg == "01" => {
+                    if let Some(start) = parse_time(&token(rest).0) {
+                        track.start = start;
+                    }
+                },
+                _ => {}
+            }
+        }
+
+        // a track ends where the next one in the same file starts
+        for i in 1..sheet.tracks.len() {
+            if sheet.tracks[i].file == sheet.tracks[i - 1].file {
+                sheet.tracks[i - 1].end = Some(sheet.tracks[i].start);
+            }
+        }
+
+        sheet
+    }
+
+    pub fn open(path: &str) -> Result<Self, PlayerError> {
+        let content = match fs::read(path) {
+            Ok(content) => content,
+            Err(e) => return Err(PlayerError::IOError(e))
+        };
+        let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+
+        Ok(Self::parse(&charset::decode_bytes(&content), dir))
+    }
+
+    #[inline]
+    pub fn track(&self, number: u32) -> Option<&CueTrack> {
+        self.tracks.iter().find(|track| track.number == number)
+    }
+
+    /// The performer of a track, the sheet's if the track has none.
+    pub fn performer_of<'a>(&'a self, track: &'a CueTrack) -> Option<&'a str> {
+        track.performer.as_deref().or(self.performer.as_deref())
+    }
+}
+
+/// The sheets parsed, with when their files were modified.
+type SheetCache = HashMap<String, (Option<SystemTime>, Arc<CueSheet>)>;
+
+/// Sheets are parsed once and kept until the file changes,
+/// the views ask for the titles of the entries every render.
+pub fn load(path: &str) -> Result<Arc<CueSheet>, PlayerError> {
+    static CACHE: OnceLock<Mutex<SheetCache>> = OnceLock::new();
+
+    let modified = fs::metadata(path).and_then(|meta| meta.modified()).ok();
+    let mut cache = CACHE.get_or_init(Default::default).lock().unwrap();
+
+    match cache.get(path) {
+        Some((mtime, sheet)) if *mtime == modified => Ok(sheet.clone()),
+        _ => {
+            let sheet = Arc::new(CueSheet::open(path)?);
+            cache.insert(String::from(path), (modified, sheet.clone()));
+            Ok(sheet)
+        }
+    }
+}
+
+/// Expand a sheet into the entries of its tracks, the tracks
+/// whose backing files are missing are left out.
+pub fn expand(path: &str) -> Result<Vec<String>, PlayerError> {
+    let sheet = load(path)?;
+    let ape = sheet.tracks
+        .iter()
+        .find(|track| track.file.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("ape")));
+    if let Some(track) = ape {
+        return Err(PlayerError::UnsupportedFormat(track.file.to_string_lossy().into_owned()));
+    }
+
+    let entries = sheet.tracks
+        .iter()
+        .filter(|track| track.file.is_file())
+        .map(|track| track_entry(path, track.number))
+        .collect::<Vec<String>>();
+
+    match entries.is_empty() {
+        true => Err(PlayerError::WrongFileType(String::from(path))),
+        false => Ok(entries)
+    }
+}
+
+/// Find the track a virtual entry points to.
+pub fn resolve(entry: &str) -> Option<CueTrack> {
+    let (sheet, number) = split_entry(entry)?;
+    load(sheet).ok()?.track(number).cloned()
+}
+
+/// "performer - title" of a virtual entry.
+pub fn display_name(entry: &str) -> Option<String> {
+    let (sheet_path, number) = split_entry(entry)?;
+    let sheet = load(sheet_path).ok()?;
+    let track = sheet.track(number)?;
+    let title = track.title.clone().unwrap_or_else(|| format!("Track {:02}", number));
+
+    Some(match sheet.performer_of(track) {
+        None => title,
+        Some(performer) => format!("{} - {}", performer, title)
+    })
+}
+
+#[test]
+fn test_parse_cue() {
+    let content = "\u{feff}REM GENRE Pop\n\
+        PERFORMER \"李宗盛\"\n\
+        TITLE \"理性与感性\"\n\
+        FILE \"album.flac\" WAVE\n  \
+          TRACK 01 AUDIO\n    \
+            TITLE \"寂寞难耐\"\n    \
+            INDEX 01 00:00:00\n  \
+          TRACK 02 AUDIO\n    \
+            TITLE \"山丘\"\n    \
+            PERFORMER \"Jonathan Lee\"\n    \
+            INDEX 00 04:10:00\n    \
+            INDEX 01 04:12:37\n";
+
+    let sheet = CueSheet::parse(content, Path::new("/music"));
+    assert_eq!(sheet.title.as_deref(), Some("理性与感性"));
+    assert_eq!(sheet.tracks.len(), 2);
+
+    let (first, second) = (&sheet.tracks[0], &sheet.tracks[1]);
+    assert_eq!(first.file, Path::new("/music/album.flac"));
+    assert_eq!(first.end, Some(second.start));
+    assert_eq!(second.start, Duration::from_micros(252_493_333));
+    assert_eq!(second.end, None);
+    assert_eq!(sheet.performer_of(first), Some("李宗盛"));
+    assert_eq!(sheet.performer_of(second), Some("Jonathan Lee"));
+
+    assert_eq!(split_entry("/music/album.cue#2"), Some(("/music/album.cue", 2)));
+    assert_eq!(split_entry("/music/a#2.flac"), None);
+}
+
+#[test]
+fn test_legacy_sheet() {
+    use encoding_rs::GBK;
+
+    let dir = std::env::temp_dir().join(format!("tmusic_cue_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let write = |name: &str, backing: &str| {
+        let content = format!("TITLE \"理性与感性\"\nFILE \"{}\" WAVE\nTRACK 01 AUDIO\nTITLE \"山丘\"\nINDEX 01 00:00:00\n", backing);
+        let (bytes, _, _) = GBK.encode(&content);
+        let path = dir.join(name);
+        fs::write(&path, bytes).unwrap();
+        fs::write(dir.join(backing), b"").unwrap();
+        path.to_string_lossy().into_owned()
+    };
+
+    let sheet = write("flac.cue", "album.flac");
+    assert_eq!(CueSheet::open(&sheet).unwrap().tracks[0].title.as_deref(), Some("山丘"));
+    assert_eq!(expand(&sheet).unwrap(), vec![track_entry(&sheet, 1)]);
+
+    let sheet = write("ape.cue", "album.ape");
+    assert!(matches!(expand(&sheet), Err(PlayerError::UnsupportedFormat(file)) if file.ends_with("album.ape")));
+    let _ = fs::remove_dir_all(&dir);
+}
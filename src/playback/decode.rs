@@ -0,0 +1,244 @@
+// Date: Sat Dec  9 15:26:40 2023
+// Mail: lunar_ubuntu@qq.com
+// Author: https://github.com/xiaoqixian
+
+/*
+ * Songs are decoded by symphonia, so that they can be
+ * started anywhere: the container is seeked to the packet
+ * around the position, and only the few samples between
+ * that packet and the position are decoded and dropped.
+ * skip_duration of rodio decodes every sample before the
+ * position instead, which takes seconds into a long file.
+ *
+ * The files symphonia cannot open are left to the decoder
+ * of rodio, see PlayQueue::decode.
+ */
+
+use std::{fs::File, path::Path, time::Duration};
+
+use rodio::Source;
+
+use symphonia::core::{
+    audio::{SampleBuffer, SignalSpec},
+    codecs::{Decoder, DecoderOptions, CodecParameters},
+    errors::Error,
+    formats::{FormatOptions, FormatReader, SeekMode, SeekTo},
+    io::MediaSourceStream,
+    meta::MetadataOptions,
+    probe::Hint,
+    units::Time
+};
+
+pub struct SeekableDecoder {
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn Decoder>,
+    track_id: u32,
+    spec: SignalSpec,
+    /// what is left after the position started from
+    total_duration: Option<Duration>,
+    buffer: Option<SampleBuffer<i16>>,
+    /// the next sample in the buffer
+    pos: usize,
+    /// frames to drop before the position is reached
+    skip: u64,
+    /// started past the end
+    ended: bool
+}
+
+#[inline]
+fn duration_of(time: Time) -> Duration {
+    Duration::from_secs(time.seconds) + Duration::from_secs_f64(time.frac)
+}
+
+/// How many frames of a track `ts` takes.
+fn frames_of(params: &CodecParameters, ts: u64) -> u64 {
+    match (params.time_base, params.sample_rate) {
+        (Some(time_base), Some(rate)) =>
+            (duration_of(time_base.calc_time(ts)).as_secs_f64() * rate as f64) as u64,
+        _ => ts
+    }
+}
+
+impl SeekableDecoder {
+    /// None if symphonia cannot open or decode the file.
+    pub fn open(path: &Path, start: Duration) -> Option<Self> {
+        let file = File::open(path).ok()?;
+        let stream = MediaSourceStream::new(Box::new(file), Default::default());
+
+        let mut hint = Hint::new();
+        if let Some(ext) = path.extension().and_then(|ext| ext.to_str()) {
+            hint.with_extension(ext);
+        }
+
+        let format = symphonia::default::get_probe()
+            .format(&hint, stream, &FormatOptions::default(), &MetadataOptions::default())
+            .ok()?
+            .format;
+        let track = format.default_track()?;
+        let (track_id, params) = (track.id, track.codec_params.clone());
+        let decoder = symphonia::default::get_codecs()
+            .make(&params, &DecoderOptions::default())
+            .ok()?;
+
+        let total_duration = match (params.time_base, params.n_frames) {
+            (Some(time_base), Some(n_frames)) =>
+                Some(duration_of(time_base.calc_time(n_frames)).saturating_sub(start)),
+            _ => None
+        };
+
+        let mut source = Self {
+            format,
+            decoder,
+            track_id,
+            spec: SignalSpec::new(params.sample_rate?, params.channels?),
+            total_duration,
+            buffer: None,
+            pos: 0,
+            skip: 0,
+            ended: false
+        };
+
+        if !start.is_zero() {
+            let seeked = source.format.seek(SeekMode::Accurate, SeekTo::Time {
+                time: Time::from(start.as_secs_f64()),
+                track_id: Some(source.track_id)
+            });
+
+            source.skip = match seeked {
+                Ok(seeked) => frames_of(&params, seeked.required_ts.saturating_sub(seeked.actual_ts)),
+                // past the end, there is nothing left to play
+                Err(Error::SeekError(_)) => {
+                    source.ended = true;
+                    return Some(source);
+                },
+                Err(_) => return None
+            };
+            source.decoder.reset();
+        }
+
+        // the first packet tells the real sample rate and channels
+        source.decode_packet();
+        Some(source)
+    }
+
+    /// Decode the next packet into the buffer, the frames
+    /// to skip are dropped. None at the end of the track.
+    fn decode_packet(&mut self) -> Option<()> {
+        if self.ended {
+            return None;
+        }
+
+        loop {
+            let packet = self.format.next_packet().ok()?;
+            if packet.track_id() != self.track_id {
+                continue;
+            }
+
+            let decoded = match self.decoder.decode(&packet) {
+                Ok(decoded) => decoded,
+                // a broken packet is left out
+                Err(Error::DecodeError(_)) => continue,
+                Err(_) => return None
+            };
+
+            let frames = decoded.frames() as u64;
+            if self.skip >= frames {
+                self.skip -= frames;
+                continue;
+            }
+
+            let spec = *decoded.spec();
+            let buffer = match self.buffer {
+                Some(ref mut buffer) if spec == self.spec
+                    && buffer.capacity() >= decoded.capacity() * spec.channels.count() => buffer,
+                _ => self.buffer.insert(SampleBuffer::new(decoded.capacity() as u64, spec))
+            };
+            buffer.copy_interleaved_ref(decoded);
+
+            self.pos = self.skip as usize * spec.channels.count();
+            self.skip = 0;
+            self.spec = spec;
+            return Some(());
+        }
+    }
+}
+
+impl Iterator for SeekableDecoder {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        loop {
+            if let Some(sample) = self.buffer.as_ref().and_then(|buffer| buffer.samples().get(self.pos)) {
+                self.pos += 1;
+                return Some(*sample);
+            }
+
+            self.decode_packet()?;
+        }
+    }
+}
+
+impl Source for SeekableDecoder {
+    #[inline]
+    fn current_frame_len(&self) -> Option<usize> {
+        // None until the next packet is decoded
+        let buffered = self.buffer.as_ref().map_or(0, |buffer| buffer.len());
+        Some(buffered.saturating_sub(self.pos)).filter(|left| *left > 0)
+    }
+
+    #[inline]
+    fn channels(&self) -> u16 {
+        self.spec.channels.count() as u16
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> u32 {
+        self.spec.rate
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        self.total_duration
+    }
+}
+
+/// Write `secs` of 8 kHz mono 16 bits WAV to `path`, for
+/// the tests. Every sample is its own frame number.
+#[cfg(test)]
+pub fn test_wav(path: &Path, secs: f64) {
+    let frames = (8000.0 * secs) as u32;
+    let samples = (0..frames).flat_map(|i| (i as i16).to_le_bytes()).collect::<Vec<u8>>();
+
+    let mut wav = Vec::new();
+    wav.extend(b"RIFF");
+    wav.extend((36 + samples.len() as u32).to_le_bytes());
+    wav.extend(b"WAVEfmt ");
+    wav.extend(16u32.to_le_bytes());
+    wav.extend([1u16, 1].iter().flat_map(|n| n.to_le_bytes()));
+    wav.extend([8000u32, 16000].iter().flat_map(|n| n.to_le_bytes()));
+    wav.extend([2u16, 16].iter().flat_map(|n| n.to_le_bytes()));
+    wav.extend(b"data");
+    wav.extend((samples.len() as u32).to_le_bytes());
+    wav.extend(samples);
+    std::fs::write(path, wav).unwrap();
+}
+
+#[test]
+fn test_seek_into_song() {
+    let file = std::env::temp_dir().join(format!("tmusic_seek_{}.wav", std::process::id()));
+    test_wav(&file, 1.0);
+
+    let mut decoded = SeekableDecoder::open(&file, Duration::ZERO).unwrap();
+    assert_eq!((decoded.sample_rate(), decoded.channels()), (8000, 1));
+    assert_eq!(decoded.total_duration(), Some(Duration::from_secs(1)));
+    assert_eq!(decoded.next(), Some(0));
+
+    let mut decoded = SeekableDecoder::open(&file, Duration::from_millis(500)).unwrap();
+    assert_eq!(decoded.total_duration(), Some(Duration::from_millis(500)));
+    assert_eq!(decoded.next(), Some(4000));
+    assert_eq!(decoded.count(), 3999);
+
+    let mut decoded = SeekableDecoder::open(&file, Duration::from_secs(5)).unwrap();
+    assert_eq!(decoded.next(), None);
+    let _ = std::fs::remove_file(&file);
+}
@@ -0,0 +1,242 @@
+// Date: Sat Nov  4 16:05:51 2023
+// Mail: lunar_ubuntu@qq.com
+// Author: https://github.com/xiaoqixian
+
+/*
+ * History records every song played with when it started,
+ * how long it was played, how it ended and where it came
+ * from. Finished entries are appended to a log file as json
+ * lines, so the history survives across runs.
+ *
+ * Going back does not destroy anything, it only moves a
+ * cursor over the entries. While the cursor is set, the
+ * songs replayed are not logged again, though they are still
+ * returned when finished so that they are counted as played.
+ * Going forward moves the cursor on until it passes the last
+ * entry.
+ */
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH}
+};
+
+use serde::{Serialize, Deserialize};
+
+use super::Lane;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PlaySource {
+    /// the user lane
+    Queue,
+    /// the context lane
+    Context,
+    Radio
+}
+
+impl From<Lane> for PlaySource {
+    fn from(lane: Lane) -> Self {
+        match lane {
+            Lane::User => PlaySource::Queue,
+            Lane::Context => PlaySource::Context
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PlayStatus {
+    Playing,
+    Completed,
+    Skipped
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub path: String,
+    /// seconds since the unix epoch
+    pub started_at: u64,
+    /// how long it was listened to, the time paused or
+    /// skipped over by seeking is not counted
+    pub played_secs: u64,
+    /// how long the song is, if it was known
+    #[serde(default)]
+    pub duration_secs: Option<u64>,
+    pub status: PlayStatus,
+    pub source: PlaySource
+}
+
+#[derive(Debug, Default)]
+pub struct History {
+    entries: Vec<HistoryEntry>,
+    cursor: Option<usize>,
+    /// the song replayed under the cursor
+    replay: Option<HistoryEntry>,
+    log: Option<PathBuf>
+}
+
+#[inline]
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+impl History {
+    /// Open the history, the entries already in the log
+    /// are loaded. Lines that fail to parse are skipped.
+    pub fn open(log: Option<PathBuf>) -> Self {
+        let entries = match log.as_ref().map(File::open) {
+            Some(Ok(f)) => BufReader::new(f)
+                .lines()
+                .map_while(Result::ok)
+                .filter_map(|line| serde_json::from_str::<HistoryEntry>(&line).ok())
+                .collect(),
+            _ => Vec::new()
+        };
+
+        Self {
+            entries,
+            cursor: None,
+            replay: None,
+            log
+        }
+    }
+
+    #[inline]
+    pub fn entries(&self) -> &[HistoryEntry] {
+        &self.entries
+    }
+
+    #[inline]
+    pub fn cursor(&self) -> Option<usize> {
+        self.cursor
+    }
+
+    /// Restore a saved cursor, ignored if it is out of range.
+    pub fn set_cursor(&mut self, cursor: Option<usize>) {
+        self.cursor = cursor.filter(|&i| i < self.entries.len());
+        self.replay = None;
+    }
+
+    /// Leave the history navigation, the next song
+    /// started is recorded again.
+    #[inline]
+    pub fn reset_cursor(&mut self) {
+        self.cursor = None;
+        self.replay = None;
+    }
+
+    fn playing(&self) -> bool {
+        match self.entries.last() {
+            Some(entry) => entry.status == PlayStatus::Playing,
+            None => false
+        }
+    }
+
+    /// Record a song just started, it is kept aside as a
+    /// replay while navigating the history.
+    pub fn start(&mut self, path: String, source: PlaySource, duration: Option<Duration>) {
+        let entry = HistoryEntry {
+            path,
+            started_at: unix_now(),
+            played_secs: 0,
+            duration_secs: duration.map(|d| d.as_secs()),
+            status: PlayStatus::Playing,
+            source
+        };
+
+        match self.cursor {
+            Some(_) => self.replay = Some(entry),
+            None => self.entries.push(entry)
+        }
+    }
+
+    /// Finish the playing entry and append it to the log,
+    /// a replay is finished without being logged.
+    pub fn finish(&mut self, played: Duration, status: PlayStatus) -> Option<HistoryEntry> {
+        if self.cursor.is_some() {
+            return self.replay.take().map(|mut entry| {
+                entry.played_secs = played.as_secs();
+                entry.status = status;
+                entry
+            });
+        }
+        if !self.playing() {
+            return None;
+        }
+
+        let entry = self.entries.last_mut().unwrap();
+        entry.played_secs = played.as_secs();
+        entry.status = status;
+        let entry = entry.clone();
+
+        if let Some(ref log) = self.log {
+            let _ = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(log)
+                .and_then(|mut f| writeln!(f, "{}", serde_json::to_string(&entry).unwrap()));
+        }
+
+        Some(entry)
+    }
+
+    /// Move the cursor one entry back and return the song
+    /// under it. The playing entry is finished as skipped.
+    pub fn back(&mut self, played: Duration) -> Option<(String, Option<HistoryEntry>)> {
+        let target = match self.cursor {
+            Some(i) => i.checked_sub(1)?,
+            None => {
+                let skip = if self.playing() { 2 } else { 1 };
+                self.entries.len().checked_sub(skip)?
+            }
+        };
+
+        let finished = self.finish(played, PlayStatus::Skipped);
+        self.cursor = Some(target);
+        Some((self.entries[target].path.clone(), finished))
+    }
+
+    /// Move the cursor one entry forward and return the song
+    /// under it, None once the cursor passes the last entry.
+    pub fn forward(&mut self) -> Option<String> {
+        let next = self.cursor? + 1;
+
+        if next < self.entries.len() {
+            self.cursor = Some(next);
+            Some(self.entries[next].path.clone())
+        } else {
+            self.cursor = None;
+            None
+        }
+    }
+}
+
+#[test]
+fn test_back_and_forward() {
+    let mut history = History::open(None);
+    ["a", "b", "c"].into_iter().for_each(|path| {
+        history.start(String::from(path), PlaySource::Context, None);
+        history.finish(Duration::from_secs(1), PlayStatus::Completed);
+    });
+    history.start(String::from("d"), PlaySource::Queue, None);
+
+    assert_eq!(history.back(Duration::ZERO).unwrap().0, "c");
+    assert_eq!(history.back(Duration::ZERO).unwrap().0, "b");
+    assert_eq!(history.entries().len(), 4);
+    assert_eq!(history.entries()[3].status, PlayStatus::Skipped);
+
+    // a replay is reported when finished, but not logged again
+    history.start(String::from("b"), PlaySource::Context, None);
+    let replayed = history.finish(Duration::from_secs(2), PlayStatus::Completed).unwrap();
+    assert_eq!((replayed.path.as_str(), replayed.played_secs), ("b", 2));
+    assert_eq!(history.entries().len(), 4);
+
+    assert_eq!(history.forward().as_deref(), Some("c"));
+    assert_eq!(history.forward().as_deref(), Some("d"));
+    assert_eq!(history.forward(), None);
+    assert_eq!(history.cursor(), None);
+}
@@ -0,0 +1,154 @@
+// Date: Thu Nov  2 21:14:36 2023
+// Mail: lunar_ubuntu@qq.com
+// Author: https://github.com/xiaoqixian
+
+/*
+ * The play list is made of two lanes:
+ *  1. The user lane, songs explicitly queued by the user
+ *     with "play next". It is drained in FIFO order and
+ *     always takes priority.
+ *  2. The context lane, the album or playlist the user
+ *     started playing from.
+ *
+ * Only when the user lane is drained does the queue go on
+ * with the context.
+ */
+
+use std::{
+    collections::{VecDeque, HashSet, hash_map::RandomState},
+    hash::{BuildHasher, Hasher}
+};
+
+use super::{Lane, QueueSnapshot};
+
+#[derive(Debug, Default)]
+pub struct Lanes {
+    user: VecDeque<String>,
+    context: VecDeque<String>,
+    /// the lane the current song was taken from
+    current: Option<Lane>
+}
+
+impl Lanes {
+    #[inline]
+    pub fn lane_mut(&mut self, lane: Lane) -> &mut VecDeque<String> {
+        match lane {
+            Lane::User => &mut self.user,
+            Lane::Context => &mut self.context
+        }
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.user.is_empty() && self.context.is_empty()
+    }
+
+    #[inline]
+    pub fn current_lane(&self) -> Option<Lane> {
+        self.current
+    }
+
+    #[inline]
+    pub fn set_current_lane(&mut self, lane: Option<Lane>) {
+        self.current = lane;
+    }
+
+    /// Pop the next song, the user lane goes first.
+    /// With shuffle on, a random song of the context is
+    /// taken, the user lane is never shuffled.
+    pub fn pop_front(&mut self, shuffle: bool) -> Option<(String, Lane)> {
+        let next = match self.user.pop_front() {
+            Some(path) => Some((path, Lane::User)),
+            None if shuffle && !self.context.is_empty() => {
+                let index = random_index(self.context.len());
+                self.context
+                    .remove(index)
+                    .map(|path| (path, Lane::Context))
+            },
+            None => self.context
+                .pop_front()
+                .map(|path| (path, Lane::Context))
+        };
+
+        self.current = next.as_ref().map(|(_, lane)| *lane);
+        next
+    }
+
+    /// Put a song back to the front of the lane it came from.
+    #[inline]
+    pub fn push_front(&mut self, lane: Lane, path: String) {
+        self.lane_mut(lane).push_front(path);
+    }
+
+    /// Insert `songs` at `index` of a lane in their order,
+    /// nothing is inserted if `index` is past the end.
+    pub fn insert(&mut self, lane: Lane, index: usize, songs: Vec<String>) -> bool {
+        let lane = self.lane_mut(lane);
+        if index > lane.len() {
+            return false;
+        }
+
+        songs.into_iter()
+            .enumerate()
+            .for_each(|(i, song)| lane.insert(index + i, song));
+        true
+    }
+
+    /// Remove repeated songs across both lanes,
+    /// only the first occurrence is kept.
+    pub fn dedupe(&mut self) {
+        let mut seen = HashSet::<String>::new();
+        self.user.retain(|path| seen.insert(path.clone()));
+        self.context.retain(|path| seen.insert(path.clone()));
+    }
+
+    /// Rewrite the songs with `f`, the songs it returns
+    /// None for are kept as they are.
+    pub fn rewrite<F>(&mut self, f: F)
+    where F: Fn(&str) -> Option<String>
+    {
+        self.user
+            .iter_mut()
+            .chain(self.context.iter_mut())
+            .for_each(|path| {
+                if let Some(new) = f(path) {
+                    *path = new;
+                }
+            });
+    }
+
+    /// Replace both lanes, used to restore a session.
+    pub fn restore(&mut self, user: Vec<String>, context: Vec<String>, current: Option<Lane>) {
+        self.user = user.into();
+        self.context = context.into();
+        self.current = current;
+    }
+
+    pub fn snapshot(&self) -> QueueSnapshot {
+        QueueSnapshot {
+            user: self.user.iter().cloned().collect(),
+            context: self.context.iter().cloned().collect()
+        }
+    }
+}
+
+/// RandomState is seeded randomly each time it is created,
+/// which is random enough for shuffling.
+fn random_index(len: usize) -> usize {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_usize(len);
+    (hasher.finish() % len as u64) as usize
+}
+
+#[test]
+fn test_user_lane_first() {
+    let mut lanes = Lanes::default();
+    lanes.lane_mut(Lane::Context).extend(["a", "b"].map(String::from));
+    lanes.lane_mut(Lane::User).push_back(String::from("x"));
+    lanes.lane_mut(Lane::User).push_back(String::from("y"));
+
+    let order = std::iter::from_fn(|| lanes.pop_front(false))
+        .map(|(path, _)| path)
+        .collect::<Vec<String>>();
+    assert_eq!(order, ["x", "y", "a", "b"]);
+}
@@ -75,7 +75,7 @@ where
                 _ => panic!("Unexpected ResponseType")
             };
 
-            let _ = self.response_sender.send(resp).unwrap();
+            self.response_sender.send(resp).unwrap();
         }
     }
 }
@@ -0,0 +1,117 @@
+// Date: Mon Nov 20 20:37:45 2023
+// Mail: lunar_ubuntu@qq.com
+// Author: https://github.com/xiaoqixian
+
+/*
+ * APEv2 tags, found at the end of Monkey's Audio, WavPack
+ * and Musepack files, and now and then on MP3s.
+ *
+ * The tag ends with a 32 bytes footer, which may be followed
+ * by an ID3v1 tag:
+ *
+ *   "APETAGEX" | version | size | item count | flags | reserved
+ *
+ * The size covers the items and the footer, and every item is
+ *
+ *   value size | flags | key, NUL terminated | value
+ *
+ * APEv1 tags are laid out the same way, their values are
+ * taken as UTF-8 too.
+ */
+
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::Path
+};
+
+use super::{Tags, id3v1};
+
+const PREAMBLE: &[u8; 8] = b"APETAGEX";
+const FOOTER_LEN: u64 = 32;
+/// Tags larger than this are taken as broken.
+const MAX_TAG_LEN: u64 = 1 << 24;
+
+fn u32_at(bytes: &[u8], at: usize) -> Option<u32> {
+    bytes.get(at..at + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+/// Find the footer at `end` and read the items before it.
+fn read_at(file: &mut File, end: u64) -> Option<Vec<u8>> {
+    let mut footer = [0u8; FOOTER_LEN as usize];
+    file.seek(SeekFrom::Start(end.checked_sub(FOOTER_LEN)?)).ok()?;
+    file.read_exact(&mut footer).ok()?;
+    if &footer[..8] != PREAMBLE {
+        return None;
+    }
+
+    let size = u32_at(&footer, 12)? as u64;
+    if !(FOOTER_LEN..=MAX_TAG_LEN).contains(&size) {
+        return None;
+    }
+
+    let mut items = vec![0u8; (size - FOOTER_LEN) as usize];
+    file.seek(SeekFrom::Start(end.checked_sub(size)?)).ok()?;
+    file.read_exact(&mut items).ok()?;
+    Some(items)
+}
+
+/// Parse the items of a tag, binary items are skipped.
+pub fn parse_items(items: &[u8]) -> Tags {
+    let mut tags = Tags::default();
+    let mut at = 0;
+
+    while let (Some(len), Some(flags)) = (u32_at(items, at), u32_at(items, at + 4)) {
+        let key_start = at + 8;
+        let Some(key_len) = items[key_start.min(items.len())..].iter().position(|b| *b == 0) else {
+            break;
+        };
+        let value_start = key_start + key_len + 1;
+        let Some(value) = items.get(value_start..value_start + len as usize) else {
+            break;
+        };
+
+        // bits 1-2 tell the type of the value, 0 is text
+        if (flags >> 1) & 0b11 == 0 {
+            let key = String::from_utf8_lossy(&items[key_start..key_start + key_len]);
+            let value = String::from_utf8_lossy(value);
+            // a list of values is separated by NULs
+            let _ = tags.set(&key, value.split('\0').next().unwrap_or_default());
+        }
+
+        at = value_start + len as usize;
+    }
+
+    tags
+}
+
+pub fn read(path: &Path) -> Option<Tags> {
+    let mut file = File::open(path).ok()?;
+    let len = file.metadata().ok()?.len();
+
+    let items = read_at(&mut file, len)
+        .or_else(|| read_at(&mut file, len.checked_sub(id3v1::TAG_LEN)?))?;
+    Some(parse_items(&items))
+}
+
+#[test]
+fn test_parse_ape_items() {
+    fn item(key: &str, value: &[u8], flags: u32) -> Vec<u8> {
+        let mut item = (value.len() as u32).to_le_bytes().to_vec();
+        item.extend(flags.to_le_bytes());
+        item.extend(key.as_bytes());
+        item.push(0);
+        item.extend(value);
+        item
+    }
+
+    let mut items = item("Title", "寂寞难耐".as_bytes(), 0);
+    items.extend(item("Artist", b"Jonathan Lee\0Li Zongsheng", 0));
+    items.extend(item("Cover Art (Front)", &[0xff, 0xd8, 0x00], 0b10));
+    items.extend(item("Track", b"4/10", 0));
+
+    let tags = parse_items(&items);
+    assert_eq!(tags.title.as_deref(), Some("寂寞难耐"));
+    assert_eq!(tags.artist.as_deref(), Some("Jonathan Lee"));
+    assert_eq!(tags.track, Some(4));
+}
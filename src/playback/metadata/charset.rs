@@ -0,0 +1,101 @@
+// Date: Tue Nov 21 19:18:40 2023
+// Mail: lunar_ubuntu@qq.com
+// Author: https://github.com/xiaoqixian
+
+/*
+ * Legacy encodings in tags. Older Chinese MP3s keep GBK or
+ * Big5 bytes in ID3v1 tags and in ID3v2.3 frames marked as
+ * Latin-1, so every byte comes out as a character of its own.
+ *
+ * A text made only of Latin-1 characters, some of them not
+ * ASCII, is turned back into its bytes and the encoding is
+ * guessed from those. If the guess is not an east asian
+ * encoding the configured fallback is tried, and the text is
+ * kept as it is if that does not fit either.
+ */
+
+use encoding_rs::{Encoding, BIG5, EUC_JP, EUC_KR, GB18030, GBK, SHIFT_JIS, UTF_8};
+
+use chardetng::EncodingDetector;
+
+use crate::config::config;
+
+/// The encodings a guess is trusted for.
+static LEGACY: [&Encoding; 6] = [GBK, GB18030, BIG5, SHIFT_JIS, EUC_JP, EUC_KR];
+
+/// The bytes a text was decoded from as Latin-1, None if
+/// it is plain ASCII or could not have been Latin-1.
+pub fn latin1_bytes(text: &str) -> Option<Vec<u8>> {
+    if text.is_ascii() {
+        return None;
+    }
+
+    text.chars()
+        .map(|c| u8::try_from(c as u32).ok())
+        .collect()
+}
+
+/// The encoding from `[tags] fallback_encoding` in the config.
+fn fallback() -> Option<&'static Encoding> {
+    config().tags.fallback_encoding
+        .as_ref()
+        .and_then(|label| Encoding::for_label(label.as_bytes()))
+}
+
+fn decode_with(encoding: &'static Encoding, bytes: &[u8]) -> Option<String> {
+    encoding.decode_without_bom_handling_and_without_replacement(bytes)
+        .map(|text| text.into_owned())
+}
+
+/// Decode the bytes of a legacy tag, None if they
+/// are taken as Latin-1 after all.
+pub fn decode_legacy(bytes: &[u8]) -> Option<String> {
+    // UTF-8 behind a Latin-1 mark is as common
+    if let Some(text) = decode_with(UTF_8, bytes) {
+        return Some(text);
+    }
+
+    let mut detector = EncodingDetector::new();
+    detector.feed(bytes, true);
+    let guess = detector.guess(None, false);
+
+    match LEGACY.contains(&guess) {
+        true => decode_with(guess, bytes),
+        false => fallback().and_then(|encoding| decode_with(encoding, bytes))
+    }
+}
+
+/// Decode the text of a tag read as Latin-1 again, None
+/// if it is fine as it is.
+pub fn repair(text: &str) -> Option<String> {
+    let bytes = latin1_bytes(text)?;
+    decode_legacy(&bytes).filter(|fixed| fixed != text)
+}
+
+/// Decode the raw bytes of an ID3v1 field.
+pub fn decode_bytes(bytes: &[u8]) -> String {
+    match bytes.is_ascii() {
+        true => String::from_utf8_lossy(bytes).into_owned(),
+        false => decode_legacy(bytes)
+            .unwrap_or_else(|| bytes.iter().map(|b| *b as char).collect())
+    }
+}
+
+#[test]
+fn test_repair_legacy_text() {
+    fn as_latin1(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| *b as char).collect()
+    }
+
+    let title = "李宗盛 - 寂寞难耐";
+    let (gbk, _, _) = GBK.encode(title);
+    assert_eq!(repair(&as_latin1(&gbk)).as_deref(), Some(title));
+
+    let title = "周杰倫 - 晴天 葉惠美";
+    let (big5, _, _) = BIG5.encode(title);
+    assert_eq!(repair(&as_latin1(&big5)).as_deref(), Some(title));
+
+    let title = "Sigur Rós";
+    assert_eq!(repair(title), None);
+    assert_eq!(repair("寂寞难耐"), None);
+}
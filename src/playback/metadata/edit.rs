@@ -0,0 +1,255 @@
+// Date: Wed Nov 22 19:40:08 2023
+// Mail: lunar_ubuntu@qq.com
+// Author: https://github.com/xiaoqixian
+
+/*
+ * Write tags back to files, in the native format of each:
+ * ID3v2.4 for MP3 and WAV, Vorbis comments for FLAC, Ogg
+ * Vorbis and Opus, and iTunes style atoms for MP4.
+ *
+ * An edit sets or clears some fields. Only those are written,
+ * the other tags of the file are left as they are, so that a
+ * full date or a track total tmusic does not show survives
+ * an edit of the title.
+ */
+
+use std::{fs, path::Path};
+
+use id3::{Tag, TagLike, Version, ErrorKind, frame::Comment};
+
+use crate::playback::{PlayerError, cue};
+
+use super::{Tags, read_tags, parse_number, parse_year, vorbis, mp4};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Title,
+    Artist,
+    Album,
+    AlbumArtist,
+    Track,
+    Disc,
+    Year,
+    Genre,
+    Composer,
+    Comment
+}
+
+/// A field set to a value, or cleared with None.
+pub type Edit = (Field, Option<String>);
+
+impl Field {
+    pub const ALL: [Field; 10] = [
+        Field::Title,
+        Field::Artist,
+        Field::Album,
+        Field::AlbumArtist,
+        Field::Track,
+        Field::Disc,
+        Field::Year,
+        Field::Genre,
+        Field::Composer,
+        Field::Comment
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Field::Title => "title",
+            Field::Artist => "artist",
+            Field::Album => "album",
+            Field::AlbumArtist => "album-artist",
+            Field::Track => "track",
+            Field::Disc => "disc",
+            Field::Year => "year",
+            Field::Genre => "genre",
+            Field::Composer => "composer",
+            Field::Comment => "comment"
+        }
+    }
+
+    pub fn by_name(name: &str) -> Option<Field> {
+        Self::ALL.into_iter().find(|field| field.name() == name)
+    }
+
+    pub fn get(self, tags: &Tags) -> Option<String> {
+        match self {
+            Field::Title => tags.title.clone(),
+            Field::Artist => tags.artist.clone(),
+            Field::Album => tags.album.clone(),
+            Field::AlbumArtist => tags.album_artist.clone(),
+            Field::Track => tags.track.map(|n| n.to_string()),
+            Field::Disc => tags.disc.map(|n| n.to_string()),
+            Field::Year => tags.year.map(|n| n.to_string()),
+            Field::Genre => tags.genre.clone(),
+            Field::Composer => tags.composer.clone(),
+            Field::Comment => tags.comment.clone()
+        }
+    }
+
+    /// Numbers that do not parse are an error rather
+    /// than clearing the field.
+    pub fn set(self, tags: &mut Tags, value: Option<&str>) -> Result<(), PlayerError> {
+        let value = value.map(str::trim).filter(|value| !value.is_empty());
+        let text = value.map(String::from);
+        let number = |parse: fn(&str) -> Option<u32>| match value {
+            None => Ok(None),
+            Some(value) => parse(value)
+                .map(Some)
+                .ok_or_else(|| PlayerError::TagError(format!("{}: not a number: {}", self.name(), value)))
+        };
+
+        match self {
+            Field::Title => tags.title = text,
+            Field::Artist => tags.artist = text,
+            Field::Album => tags.album = text,
+            Field::AlbumArtist => tags.album_artist = text,
+            Field::Track => tags.track = number(parse_number)?,
+            Field::Disc => tags.disc = number(parse_number)?,
+            Field::Year => tags.year = number(parse_year)?,
+            Field::Genre => tags.genre = text,
+            Field::Composer => tags.composer = text,
+            Field::Comment => tags.comment = text
+        }
+        Ok(())
+    }
+}
+
+/// The tags after the edits.
+pub fn apply(tags: &Tags, edits: &[Edit]) -> Result<Tags, PlayerError> {
+    let mut tags = tags.clone();
+    for (field, value) in edits {
+        field.set(&mut tags, value.as_deref())?;
+    }
+    Ok(tags)
+}
+
+/// Replace a file by writing a copy aside first.
+pub(super) fn replace_file(path: &Path, content: &[u8]) -> Result<(), PlayerError> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+
+    fs::write(&tmp, content)
+        .and_then(|_| fs::rename(&tmp, path))
+        .map_err(PlayerError::IOError)
+}
+
+fn id3_error(e: id3::Error) -> PlayerError {
+    PlayerError::TagError(e.to_string())
+}
+
+/// The id3 crate finds where the tag goes in WAV files too.
+/// A tag is written back in its own version, v2.2 tags are
+/// upgraded as the crate cannot write them.
+fn write_id3(path: &Path, tags: &Tags, fields: &[Field]) -> Result<(), PlayerError> {
+    let mut tag = match Tag::read_from_path(path) {
+        Ok(tag) => tag,
+        Err(e) if matches!(e.kind, ErrorKind::NoTag) => Tag::new(),
+        Err(e) => return Err(id3_error(e))
+    };
+    let version = match tag.version() {
+        Version::Id3v22 => Version::Id3v24,
+        version => version
+    };
+
+    for field in fields {
+        let id = match field {
+            Field::Title => "TIT2",
+            Field::Artist => "TPE1",
+            Field::Album => "TALB",
+            Field::AlbumArtist => "TPE2",
+            Field::Genre => "TCON",
+            Field::Composer => "TCOM",
+            // the year of v2.3 has become the recording time
+            Field::Year => {
+                let _ = tag.remove("TYER");
+                let _ = tag.remove("TDRC");
+                match version {
+                    Version::Id3v23 => "TYER",
+                    _ => "TDRC"
+                }
+            },
+            Field::Track => {
+                match tags.track {
+                    Some(track) => tag.set_track(track),
+                    None => tag.remove_track()
+                }
+                continue;
+            },
+            Field::Disc => {
+                match tags.disc {
+                    Some(disc) => tag.set_disc(disc),
+                    None => tag.remove_disc()
+                }
+                continue;
+            },
+            Field::Comment => {
+                tag.remove_comment(Some(""), None);
+                if let Some(ref comment) = tags.comment {
+                    tag.add_frame(Comment {
+                        lang: String::from("eng"),
+                        description: String::new(),
+                        text: comment.clone()
+                    });
+                }
+                continue;
+            }
+        };
+
+        match field.get(tags) {
+            Some(value) => tag.set_text(id, value),
+            None => {
+                let _ = tag.remove(id);
+            }
+        }
+    }
+
+    tag.write_to_path(path, version).map_err(id3_error)
+}
+
+/// Write the `fields` of `tags` into a file.
+pub fn write(path: &Path, tags: &Tags, fields: &[Field]) -> Result<(), PlayerError> {
+    let ext = path.extension()
+        .map(|ext| ext.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+
+    match ext.as_str() {
+        "mp3" | "mp2" | "aac" | "wav" => write_id3(path, tags, fields),
+        "flac" => vorbis::write_flac(path, tags, fields),
+        "ogg" | "oga" | "opus" => vorbis::write_ogg(path, tags, fields),
+        "m4a" | "m4b" | "mp4" => mp4::write(path, tags, fields),
+        _ => Err(PlayerError::WrongFileType(path.to_string_lossy().into_owned()))
+    }
+}
+
+/// Apply the edits to the tags of a file. Tracks of CUE
+/// sheets are edited in the sheet, not here.
+pub fn write_tags(path: &Path, edits: &[Edit]) -> Result<Tags, PlayerError> {
+    let entry = path.to_string_lossy();
+    if cue::split_entry(&entry).is_some() {
+        return Err(PlayerError::WrongFileType(entry.into_owned()));
+    }
+
+    let tags = apply(&read_tags(path), edits)?;
+    let fields = edits.iter().map(|(field, _)| *field).collect::<Vec<Field>>();
+    write(path, &tags, &fields)?;
+    Ok(tags)
+}
+
+#[test]
+fn test_edit_keeps_other_tags() {
+    let path = std::env::temp_dir().join(format!("tmusic_edit_{}.mp3", std::process::id()));
+    fs::write(&path, b"").unwrap();
+    let mut tag = Tag::new();
+    tag.set_title("old");
+    tag.set_text("TDRC", "2019-05-01");
+    tag.set_text("TRCK", "3/12");
+    tag.write_to_path(&path, Version::Id3v24).unwrap();
+
+    write_tags(&path, &[(Field::Title, Some(String::from("寂寞难耐")))]).unwrap();
+    let tag = Tag::read_from_path(&path).unwrap();
+    assert_eq!(tag.title(), Some("寂寞难耐"));
+    assert_eq!(tag.get("TDRC").and_then(|frame| frame.content().text()), Some("2019-05-01"));
+    assert_eq!((tag.track(), tag.total_tracks()), (Some(3), Some(12)));
+
+    let _ = fs::remove_file(&path);
+}
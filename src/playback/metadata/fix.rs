@@ -0,0 +1,128 @@
+// Date: Tue Nov 21 21:15:06 2023
+// Mail: lunar_ubuntu@qq.com
+// Author: https://github.com/xiaoqixian
+
+/*
+ * Rewrite the ID3 tags of a file in legacy encodings as
+ * ID3v2.4, whose text frames are UTF-8, so other players
+ * read them right too. The fields of an ID3v1 tag missing
+ * in the ID3v2 tag are carried over, the ID3v1 tag itself
+ * is left alone.
+ */
+
+use std::path::Path;
+
+use id3::{Tag, TagLike, Frame, Content, Version, ErrorKind};
+
+use crate::playback::PlayerError;
+
+use super::{charset::repair, id3v1};
+
+/// A frame rewritten, or carried over from ID3v1.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TagFix {
+    pub frame: String,
+    /// None if the frame is new
+    pub from: Option<String>,
+    pub to: String
+}
+
+fn read_id3(path: &Path) -> Result<Tag, PlayerError> {
+    match Tag::read_from_path(path) {
+        Ok(tag) => Ok(tag),
+        Err(e) if matches!(e.kind, ErrorKind::NoTag) => Ok(Tag::new()),
+        Err(e) => Err(PlayerError::TagError(e.to_string()))
+    }
+}
+
+/// Collect what the fix would change in a tag, and change it.
+fn fix(tag: &mut Tag, path: &Path) -> Vec<TagFix> {
+    let mut fixes = Vec::new();
+
+    for frame in tag.frames().cloned().collect::<Vec<Frame>>() {
+        match frame.content() {
+            Content::Text(text) => if let Some(fixed) = repair(text) {
+                tag.set_text(frame.id(), fixed.clone());
+                fixes.push(TagFix {
+                    frame: String::from(frame.id()),
+                    from: Some(text.clone()),
+                    to: fixed
+                });
+            },
+            Content::Comment(comment) => if let Some(fixed) = repair(&comment.text) {
+                let mut comment = comment.clone();
+                let from = std::mem::replace(&mut comment.text, fixed.clone());
+                tag.add_frame(comment);
+                fixes.push(TagFix {
+                    frame: String::from(frame.id()),
+                    from: Some(from),
+                    to: fixed
+                });
+            },
+            _ => {}
+        }
+    }
+
+    let Some(v1) = id3v1::read(path) else {
+        return fixes;
+    };
+
+    let fields = [
+        ("TIT2", v1.title),
+        ("TPE1", v1.artist),
+        ("TALB", v1.album),
+        ("TDRC", v1.year.map(|year| year.to_string())),
+        ("TRCK", v1.track.map(|track| track.to_string())),
+        ("TCON", v1.genre)
+    ];
+    for (id, value) in fields {
+        if let (None, Some(value)) = (tag.get(id), value) {
+            tag.set_text(id, value.clone());
+            fixes.push(TagFix {
+                frame: String::from(id),
+                from: None,
+                to: value
+            });
+        }
+    }
+
+    fixes
+}
+
+/// Fix the tags of a file, or only tell what would be
+/// fixed if `write` is false. Files without anything
+/// to fix are not touched.
+pub fn fix_tags(path: &Path, write: bool) -> Result<Vec<TagFix>, PlayerError> {
+    let mut tag = read_id3(path)?;
+    let fixes = fix(&mut tag, path);
+
+    if write && !fixes.is_empty() {
+        tag.write_to_path(path, Version::Id3v24)
+            .map_err(|e| PlayerError::TagError(e.to_string()))?;
+    }
+    Ok(fixes)
+}
+
+#[test]
+fn test_fix_legacy_tags() {
+    use encoding_rs::GBK;
+
+    let path = std::env::temp_dir().join("tmusic_test_fix_tags.mp3");
+    std::fs::write(&path, [0xff, 0xfb, 0x90, 0x00]).unwrap();
+    let (title, _, _) = GBK.encode("寂寞难耐 李宗盛");
+    let mut tag = Tag::new();
+    tag.set_title(title.iter().map(|b| *b as char).collect::<String>());
+    tag.set_album("Fishmans");
+    tag.write_to_path(&path, Version::Id3v23).unwrap();
+
+    let fixes = fix_tags(&path, true).unwrap();
+    assert_eq!(fixes.len(), 1);
+    assert_eq!(fixes[0].to, "寂寞难耐 李宗盛");
+
+    let tag = Tag::read_from_path(&path).unwrap();
+    assert_eq!(tag.title(), Some("寂寞难耐 李宗盛"));
+    assert_eq!(tag.version(), Version::Id3v24);
+    assert!(fix_tags(&path, false).unwrap().is_empty());
+
+    let _ = std::fs::remove_file(&path);
+}
@@ -0,0 +1,204 @@
+// Date: Fri Nov 24 19:33:52 2023
+// Mail: lunar_ubuntu@qq.com
+// Author: https://github.com/xiaoqixian
+
+/*
+ * Guess the tags of a song from its path, for the files
+ * without any. A pattern is matched against the end of the
+ * path, a component for each '/' in it, and the last one
+ * against the file name without its extension:
+ *
+ *   {artist}/{album}/{track} - {title}
+ *   {title}-{artist}
+ *
+ * The fields are named as in edit.rs, `{_}` matches anything
+ * and is thrown away. Paths under a library root are only
+ * matched below the root, so ~/Music is never an artist.
+ *
+ * The patterns come from `[guess] patterns` in the config,
+ * and are tried in order until one matches.
+ */
+
+use std::{
+    path::{Path, Component},
+    sync::OnceLock
+};
+
+use crate::config::config;
+
+use super::{Tags, edit::Field};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Literal(String),
+    /// None for `{_}`
+    Field(Option<Field>)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pattern {
+    components: Vec<Vec<Token>>
+}
+
+impl Pattern {
+    /// None if a field is not known or a brace not closed.
+    pub fn parse(pattern: &str) -> Option<Self> {
+        let mut components = Vec::new();
+
+        for component in pattern.split('/') {
+            let mut tokens = Vec::new();
+            let mut rest = component;
+
+            while !rest.is_empty() {
+                match rest.find('{') {
+                    Some(0) => {
+                        let end = rest.find('}')?;
+                        let field = match &rest[1..end] {
+                            "_" => None,
+                            name => Some(Field::by_name(name)?)
+                        };
+                        tokens.push(Token::Field(field));
+                        rest = &rest[end + 1..];
+                    },
+                    Some(start) => {
+                        tokens.push(Token::Literal(String::from(&rest[..start])));
+                        rest = &rest[start..];
+                    },
+                    None => {
+                        tokens.push(Token::Literal(String::from(rest)));
+                        rest = "";
+                    }
+                }
+            }
+            components.push(tokens);
+        }
+
+        Some(Self { components })
+    }
+
+    /// The tags a path tells by the pattern, None if it does not match.
+    pub fn matches(&self, components: &[String]) -> Option<Tags> {
+        let skip = components.len().checked_sub(self.components.len())?;
+        let mut values = Vec::new();
+
+        for (tokens, text) in self.components.iter().zip(&components[skip..]) {
+            if !match_tokens(tokens, text, &mut values) {
+                return None;
+            }
+        }
+
+        let mut tags = Tags::default();
+        for (field, value) in values {
+            field.set(&mut tags, Some(&value)).ok()?;
+        }
+        Some(tags)
+    }
+}
+
+#[inline]
+fn is_number(field: Option<Field>) -> bool {
+    matches!(field, Some(Field::Track | Field::Disc | Field::Year))
+}
+
+/// Match the tokens of a component, the fields take as little
+/// as they can, and numbers take only digits.
+fn match_tokens(tokens: &[Token], text: &str, values: &mut Vec<(Field, String)>) -> bool {
+    let (token, rest) = match tokens.split_first() {
+        None => return text.is_empty(),
+        Some(split) => split
+    };
+
+    match token {
+        Token::Literal(literal) => text
+            .strip_prefix(literal.as_str())
+            .is_some_and(|text| match_tokens(rest, text, values)),
+        Token::Field(field) => {
+            for (end, c) in text.char_indices().skip(1).map(|(i, c)| (i, Some(c))).chain([(text.len(), None)]) {
+                let value = text[..end].trim();
+                if is_number(*field) && !value.chars().all(|c| c.is_ascii_digit()) {
+                    return false;
+                }
+                if value.is_empty() {
+                    continue;
+                }
+
+                let pushed = field.map(|field| values.push((field, String::from(value)))).is_some();
+                if match_tokens(rest, &text[end..], values) {
+                    return true;
+                }
+                if pushed {
+                    values.pop();
+                }
+                // the field may not go on past the text
+                if c.is_none() {
+                    break;
+                }
+            }
+            false
+        }
+    }
+}
+
+fn patterns() -> &'static [Pattern] {
+    static PATTERNS: OnceLock<Vec<Pattern>> = OnceLock::new();
+    PATTERNS.get_or_init(|| config().guess.patterns
+        .iter()
+        .filter_map(|pattern| Pattern::parse(pattern))
+        .collect())
+}
+
+/// The components of a path below the library root it is
+/// under, with the extension of the file cut.
+fn components_of(path: &Path) -> Vec<String> {
+    let roots = config().library.root_paths();
+    let rel = roots.iter()
+        .find_map(|root| path.strip_prefix(root).ok())
+        .unwrap_or(path);
+
+    let mut components = rel.components()
+        .filter_map(|c| match c {
+            Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+            _ => None
+        })
+        .collect::<Vec<String>>();
+
+    if let (Some(last), Some(stem)) = (components.last_mut(), path.file_stem()) {
+        *last = stem.to_string_lossy().into_owned();
+    }
+    components
+}
+
+/// Guess the tags of a file by the first pattern its path
+/// matches, or by none of them.
+pub fn guess_tags(path: &Path) -> Tags {
+    let components = components_of(path);
+    patterns()
+        .iter()
+        .find_map(|pattern| pattern.matches(&components))
+        .unwrap_or_default()
+}
+
+#[test]
+fn test_guess_by_patterns() {
+    let components = |path: &str| path.split('/').map(String::from).collect::<Vec<String>>();
+
+    let pattern = Pattern::parse("{title}-{artist}").unwrap();
+    let tags = pattern.matches(&components("下载/寂寞难耐-李宗盛")).unwrap();
+    assert_eq!(tags.title.as_deref(), Some("寂寞难耐"));
+    assert_eq!(tags.artist.as_deref(), Some("李宗盛"));
+
+    let pattern = Pattern::parse("{artist}/{album}/{track} - {title}").unwrap();
+    let tags = pattern.matches(&components("Fishmans/空中キャンプ/03 - ナイトクルージング")).unwrap();
+    assert_eq!(tags.artist.as_deref(), Some("Fishmans"));
+    assert_eq!(tags.album.as_deref(), Some("空中キャンプ"));
+    assert_eq!(tags.track, Some(3));
+    assert_eq!(tags.title.as_deref(), Some("ナイトクルージング"));
+
+    // the title may hold the separator, the track may not
+    let tags = pattern.matches(&components("a/b/1 - Long - Season")).unwrap();
+    assert_eq!(tags.title.as_deref(), Some("Long - Season"));
+    assert_eq!(pattern.matches(&components("a/b/Intro - Season")), None);
+    assert_eq!(pattern.matches(&components("b/01 - Season")), None);
+
+    assert_eq!(Pattern::parse("{artist}/{nope}"), None);
+}
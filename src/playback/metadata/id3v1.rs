@@ -0,0 +1,81 @@
+// Date: Tue Nov 21 20:02:51 2023
+// Mail: lunar_ubuntu@qq.com
+// Author: https://github.com/xiaoqixian
+
+/*
+ * ID3v1 tags, the last 128 bytes of a file:
+ *
+ *   "TAG" | title 30 | artist 30 | album 30 | year 4 | comment 30 | genre 1
+ *
+ * In ID3v1.1 the comment is cut to 28 bytes, followed by a
+ * zero and the track number. The fields have no encoding of
+ * their own, they are decoded as legacy text, see charset.rs
+ */
+
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::Path
+};
+
+use super::{Tags, charset::decode_bytes};
+
+pub const TAG_LEN: u64 = 128;
+
+static GENRES: [&str; 80] = [
+    "Blues", "Classic Rock", "Country", "Dance", "Disco", "Funk", "Grunge", "Hip-Hop",
+    "Jazz", "Metal", "New Age", "Oldies", "Other", "Pop", "R&B", "Rap", "Reggae", "Rock",
+    "Techno", "Industrial", "Alternative", "Ska", "Death Metal", "Pranks", "Soundtrack",
+    "Euro-Techno", "Ambient", "Trip-Hop", "Vocal", "Jazz+Funk", "Fusion", "Trance",
+    "Classical", "Instrumental", "Acid", "House", "Game", "Sound Clip", "Gospel", "Noise",
+    "AlternRock", "Bass", "Soul", "Punk", "Space", "Meditative", "Instrumental Pop",
+    "Instrumental Rock", "Ethnic", "Gothic", "Darkwave", "Techno-Industrial", "Electronic",
+    "Pop-Folk", "Eurodance", "Dream", "Southern Rock", "Comedy", "Cult", "Gangsta", "Top 40",
+    "Christian Rap", "Pop/Funk", "Jungle", "Native American", "Cabaret", "New Wave",
+    "Psychadelic", "Rave", "Showtunes", "Trailer", "Lo-Fi", "Tribal", "Acid Punk",
+    "Acid Jazz", "Polka", "Retro", "Musical", "Rock & Roll", "Hard Rock"
+];
+
+/// A field is padded with zeros or spaces.
+fn field(bytes: &[u8]) -> Option<String> {
+    let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    let text = decode_bytes(&bytes[..end]);
+    let text = text.trim();
+    match text.is_empty() {
+        true => None,
+        false => Some(String::from(text))
+    }
+}
+
+pub fn parse(tag: &[u8; TAG_LEN as usize]) -> Option<Tags> {
+    if &tag[..3] != b"TAG" {
+        return None;
+    }
+
+    // v1.1 keeps the track number at the end of the comment
+    let (comment, track) = match (tag[125], tag[126]) {
+        (0, track) if track != 0 => (&tag[97..125], Some(track as u32)),
+        _ => (&tag[97..127], None)
+    };
+
+    Some(Tags {
+        title: field(&tag[3..33]),
+        artist: field(&tag[33..63]),
+        album: field(&tag[63..93]),
+        year: field(&tag[93..97]).and_then(|year| year.parse().ok()),
+        comment: field(comment),
+        track,
+        genre: GENRES.get(tag[127] as usize).map(|genre| String::from(*genre)),
+        ..Default::default()
+    })
+}
+
+pub fn read(path: &Path) -> Option<Tags> {
+    let mut file = File::open(path).ok()?;
+    let len = file.metadata().ok()?.len();
+
+    let mut tag = [0u8; TAG_LEN as usize];
+    file.seek(SeekFrom::Start(len.checked_sub(TAG_LEN)?)).ok()?;
+    file.read_exact(&mut tag).ok()?;
+    parse(&tag)
+}
@@ -0,0 +1,330 @@
+// Date: Mon Nov 20 19:52:13 2023
+// Mail: lunar_ubuntu@qq.com
+// Author: https://github.com/xiaoqixian
+
+/*
+ * The tags of songs. ID3v2, Vorbis comments and MP4 atoms
+ * are read by symphonia along with the container, APE and
+ * ID3v1 tags are read by hand from the end of the file, and
+ * only fill in what the others left out.
+ *
+ * Text in legacy encodings is decoded again, see charset.rs
+ *
+ * Tags are written back in the native format, see edit.rs
+ *
+ * What the tags leave out is guessed from the path of the
+ * file, see guess.rs. The guesses are shown and searched,
+ * but only written to the file on request.
+ *
+ * Virtual entries of CUE sheets take what the sheet tells,
+ * and the rest from the tags of their backing files.
+ */
+
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    path::Path,
+    sync::{Mutex, OnceLock},
+    time::SystemTime
+};
+
+use serde::{Serialize, Deserialize};
+
+use symphonia::core::{
+    io::MediaSourceStream,
+    probe::Hint,
+    formats::{FormatOptions, FormatReader},
+    meta::{MetadataOptions, MetadataRevision, StandardTagKey}
+};
+
+use super::{cue, display_name};
+
+mod ape;
+mod id3v1;
+mod vorbis;
+mod mp4;
+pub mod fix;
+pub mod charset;
+pub mod edit;
+pub mod guess;
+
+/// The tags of a song, every tag may be missing.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Tags {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub album_artist: Option<String>,
+    pub genre: Option<String>,
+    pub year: Option<u32>,
+    pub track: Option<u32>,
+    pub disc: Option<u32>,
+    pub composer: Option<String>,
+    pub comment: Option<String>
+}
+
+/// A song in the queue along with its tags,
+/// what `Playback::get_song` returns.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrackInfo {
+    /// the entry in the queue
+    pub path: String,
+    pub tags: Tags
+}
+
+impl Tags {
+    /// Take the tags of `other` that are missing here.
+    pub fn fill(&mut self, other: Tags) {
+        fn or<T>(field: &mut Option<T>, other: Option<T>) {
+            if field.is_none() {
+                *field = other;
+            }
+        }
+
+        or(&mut self.title, other.title);
+        or(&mut self.artist, other.artist);
+        or(&mut self.album, other.album);
+        or(&mut self.album_artist, other.album_artist);
+        or(&mut self.genre, other.genre);
+        or(&mut self.year, other.year);
+        or(&mut self.track, other.track);
+        or(&mut self.disc, other.disc);
+        or(&mut self.composer, other.composer);
+        or(&mut self.comment, other.comment);
+    }
+
+    /// Set a tag by its name, the names are the ones common
+    /// to Vorbis comments and APE tags, case insensitive.
+    /// Returns false if the name is not known.
+    pub fn set(&mut self, name: &str, value: &str) -> bool {
+        let value = value.trim();
+        let text = match value.is_empty() {
+            true => return true,
+            false => Some(String::from(value))
+        };
+
+        match name.to_ascii_lowercase().as_str() {
+            "title" => self.title = text,
+            "artist" => self.artist = text,
+            "album" => self.album = text,
+            "album artist" | "albumartist" => self.album_artist = text,
+            "genre" => self.genre = text,
+            "year" | "date" => self.year = self.year.or(parse_year(value)),
+            "track" | "tracknumber" => self.track = parse_number(value),
+            "disc" | "discnumber" => self.disc = parse_number(value),
+            "composer" => self.composer = text,
+            "comment" | "description" => self.comment = text,
+            _ => return false
+        }
+        true
+    }
+}
+
+impl TrackInfo {
+    /// The title, the name of the entry if it has none.
+    pub fn title(&self) -> String {
+        self.tags.title.clone().unwrap_or_else(|| display_name(&self.path))
+    }
+
+    /// "artist - title", or just the title.
+    pub fn name(&self) -> String {
+        match self.tags.artist {
+            None => self.title(),
+            Some(ref artist) => format!("{} - {}", artist, self.title())
+        }
+    }
+}
+
+/// "3/12" is track 3 of 12.
+pub fn parse_number(value: &str) -> Option<u32> {
+    value.split('/').next()?.trim().parse().ok()
+}
+
+/// Dates come as "1995", "1995-08-25" and such.
+pub fn parse_year(value: &str) -> Option<u32> {
+    value.trim().get(..4)?.parse().ok()
+}
+
+/// Take the tags of a metadata revision of symphonia.
+pub fn read_revision(revision: &MetadataRevision, tags: &mut Tags) {
+    for tag in revision.tags() {
+        let value = tag.value.to_string();
+        let value = charset::repair(&value).unwrap_or(value);
+        let value = value.trim();
+        if value.is_empty() {
+            continue;
+        }
+
+        let text = Some(String::from(value));
+        match tag.std_key {
+            Some(StandardTagKey::TrackTitle) => tags.title = text,
+            Some(StandardTagKey::Artist) => tags.artist = text,
+            Some(StandardTagKey::Album) => tags.album = text,
+            Some(StandardTagKey::AlbumArtist) => tags.album_artist = text,
+            Some(StandardTagKey::Genre) => tags.genre = text,
+            Some(StandardTagKey::Date) | Some(StandardTagKey::OriginalDate) if tags.year.is_none() =>
+                tags.year = parse_year(value),
+            Some(StandardTagKey::TrackNumber) => tags.track = parse_number(value),
+            Some(StandardTagKey::DiscNumber) => tags.disc = parse_number(value),
+            Some(StandardTagKey::Composer) => tags.composer = text,
+            Some(StandardTagKey::Comment) | Some(StandardTagKey::Description) if tags.comment.is_none() =>
+                tags.comment = text,
+            _ => {}
+        }
+    }
+}
+
+/// The tags at the end of a file, APE tags go before ID3v1.
+pub fn read_trailing(path: &Path) -> Tags {
+    let mut tags = ape::read(path).unwrap_or_default();
+    if let Some(v1) = id3v1::read(path) {
+        tags.fill(v1);
+    }
+    tags
+}
+
+/// Probe a file with symphonia, nothing is decoded. Returns
+/// the tags of the file along with the container, for its
+/// codec parameters. None if it is not audio.
+pub fn probe(path: &Path) -> Option<(Tags, Box<dyn FormatReader>)> {
+    let file = File::open(path).ok()?;
+    let stream = MediaSourceStream::new(Box::new(file), Default::default());
+
+    let mut hint = Hint::new();
+    if let Some(ext) = path.extension().and_then(|ext| ext.to_str()) {
+        hint.with_extension(ext);
+    }
+
+    let mut probed = symphonia::default::get_probe()
+        .format(&hint, stream, &FormatOptions::default(), &MetadataOptions::default())
+        .ok()?;
+
+    // tags in front of the container, like ID3v2, come
+    // with the probe, the rest come with the container
+    let mut tags = Tags::default();
+    if let Some(revision) = probed.metadata.get().as_ref().and_then(|m| m.current()) {
+        read_revision(revision, &mut tags);
+    }
+    if let Some(revision) = probed.format.metadata().current() {
+        read_revision(revision, &mut tags);
+    }
+
+    tags.fill(read_trailing(path));
+    Some((tags, probed.format))
+}
+
+/// Read the tags of a file, missing tags are left None.
+pub fn read_tags(path: &Path) -> Tags {
+    match probe(path) {
+        Some((tags, _)) => tags,
+        None => read_trailing(path)
+    }
+}
+
+/// The tags of a virtual entry, the sheet goes first.
+fn cue_tags(entry: &str) -> Option<Tags> {
+    let (sheet_path, number) = cue::split_entry(entry)?;
+    let sheet = cue::load(sheet_path).ok()?;
+    let track = sheet.track(number)?;
+
+    let mut tags = Tags {
+        title: track.title.clone(),
+        artist: sheet.performer_of(track).map(String::from),
+        album: sheet.title.clone(),
+        album_artist: sheet.performer.clone(),
+        track: Some(number),
+        ..Default::default()
+    };
+
+    // the title and the number of the backing
+    // file are of the whole album, if any
+    tags.fill(Tags {
+        title: None,
+        track: None,
+        ..read_tags(&track.file)
+    });
+    Some(tags)
+}
+
+/// When the files of an entry were modified: the audio file,
+/// and the sheet of a virtual entry.
+type Stamp = (Option<SystemTime>, Option<SystemTime>);
+
+/// The tags read, with the stamp of their entries.
+type TagCache = HashMap<String, (Stamp, Tags)>;
+
+fn stamp(entry: &str) -> Stamp {
+    let modified = |path: &Path| fs::metadata(path).and_then(|meta| meta.modified()).ok();
+    match cue::split_entry(entry) {
+        None => (modified(Path::new(entry)), None),
+        Some((sheet, _)) => (
+            cue::resolve(entry).and_then(|track| modified(&track.file)),
+            modified(Path::new(sheet))
+        )
+    }
+}
+
+fn cache() -> &'static Mutex<TagCache> {
+    static CACHE: OnceLock<Mutex<TagCache>> = OnceLock::new();
+    CACHE.get_or_init(Default::default)
+}
+
+/// The tags of an entry if they were read before, nothing
+/// is read here so the views can ask for every entry.
+pub fn cached_info(entry: &str) -> Option<TrackInfo> {
+    cache().lock().unwrap().get(entry).map(|(_, tags)| TrackInfo {
+        path: String::from(entry),
+        tags: tags.clone()
+    })
+}
+
+/// The tags of an entry in the queue. They are read once
+/// and kept until the file changes, as the ui asks for the
+/// current song every round.
+pub fn track_info(entry: &str) -> TrackInfo {
+    let stamp = stamp(entry);
+    let cached = match cache().lock().unwrap().get(entry) {
+        Some((at, tags)) if *at == stamp => Some(tags.clone()),
+        _ => None
+    };
+
+    // read with the cache unlocked, a slow file does not
+    // hold up the views asking for the other entries
+    let tags = cached.unwrap_or_else(|| {
+        let tags = cue_tags(entry).unwrap_or_else(|| {
+            let mut tags = read_tags(Path::new(entry));
+            tags.fill(guess::guess_tags(Path::new(entry)));
+            tags
+        });
+        cache().lock().unwrap().insert(String::from(entry), (stamp, tags.clone()));
+        tags
+    });
+
+    TrackInfo {
+        path: String::from(entry),
+        tags
+    }
+}
+
+#[test]
+fn test_fill_tags() {
+    let mut tags = Tags::default();
+    assert!(tags.set("TITLE", "寂寞难耐"));
+    assert!(tags.set("Album Artist", "李宗盛"));
+    assert!(tags.set("Track", "3/12"));
+    assert!(tags.set("Year", "1986-05-01"));
+    assert!(!tags.set("ReplayGain_Track_Gain", "-6.2 dB"));
+
+    tags.fill(Tags {
+        title: Some(String::from("Lonely")),
+        artist: Some(String::from("李宗盛")),
+        ..Default::default()
+    });
+
+    assert_eq!(tags.title.as_deref(), Some("寂寞难耐"));
+    assert_eq!(tags.artist.as_deref(), Some("李宗盛"));
+    assert_eq!((tags.track, tags.year), (Some(3), Some(1986)));
+
+    let info = TrackInfo { path: String::from("/music/a.mp3"), tags };
+    assert_eq!(info.name(), "李宗盛 - 寂寞难耐");
+}
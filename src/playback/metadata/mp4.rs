@@ -0,0 +1,289 @@
+// Date: Wed Nov 22 22:08:31 2023
+// Mail: lunar_ubuntu@qq.com
+// Author: https://github.com/xiaoqixian
+
+/*
+ * Write iTunes style tags of MP4 files, kept as items of
+ * moov/udta/meta/ilst, each item holding a data atom:
+ *
+ *   item | data | type | locale | value
+ *
+ * Text is of type 1, UTF-8. trkn and disk are of type 0,
+ * with the number and the total as big endian u16s. Only
+ * the items of the edited fields are touched, and a new
+ * track or disc number keeps the total of the old one.
+ *
+ * The moov atom is rebuilt, and when the media data comes
+ * after it, the chunk offsets in stco and co64 are shifted
+ * by how much moov grew.
+ */
+
+use std::{fs, path::Path};
+
+use crate::playback::PlayerError;
+
+use super::{Tags, edit::{Field, replace_file}};
+
+/// The handler of a meta atom holding iTunes items.
+const MDIR_HANDLER: [u8; 25] = [
+    0, 0, 0, 0, 0, 0, 0, 0, b'm', b'd', b'i', b'r', b'a', b'p', b'p', b'l',
+    0, 0, 0, 0, 0, 0, 0, 0, 0
+];
+
+#[derive(Debug, Clone, Copy)]
+struct Atom {
+    name: [u8; 4],
+    start: usize,
+    body: usize,
+    end: usize
+}
+
+fn tag_error(what: &str, path: &Path) -> PlayerError {
+    PlayerError::TagError(format!("{}: {}", what, path.display()))
+}
+
+/// The atoms laid in `data[from..to]`.
+fn children(data: &[u8], from: usize, to: usize) -> Option<Vec<Atom>> {
+    let mut atoms = Vec::new();
+    let mut at = from;
+
+    while at + 8 <= to {
+        let size = u32::from_be_bytes(data[at..at + 4].try_into().ok()?) as usize;
+        let name = data[at + 4..at + 8].try_into().ok()?;
+        let (body, end) = match size {
+            0 => (at + 8, to),
+            1 => {
+                let size = u64::from_be_bytes(data.get(at + 8..at + 16)?.try_into().ok()?) as usize;
+                (at + 16, at.checked_add(size)?)
+            },
+            size => (at + 8, at + size)
+        };
+        if end > to || end < body {
+            return None;
+        }
+
+        atoms.push(Atom { name, start: at, body, end });
+        at = end;
+    }
+    Some(atoms)
+}
+
+fn atom(name: &[u8; 4], body: &[u8]) -> Vec<u8> {
+    let mut atom = ((body.len() + 8) as u32).to_be_bytes().to_vec();
+    atom.extend(name);
+    atom.extend(body);
+    atom
+}
+
+fn item(name: &[u8; 4], kind: u32, value: &[u8]) -> Vec<u8> {
+    let mut data = kind.to_be_bytes().to_vec();
+    data.extend([0u8; 4]);
+    data.extend(value);
+    atom(name, &atom(b"data", &data))
+}
+
+/// The items a field is read from, the first one is written.
+fn names(field: Field) -> &'static [&'static [u8; 4]] {
+    match field {
+        Field::Title => &[b"\xa9nam"],
+        Field::Artist => &[b"\xa9ART"],
+        Field::Album => &[b"\xa9alb"],
+        Field::AlbumArtist => &[b"aART"],
+        Field::Track => &[b"trkn"],
+        Field::Disc => &[b"disk"],
+        Field::Year => &[b"\xa9day"],
+        Field::Genre => &[b"\xa9gen", b"gnre"],
+        Field::Composer => &[b"\xa9wrt"],
+        Field::Comment => &[b"\xa9cmt"]
+    }
+}
+
+/// The item a field is written as, None if it is cleared.
+/// `old` is the value of the item it replaces.
+fn item_of(field: Field, tags: &Tags, old: Option<&[u8]>) -> Option<Vec<u8>> {
+    let name = names(field)[0];
+    let number = |n: Option<u32>, len: usize| n.map(|n| {
+        let mut value = old
+            .filter(|old| old.len() >= 4)
+            .map_or_else(|| vec![0u8; len], <[u8]>::to_vec);
+        value[2..4].copy_from_slice(&(n as u16).to_be_bytes());
+        item(name, 0, &value)
+    });
+
+    match field {
+        Field::Track => number(tags.track, 8),
+        Field::Disc => number(tags.disc, 6),
+        _ => field.get(tags).map(|value| item(name, 1, value.as_bytes()))
+    }
+}
+
+/// The value in the data atom of an item.
+fn value_of(data: &[u8], item: Atom) -> Option<&[u8]> {
+    children(data, item.body, item.end)?
+        .into_iter()
+        .find(|a| &a.name == b"data")
+        .and_then(|a| data.get(a.body + 8..a.end))
+}
+
+/// Rebuild the body of a container, replacing its child
+/// `name` by `f` of the old one, added at the end if missing.
+fn replace_child<F>(data: &[u8], atom: Option<Atom>, skip: usize, name: &[u8; 4], f: F) -> Option<Vec<u8>>
+where F: FnOnce(Option<Atom>) -> Option<Vec<u8>>
+{
+    let (head, atoms) = match atom {
+        None => (Vec::new(), Vec::new()),
+        Some(atom) => (
+            data[atom.body..atom.body + skip].to_vec(),
+            children(data, atom.body + skip, atom.end)?
+        )
+    };
+
+    let mut body = head;
+    let mut child = None;
+    for a in atoms.iter() {
+        match &a.name == name && child.is_none() {
+            true => child = Some(*a),
+            false => body.extend(&data[a.start..a.end])
+        }
+    }
+    body.extend(f(child)?);
+    Some(body)
+}
+
+fn build_moov(data: &[u8], moov: Atom, tags: &Tags, fields: &[Field]) -> Option<Vec<u8>> {
+    let body = replace_child(data, Some(moov), 0, b"udta", |udta| {
+        let body = replace_child(data, udta, 0, b"meta", |meta| {
+            // meta is a full atom, version and flags come first
+            let mut body = replace_child(data, meta, 4, b"ilst", |ilst| {
+                let mut body = Vec::new();
+                let mut written: Vec<Field> = Vec::new();
+                for a in ilst.map_or(Some(Vec::new()), |ilst| children(data, ilst.body, ilst.end))? {
+                    // an edited field takes the place of its first item
+                    match fields.iter().find(|field| names(**field).contains(&&a.name)) {
+                        None => body.extend(&data[a.start..a.end]),
+                        Some(field) if written.contains(field) => {},
+                        Some(field) => {
+                            written.push(*field);
+                            body.extend(item_of(*field, tags, value_of(data, a)).unwrap_or_default());
+                        }
+                    }
+                }
+                for field in fields.iter().filter(|field| !written.contains(field)) {
+                    body.extend(item_of(*field, tags, None).unwrap_or_default());
+                }
+                Some(atom(b"ilst", &body))
+            })?;
+
+            if meta.is_none() {
+                let mut head = vec![0u8; 4];
+                head.extend(atom(b"hdlr", &MDIR_HANDLER));
+                body.splice(0..0, head);
+            }
+            Some(atom(b"meta", &body))
+        })?;
+        Some(atom(b"udta", &body))
+    })?;
+
+    Some(atom(b"moov", &body))
+}
+
+/// Shift the chunk offsets at or after `from` by `delta`.
+fn shift_offsets(moov: &mut [u8], from: usize, to: usize, after: u64, delta: i64) -> Option<()> {
+    for a in children(moov, from, to)? {
+        match &a.name {
+            b"trak" | b"mdia" | b"minf" | b"stbl" => shift_offsets(moov, a.body, a.end, after, delta)?,
+            b"stco" | b"co64" => {
+                let width = if &a.name == b"stco" { 4 } else { 8 };
+                let count = u32::from_be_bytes(moov.get(a.body + 4..a.body + 8)?.try_into().ok()?) as usize;
+                for i in 0..count {
+                    let at = a.body + 8 + i * width;
+                    let field = moov.get_mut(at..at + width)?;
+                    let offset = match width {
+                        4 => u32::from_be_bytes(field.try_into().ok()?) as u64,
+                        _ => u64::from_be_bytes(field.try_into().ok()?)
+                    };
+                    if offset < after {
+                        continue;
+                    }
+
+                    let offset = (offset as i64 + delta) as u64;
+                    match width {
+                        4 => field.copy_from_slice(&u32::try_from(offset).ok()?.to_be_bytes()),
+                        _ => field.copy_from_slice(&offset.to_be_bytes())
+                    }
+                }
+            },
+            _ => {}
+        }
+    }
+    Some(())
+}
+
+pub fn write(path: &Path, tags: &Tags, fields: &[Field]) -> Result<(), PlayerError> {
+    let data = fs::read(path).map_err(PlayerError::IOError)?;
+    let top = children(&data, 0, data.len()).ok_or_else(|| tag_error("broken mp4 atoms", path))?;
+    let moov = top.iter()
+        .find(|a| &a.name == b"moov")
+        .copied()
+        .ok_or_else(|| tag_error("no moov atom", path))?;
+
+    let mut new_moov = build_moov(&data, moov, tags, fields).ok_or_else(|| tag_error("broken mp4 atoms", path))?;
+    let delta = new_moov.len() as i64 - (moov.end - moov.start) as i64;
+
+    // the media after moov moves along with its end
+    if delta != 0 && top.iter().any(|a| &a.name == b"mdat" && a.start >= moov.end) {
+        let len = new_moov.len();
+        shift_offsets(&mut new_moov, 8, len, moov.end as u64, delta)
+            .ok_or_else(|| tag_error("broken chunk offsets", path))?;
+    }
+
+    let mut content = data[..moov.start].to_vec();
+    content.extend(new_moov);
+    content.extend(&data[moov.end..]);
+    replace_file(path, &content)
+}
+
+#[test]
+fn test_rebuild_moov() {
+    // moov with a track whose only chunk is in the mdat after it
+    let stco = atom(b"stco", &[0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0]);
+    let trak = atom(b"trak", &atom(b"mdia", &atom(b"minf", &atom(b"stbl", &stco))));
+    // track 3 of 12 with a full date
+    let mut ilst = item(b"\xa9day", 1, b"2019-05-01");
+    ilst.extend(item(b"trkn", 0, &[0, 0, 0, 3, 0, 12, 0, 0]));
+    let mut meta = vec![0u8; 4];
+    meta.extend(atom(b"hdlr", &MDIR_HANDLER));
+    meta.extend(atom(b"ilst", &ilst));
+    let mut body = trak.clone();
+    body.extend(atom(b"udta", &atom(b"meta", &meta)));
+    let moov_len = 8 + body.len();
+    let mut moov = atom(b"moov", &body);
+    // the chunk starts right after the mdat header
+    let offset = (8 + moov_len + 8) as u32;
+    moov[8 + trak.len() - 4..8 + trak.len()].copy_from_slice(&offset.to_be_bytes());
+
+    let mut data = atom(b"ftyp", b"M4A ");
+    data.extend(moov);
+    let moov = children(&data, 0, data.len()).unwrap()[1];
+
+    let tags = Tags {
+        title: Some(String::from("寂寞难耐")),
+        track: Some(5),
+        ..Default::default()
+    };
+    let mut new_moov = build_moov(&data, moov, &tags, &[Field::Title, Field::Track]).unwrap();
+    let delta = new_moov.len() as i64 - (moov.end - moov.start) as i64;
+    let len = new_moov.len();
+    shift_offsets(&mut new_moov, 8, len, moov.end as u64, delta).unwrap();
+
+    let udta = children(&new_moov, 8, len).unwrap().into_iter().find(|a| &a.name == b"udta").unwrap();
+    let meta = children(&new_moov, udta.body, udta.end).unwrap()[0];
+    let ilst = children(&new_moov, meta.body + 4, meta.end).unwrap()[1];
+    let items = children(&new_moov, ilst.body, ilst.end).unwrap();
+    assert_eq!(items.iter().map(|a| a.name).collect::<Vec<_>>(), vec![*b"\xa9day", *b"trkn", *b"\xa9nam"]);
+    assert_eq!(value_of(&new_moov, items[0]), Some(&b"2019-05-01"[..]));
+    assert_eq!(value_of(&new_moov, items[1]), Some(&[0, 0, 0, 5, 0, 12, 0, 0][..]));
+
+    let shifted = u32::from_be_bytes(new_moov[8 + trak.len() - 4..8 + trak.len()].try_into().unwrap());
+    assert_eq!(shifted as i64, offset as i64 + delta);
+}
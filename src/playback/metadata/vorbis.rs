@@ -0,0 +1,408 @@
+// Date: Wed Nov 22 20:26:53 2023
+// Mail: lunar_ubuntu@qq.com
+// Author: https://github.com/xiaoqixian
+
+/*
+ * Write Vorbis comments, into the VORBIS_COMMENT block of
+ * FLAC files and into the comment header of Ogg Vorbis and
+ * Opus streams.
+ *
+ * A comment block is
+ *
+ *   vendor length | vendor | count | (length | "KEY=value")...
+ *
+ * with little endian u32 lengths. Only the comments of the
+ * edited fields are touched, in place where they were, and
+ * a track or disc number keeps the total after its slash.
+ *
+ * FLAC metadata is rewritten in place when it fits in the
+ * space of the old metadata and padding, otherwise the file
+ * is rewritten with some padding to spare for next time.
+ *
+ * In Ogg the header packets are laid into pages again, and
+ * the pages after them are renumbered.
+ */
+
+use std::{
+    fs::{self, OpenOptions},
+    io::{Seek, SeekFrom, Write},
+    path::Path
+};
+
+use crate::playback::PlayerError;
+
+use super::{Tags, edit::{Field, replace_file}};
+
+const VENDOR: &str = "tmusic";
+
+const FLAC_COMMENT: u8 = 4;
+const FLAC_PADDING: u8 = 1;
+/// Padding left when the whole file is rewritten.
+const FLAC_SPARE: usize = 4096;
+
+fn tag_error(what: &str, path: &Path) -> PlayerError {
+    PlayerError::TagError(format!("{}: {}", what, path.display()))
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> Option<usize> {
+    bytes.get(at..at + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+}
+
+/// The keys a field is read from, the first one is written.
+fn keys(field: Field) -> &'static [&'static str] {
+    match field {
+        Field::Title => &["TITLE"],
+        Field::Artist => &["ARTIST"],
+        Field::Album => &["ALBUM"],
+        Field::AlbumArtist => &["ALBUMARTIST", "ALBUM ARTIST"],
+        Field::Track => &["TRACKNUMBER"],
+        Field::Disc => &["DISCNUMBER"],
+        Field::Year => &["DATE", "YEAR"],
+        Field::Genre => &["GENRE"],
+        Field::Composer => &["COMPOSER"],
+        Field::Comment => &["COMMENT", "DESCRIPTION"]
+    }
+}
+
+/// The comment a field is written as, None if it is cleared.
+/// `old` is the value it replaces, whose total is kept.
+fn comment_of(field: Field, tags: &Tags, old: Option<&[u8]>) -> Option<Vec<u8>> {
+    let value = field.get(tags)?;
+    let total = old
+        .filter(|_| matches!(field, Field::Track | Field::Disc))
+        .and_then(|old| old.iter().position(|b| *b == b'/').map(|at| &old[at..]))
+        .unwrap_or_default();
+
+    let mut comment = format!("{}={}", keys(field)[0], value).into_bytes();
+    comment.extend(total);
+    Some(comment)
+}
+
+/// Replace the comments of the edited fields, or make a new
+/// block. The other comments are kept as they are.
+pub fn update_block(block: Option<&[u8]>, tags: &Tags, fields: &[Field]) -> Vec<u8> {
+    let mut vendor = VENDOR.as_bytes().to_vec();
+    let mut comments: Vec<Vec<u8>> = Vec::new();
+    let mut written: Vec<Field> = Vec::new();
+
+    if let Some(block) = block {
+        if let Some(len) = u32_at(block, 0) {
+            vendor = block.get(4..4 + len).unwrap_or_default().to_vec();
+
+            let mut at = 8 + len;
+            let count = u32_at(block, 4 + len).unwrap_or(0);
+            for _ in 0..count {
+                let Some(comment) = u32_at(block, at).and_then(|len| block.get(at + 4..at + 4 + len)) else {
+                    break;
+                };
+                at += 4 + comment.len();
+
+                let mut parts = comment.splitn(2, |b| *b == b'=');
+                let key = String::from_utf8_lossy(parts.next().unwrap_or_default()).to_ascii_uppercase();
+                let field = fields.iter().find(|field| keys(**field).contains(&key.as_str()));
+
+                // an edited field takes the place of its first comment
+                match field {
+                    None => comments.push(comment.to_vec()),
+                    Some(field) if written.contains(field) => {},
+                    Some(field) => {
+                        written.push(*field);
+                        comments.extend(comment_of(*field, tags, parts.next()));
+                    }
+                }
+            }
+        }
+    }
+
+    for field in fields.iter().filter(|field| !written.contains(field)) {
+        comments.extend(comment_of(*field, tags, None));
+    }
+
+    let mut encoded = (vendor.len() as u32).to_le_bytes().to_vec();
+    encoded.extend(vendor);
+    encoded.extend((comments.len() as u32).to_le_bytes());
+    for comment in comments {
+        encoded.extend((comment.len() as u32).to_le_bytes());
+        encoded.extend(comment);
+    }
+    encoded
+}
+
+fn flac_block(kind: u8, last: bool, body: &[u8]) -> Vec<u8> {
+    let len = body.len() as u32;
+    let mut block = vec![kind | if last { 0x80 } else { 0 }];
+    block.extend(&len.to_be_bytes()[1..]);
+    block.extend(body);
+    block
+}
+
+pub fn write_flac(path: &Path, tags: &Tags, fields: &[Field]) -> Result<(), PlayerError> {
+    let data = fs::read(path).map_err(PlayerError::IOError)?;
+    if !data.starts_with(b"fLaC") {
+        return Err(tag_error("not a flac file", path));
+    }
+
+    let mut blocks: Vec<(u8, &[u8])> = Vec::new();
+    let mut at = 4;
+    loop {
+        let header = data.get(at..at + 4).ok_or_else(|| tag_error("broken flac metadata", path))?;
+        let len = u32::from_be_bytes([0, header[1], header[2], header[3]]) as usize;
+        let body = data.get(at + 4..at + 4 + len).ok_or_else(|| tag_error("broken flac metadata", path))?;
+        blocks.push((header[0] & 0x7f, body));
+        at += 4 + len;
+        if header[0] & 0x80 != 0 {
+            break;
+        }
+    }
+    let audio = at;
+
+    let old = blocks.iter().find(|(kind, _)| *kind == FLAC_COMMENT).map(|(_, body)| *body);
+    let comment = update_block(old, tags, fields);
+    if comment.len() > 0xff_ffff {
+        return Err(tag_error("tags too large", path));
+    }
+
+    // STREAMINFO stays first, the comments go right after it
+    let mut kept = blocks.into_iter()
+        .filter(|(kind, _)| *kind != FLAC_COMMENT && *kind != FLAC_PADDING)
+        .collect::<Vec<(u8, &[u8])>>();
+    kept.insert(1.min(kept.len()), (FLAC_COMMENT, &comment));
+
+    let encode = |padding: usize| {
+        let mut meta = Vec::new();
+        for (kind, body) in kept.iter() {
+            meta.extend(flac_block(*kind, false, body));
+        }
+        meta.extend(flac_block(FLAC_PADDING, true, &vec![0u8; padding]));
+        meta
+    };
+
+    let space = audio - 4;
+    let needed = kept.iter().map(|(_, body)| 4 + body.len()).sum::<usize>() + 4;
+
+    match needed <= space {
+        true => {
+            let meta = encode(space - needed);
+            let mut file = OpenOptions::new().write(true).open(path).map_err(PlayerError::IOError)?;
+            file.seek(SeekFrom::Start(4))
+                .and_then(|_| file.write_all(&meta))
+                .map_err(PlayerError::IOError)
+        },
+        false => {
+            let mut content = b"fLaC".to_vec();
+            content.extend(encode(FLAC_SPARE));
+            content.extend(&data[audio..]);
+            replace_file(path, &content)
+        }
+    }
+}
+
+/// Ogg pages carry a CRC-32 with polynomial 0x04c11db7,
+/// unreflected and starting from zero.
+fn ogg_crc(bytes: &[u8]) -> u32 {
+    let mut table = [0u32; 256];
+    for (i, entry) in table.iter_mut().enumerate() {
+        let mut r = (i as u32) << 24;
+        for _ in 0..8 {
+            r = match r & 0x8000_0000 {
+                0 => r << 1,
+                _ => (r << 1) ^ 0x04c1_1db7
+            };
+        }
+        *entry = r;
+    }
+
+    bytes.iter().fold(0u32, |crc, b| (crc << 8) ^ table[((crc >> 24) as u8 ^ b) as usize])
+}
+
+struct Page<'a> {
+    flags: u8,
+    granule: u64,
+    serial: u32,
+    lacing: &'a [u8],
+    body: &'a [u8]
+}
+
+fn parse_pages(data: &[u8]) -> Option<Vec<Page<'_>>> {
+    let mut pages = Vec::new();
+    let mut at = 0;
+
+    while at < data.len() {
+        let header = data.get(at..at + 27)?;
+        if &header[..4] != b"OggS" {
+            return None;
+        }
+        let segments = header[26] as usize;
+        let lacing = data.get(at + 27..at + 27 + segments)?;
+        let len = lacing.iter().map(|n| *n as usize).sum::<usize>();
+        let body = data.get(at + 27 + segments..at + 27 + segments + len)?;
+
+        pages.push(Page {
+            flags: header[5],
+            granule: u64::from_le_bytes(header[6..14].try_into().ok()?),
+            serial: u32::from_le_bytes(header[14..18].try_into().ok()?),
+            lacing,
+            body
+        });
+        at += 27 + segments + len;
+    }
+    Some(pages)
+}
+
+fn encode_page(page: &Page, sequence: u32, out: &mut Vec<u8>) {
+    let start = out.len();
+    out.extend(b"OggS\0");
+    out.push(page.flags);
+    out.extend(page.granule.to_le_bytes());
+    out.extend(page.serial.to_le_bytes());
+    out.extend(sequence.to_le_bytes());
+    out.extend([0u8; 4]);
+    out.push(page.lacing.len() as u8);
+    out.extend(page.lacing);
+    out.extend(page.body);
+
+    let crc = ogg_crc(&out[start..]);
+    out[start + 22..start + 26].copy_from_slice(&crc.to_le_bytes());
+}
+
+/// Lay packets into pages, the first one begins a page
+/// and the last one ends one. Returns the lacing values
+/// and bodies of the pages, and whether a packet ends in
+/// each of them.
+fn paginate(packets: &[Vec<u8>]) -> Vec<(Vec<u8>, Vec<u8>, bool, bool)> {
+    let mut pages = Vec::new();
+    let (mut lacing, mut body) = (Vec::new(), Vec::new());
+    let (mut continued, mut ends) = (false, false);
+
+    for packet in packets {
+        let mut rest = packet.as_slice();
+        loop {
+            let len = rest.len().min(255);
+            lacing.push(len as u8);
+            body.extend(&rest[..len]);
+            rest = &rest[len..];
+
+            let done = len < 255;
+            ends |= done;
+            if lacing.len() == 255 {
+                pages.push((std::mem::take(&mut lacing), std::mem::take(&mut body), continued, ends));
+                continued = !done;
+                ends = false;
+            }
+            if done {
+                break;
+            }
+        }
+    }
+
+    if !lacing.is_empty() {
+        pages.push((lacing, body, continued, ends));
+    }
+    pages
+}
+
+pub fn write_ogg(path: &Path, tags: &Tags, fields: &[Field]) -> Result<(), PlayerError> {
+    let data = fs::read(path).map_err(PlayerError::IOError)?;
+    let pages = parse_pages(&data).ok_or_else(|| tag_error("broken ogg pages", path))?;
+    let serial = pages.first().map(|page| page.serial).unwrap_or_default();
+    if pages.iter().any(|page| page.serial != serial) {
+        return Err(tag_error("multiplexed ogg streams are not supported", path));
+    }
+
+    // gather the header packets, they end at a page boundary
+    let mut packets: Vec<Vec<u8>> = Vec::new();
+    let mut partial = Vec::new();
+    let mut needed = usize::MAX;
+    let mut header_pages = 0;
+
+    for page in pages.iter() {
+        let mut at = 0;
+        for len in page.lacing.iter().map(|n| *n as usize) {
+            partial.extend(&page.body[at..at + len]);
+            at += len;
+            if len < 255 {
+                packets.push(std::mem::take(&mut partial));
+            }
+        }
+        header_pages += 1;
+
+        if needed == usize::MAX {
+            needed = match packets.first() {
+                Some(id) if id.starts_with(b"\x01vorbis") => 3,
+                Some(id) if id.starts_with(b"OpusHead") => 2,
+                Some(_) => return Err(tag_error("not a vorbis or opus stream", path)),
+                None => continue
+            };
+        }
+        if packets.len() >= needed {
+            break;
+        }
+    }
+
+    if packets.len() != needed || !partial.is_empty() {
+        return Err(tag_error("broken ogg headers", path));
+    }
+
+    let (prefix, framing): (&[u8], &[u8]) = match needed {
+        3 => (b"\x03vorbis", &[1]),
+        _ => (b"OpusTags", &[])
+    };
+    let old = packets[1].strip_prefix(prefix).ok_or_else(|| tag_error("broken ogg headers", path))?;
+    let mut comment = prefix.to_vec();
+    comment.extend(update_block(Some(old), tags, fields));
+    comment.extend(framing);
+    packets[1] = comment;
+
+    // the identification header keeps a page of its own
+    let mut laid = paginate(&packets[..1]);
+    laid.extend(paginate(&packets[1..]));
+
+    let mut content = Vec::with_capacity(data.len());
+    let mut sequence = 0;
+    for (i, (lacing, body, continued, ends)) in laid.iter().enumerate() {
+        let page = Page {
+            flags: (if i == 0 { 0x02 } else { 0 }) | (if *continued { 0x01 } else { 0 }),
+            granule: if *ends { 0 } else { u64::MAX },
+            serial,
+            lacing,
+            body
+        };
+        encode_page(&page, sequence, &mut content);
+        sequence += 1;
+    }
+    for page in pages.iter().skip(header_pages) {
+        encode_page(page, sequence, &mut content);
+        sequence += 1;
+    }
+
+    replace_file(path, &content)
+}
+
+#[test]
+fn test_update_comment_block() {
+    let mut block = Vec::new();
+    block.extend(4u32.to_le_bytes());
+    block.extend(b"Lavf");
+    block.extend(5u32.to_le_bytes());
+    for comment in ["TITLE=old", "DATE=2019-05-01", "REPLAYGAIN_TRACK_GAIN=-6.2 dB", "tracknumber=1/12", "Description=old"] {
+        block.extend((comment.len() as u32).to_le_bytes());
+        block.extend(comment.as_bytes());
+    }
+
+    let tags = Tags {
+        title: Some(String::from("寂寞难耐")),
+        track: Some(2),
+        year: Some(2019),
+        ..Default::default()
+    };
+    let updated = update_block(Some(&block), &tags, &[Field::Title, Field::Track, Field::Composer]);
+    let text = String::from_utf8_lossy(&updated);
+
+    assert!(text.starts_with("\u{4}\0\0\0Lavf\u{5}\0\0\0"));
+    assert!(text.contains("REPLAYGAIN_TRACK_GAIN=-6.2 dB"));
+    assert!(text.contains("TITLE=寂寞难耐"));
+    assert!(text.contains("TRACKNUMBER=2/12"));
+    assert!(text.contains("DATE=2019-05-01") && text.contains("Description=old"));
+    assert!(!text.contains("TITLE=old") && !text.contains("tracknumber=1") && !text.contains("COMPOSER"));
+    assert!(text.find("TITLE").unwrap() < text.find("DATE").unwrap());
+}
@@ -2,13 +2,33 @@
 // Mail: lunar_ubuntu@qq.com
 // Author: https://github.com/xiaoqixian
 
-use std::time::Duration;
+use std::{path::Path, sync::Arc, time::Duration};
 
-use mp3_duration::MP3DurationError;
+use rodio::{
+    decoder::DecoderError,
+    source::Source
+};
 
-use rodio::decoder::DecoderError;
+use serde::{Serialize, Deserialize};
+
+use history::HistoryEntry;
+use resume::Bookmark;
+use chapters::Chapter;
+pub use metadata::TrackInfo;
+#[cfg(test)]
+pub use decode::test_wav;
 
 mod source_stream;
+mod decode;
+mod lanes;
+pub mod history;
+mod session;
+pub mod resume;
+pub mod chapters;
+pub mod cue;
+pub mod playlist;
+pub mod metadata;
+pub mod scrobble;
 mod play_queue;
 mod listener;
 mod player;
@@ -20,7 +40,153 @@ pub enum PlayerError {
     IOError(std::io::Error),
     WrongFileType(String),
     DecoderError(DecoderError),
-    MP3DurationError(MP3DurationError)
+    IndexOutOfRange(usize),
+    /// Empty, not usable as a file name, or taken.
+    InvalidPlaylistName(String),
+    /// The tags of a file could not be read or written.
+    TagError(String),
+    /// A file of a format none of the decoders takes.
+    UnsupportedFormat(String)
+}
+
+/// The play list is split into two lanes, see lanes.rs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Lane {
+    /// Songs queued by the user with "play next"
+    User,
+    /// The album or playlist the user started from
+    Context
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct QueueSnapshot {
+    pub user: Vec<String>,
+    pub context: Vec<String>
+}
+
+/// Notices broadcast by the PlayQueue to its subscribers,
+/// so that nobody needs to lock the queue to watch it.
+#[derive(Debug, Clone)]
+pub enum Notice {
+    /// Carries a snapshot of both lanes after the change.
+    QueueChanged(QueueSnapshot),
+    /// A song is finished and recorded in the history.
+    Played(HistoryEntry),
+    /// The bookmarks of the current song.
+    BookmarksChanged(Vec<Bookmark>),
+    /// The chapters of the current song, empty if it has none.
+    ChaptersChanged(Vec<Chapter>),
+    /// Entries of a playlist that cannot be played, they
+    /// are left out while the rest is loaded.
+    MissingEntries {
+        playlist: String,
+        missing: Vec<String>
+    },
+    /// A song of the queue failed to load and was skipped.
+    Unplayable {
+        entry: String,
+        error: Arc<PlayerError>
+    }
+}
+
+/// Commands issued by the ui components, they are carried
+/// out by the ui loop which owns the player.
+#[derive(Debug, Clone)]
+pub enum Command {
+    /// Play a song right away.
+    Play(String),
+    PlayNext(String),
+    Seek(Duration),
+    /// Skip the current song.
+    Next,
+    /// Go back one song in the history.
+    Prev,
+    SetPaused(bool),
+    SetRepeat(bool),
+    SetShuffle(bool),
+    SetVolume(f32),
+    AddBookmark(String),
+    RemoveBookmark(usize),
+    JumpToBookmark(usize),
+    NextChapter,
+    PrevChapter,
+    /// Play the song at `index` of a lane right away.
+    JumpTo {
+        lane: Lane,
+        index: usize
+    },
+    /// Insert a song, or the tracks of a CUE sheet,
+    /// at `index` of a lane.
+    Insert {
+        lane: Lane,
+        index: usize,
+        path: String
+    },
+    Remove {
+        lane: Lane,
+        index: usize
+    },
+    /// Move a song within its lane.
+    Move {
+        lane: Lane,
+        from: usize,
+        to: usize
+    },
+    Clear(Lane),
+    Dedupe,
+    /// Load a playlist file into the context lane,
+    /// replacing it or appended to it.
+    LoadPlaylist {
+        path: String,
+        replace: bool
+    },
+    /// Load songs into the context lane, replacing
+    /// it or appended to it.
+    LoadSongs {
+        paths: Vec<String>,
+        replace: bool
+    },
+    /// Save the current song and both lanes to a playlist file.
+    SavePlaylist(String),
+    /// A file or directory was moved, the entries
+    /// in the queue follow it.
+    Repoint {
+        from: String,
+        to: String
+    }
+}
+
+impl Command {
+    pub fn apply<P: Playback>(self, player: &mut P) -> Result<(), PlayerError> {
+        match self {
+            Command::Play(path) => player.play(path),
+            Command::PlayNext(path) => player.play_next(path),
+            Command::Seek(position) => player.seek(position),
+            Command::Next => player.go_next(),
+            Command::Prev => player.go_prev(),
+            Command::SetPaused(paused) => player.set_paused(paused),
+            Command::SetRepeat(repeat) => player.set_repeat(repeat),
+            Command::SetShuffle(shuffle) => player.set_shuffle(shuffle),
+            Command::SetVolume(volume) => player.set_volume(volume),
+            Command::AddBookmark(name) => player.add_bookmark(name),
+            Command::RemoveBookmark(index) => player.remove_bookmark(index),
+            Command::JumpToBookmark(index) => player.jump_to_bookmark(index),
+            Command::NextChapter => player.next_chapter(),
+            Command::PrevChapter => player.prev_chapter(),
+            Command::JumpTo { lane, index } => player.jump_to(lane, index),
+            Command::Insert { lane, index, path } => player.insert(lane, index, path),
+            Command::Remove { lane, index } => player.remove(lane, index).map(|_| ()),
+            Command::Move { lane, from, to } => player.move_item(lane, from, to),
+            Command::Clear(lane) => player.clear(lane),
+            Command::Dedupe => player.dedupe(),
+            Command::LoadPlaylist { path, replace: true } => player.set_context(vec![path]),
+            Command::LoadPlaylist { path, replace: false } => player.append_list(path),
+            Command::LoadSongs { paths, replace: true } => player.set_context(paths),
+            Command::LoadSongs { paths, replace: false } => player.append_many(paths),
+            Command::SavePlaylist(path) => player.save_playlist(path),
+            Command::Repoint { from, to } => player.repoint(from, to)
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -41,12 +207,131 @@ enum ResponseType {
     TotalDuration(Option<Duration>)
 }
 
-pub trait Playback {
-    type ListContainer;
-    type ListHandle;
+/// Whether an entry of the queue can be played, either a
+/// regular file or a track of a CUE sheet.
+pub fn playable(entry: &str) -> bool {
+    match cue::split_entry(entry) {
+        None => Path::new(entry).is_file(),
+        Some(_) => cue::resolve(entry).is_some_and(|track| track.file.is_file())
+    }
+}
+
+/// Whether `expand` takes a file, a song, a CUE sheet or
+/// a playlist. Songs and playlists are told by their
+/// extensions, a sheet is parsed for its backing files.
+pub fn appendable(path: &Path) -> bool {
+    let entry = path.to_string_lossy();
+    match cue::is_cue(&entry) {
+        true => cue::expand(&entry).is_ok(),
+        false => (crate::library::is_audio(path) || playlist::is_playlist(&entry)) && playable(&entry)
+    }
+}
 
+/// What a path given by the user expands into.
+#[derive(Debug, Default)]
+pub struct Expanded {
+    pub entries: Vec<String>,
+    /// entries of a playlist that cannot be played
+    pub missing: Vec<String>
+}
+
+/// Expand a path given by the user into queue entries,
+/// a CUE sheet expands into all of its tracks, and a playlist
+/// into its entries. Missing entries of a playlist are not
+/// fatal, they are left out and reported.
+pub fn expand(path: String) -> Result<Expanded, PlayerError> {
+    if cue::is_cue(&path) {
+        return Ok(Expanded {
+            entries: cue::expand(&path)?,
+            missing: Vec::new()
+        });
+    }
+
+    if !playlist::is_playlist(&path) {
+        return match crate::library::is_audio(Path::new(&path)) && playable(&path) {
+            true => Ok(Expanded { entries: vec![path], missing: Vec::new() }),
+            false => Err(PlayerError::WrongFileType(path))
+        };
+    }
+
+    let list = playlist::Playlist::load(&path)?;
+    list.keep_hints();
+
+    let mut expanded = Expanded::default();
+    for entry in list.entries {
+        match cue::is_cue(&entry.path) {
+            true => match cue::expand(&entry.path) {
+                Ok(tracks) => expanded.entries.extend(tracks),
+                Err(_) => expanded.missing.push(entry.path)
+            },
+            false if playable(&entry.path) => expanded.entries.push(entry.path),
+            false => expanded.missing.push(entry.path)
+        }
+    }
+
+    Ok(expanded)
+}
+
+/// The entry after `from` is moved to `to`, None if the move
+/// does not concern it. A directory move concerns everything
+/// under it, and a sheet move concerns all its tracks.
+pub fn repointed(entry: &str, from: &str, to: &str) -> Option<String> {
+    if entry == from {
+        return Some(String::from(to));
+    }
+
+    let rest = entry.strip_prefix(from)?;
+    match rest.starts_with('/') || (rest.starts_with('#') && cue::is_cue(from)) {
+        true => Some(format!("{}{}", to, rest)),
+        false => None
+    }
+}
+
+/// The name an entry is shown by: the tags if they were read,
+/// the title a playlist gave it, what its path tells, or at
+/// last the file name.
+pub fn display_name(entry: &str) -> String {
+    cue::display_name(entry)
+        .or_else(|| metadata::cached_info(entry)
+            .filter(|info| info.tags.title.is_some())
+            .map(|info| info.name()))
+        .or_else(|| playlist::hint(entry).and_then(|hint| hint.title))
+        .or_else(|| {
+            let guessed = TrackInfo {
+                path: String::from(entry),
+                tags: metadata::guess::guess_tags(Path::new(entry))
+            };
+            guessed.tags.title.is_some().then(|| guessed.name())
+        })
+        .unwrap_or_else(|| Path::new(entry)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or(entry.to_string()))
+}
+
+pub trait Playback {
     fn append_list(&mut self, path: String) -> Result<(), PlayerError>;
 
+    fn append_many(&mut self, paths: Vec<String>) -> Result<(), PlayerError>;
+
+    fn set_context(&mut self, paths: Vec<String>) -> Result<(), PlayerError>;
+
+    fn insert(&mut self, lane: Lane, index: usize, path: String) -> Result<(), PlayerError>;
+
+    fn remove(&mut self, lane: Lane, index: usize) -> Result<String, PlayerError>;
+
+    fn move_item(&mut self, lane: Lane, from: usize, to: usize) -> Result<(), PlayerError>;
+
+    fn clear(&mut self, lane: Lane) -> Result<(), PlayerError>;
+
+    fn dedupe(&mut self) -> Result<(), PlayerError>;
+
+    /// Make the entries under `from` point to `to`,
+    /// after the files were moved there.
+    fn repoint(&mut self, from: String, to: String) -> Result<(), PlayerError>;
+
+    fn jump_to(&mut self, lane: Lane, index: usize) -> Result<(), PlayerError>;
+
     fn go_next(&mut self) -> Result<(), PlayerError>;
 
     fn go_prev(&mut self) -> Result<(), PlayerError>;
@@ -57,13 +342,57 @@ pub trait Playback {
 
     fn set_paused(&mut self, paused: bool) -> Result<(), PlayerError>;
 
+    fn set_repeat(&mut self, repeat: bool) -> Result<(), PlayerError>;
+
+    fn set_shuffle(&mut self, shuffle: bool) -> Result<(), PlayerError>;
+
+    /// 1.0 is the original volume
+    fn set_volume(&mut self, volume: f32) -> Result<(), PlayerError>;
+
+    fn volume(&self) -> f32;
+
+    fn paused(&self) -> bool;
+
+    fn repeat(&self) -> bool;
+
+    fn shuffle(&self) -> bool;
+
+    fn seek(&mut self, position: Duration) -> Result<(), PlayerError>;
+
+    /// Bookmark the current position of the current song.
+    fn add_bookmark(&mut self, name: String) -> Result<(), PlayerError>;
+
+    fn remove_bookmark(&mut self, index: usize) -> Result<(), PlayerError>;
+
+    fn jump_to_bookmark(&mut self, index: usize) -> Result<(), PlayerError>;
+
+    fn bookmarks(&self) -> Vec<Bookmark>;
+
+    fn next_chapter(&mut self) -> Result<(), PlayerError>;
+
+    fn prev_chapter(&mut self) -> Result<(), PlayerError>;
+
+    fn chapters(&self) -> Vec<Chapter>;
+
     fn total_duration(&self) -> Option<Duration>;
 
     fn progress(&self) -> Option<Duration>;
 
-    fn get_song(&self) -> Option<String>;
+    /// The current song and its tags.
+    fn get_song(&self) -> Option<TrackInfo>;
+
+    fn get_playlist(&self) -> QueueSnapshot;
+
+    /// Save the current song and both lanes to a playlist file,
+    /// the format is chosen by the extension.
+    fn save_playlist(&self, path: String) -> Result<(), PlayerError>;
+
+    fn get_history(&self) -> Vec<HistoryEntry>;
+
+    fn subscribe(&self) -> kanal::Receiver<Notice>;
+}
 
-    fn get_playlist(&self) -> Self::ListHandle
-        where Self::ListContainer: IntoIterator,
-              <<Self as Playback>::ListContainer as IntoIterator>::Item: Into<String>;
+/// Create a player that decodes songs into i16 samples.
+pub fn player() -> impl Playback {
+    player::Player::<Box<dyn Source<Item = i16> + Send>>::new()
 }
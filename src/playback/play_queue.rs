@@ -3,10 +3,9 @@
 // Author: https://github.com/xiaoqixian
 
 use std::{
-    collections::VecDeque,
     time::Duration,
-    sync::{Mutex, Arc},
-    sync::atomic::{AtomicBool, AtomicU16, AtomicU32, Ordering},
+    sync::{Arc, Mutex},
+    sync::atomic::{AtomicBool, AtomicU16, AtomicU32, AtomicU64, Ordering},
     path::Path,
     io::BufReader,
     fs::File
@@ -19,30 +18,432 @@ use rodio::{
     decoder::Decoder
 };
 
-use super::{PlayerError, THRESHOLD};
+use kanal::{Sender, Receiver, unbounded};
+
+use super::{
+    PlayerError,
+    Notice,
+    Lane,
+    QueueSnapshot,
+    lanes::Lanes,
+    history::{History, HistoryEntry, PlaySource, PlayStatus},
+    session::{Session, SESSION_FILE},
+    resume::{ResumeStore, Bookmark, RESUME_FILE},
+    chapters::{self, Chapter},
+    decode::SeekableDecoder,
+    cue,
+    display_name,
+    repointed,
+    playlist::{self, Playlist, PlaylistEntry},
+    metadata::{self, TrackInfo},
+    THRESHOLD
+};
+
+use crate::{xdg, config::config};
+
+/// prev_chapter goes to the previous chapter rather than
+/// restarting the current one within this time.
+const CHAPTER_RESTART: Duration = Duration::from_secs(3);
 
 struct Control {
     paused: AtomicBool,
-    repeat: AtomicBool
+    repeat: AtomicBool,
+    shuffle: AtomicBool,
+    /// bits of the f32 volume
+    volume: AtomicU32
 }
 
 
+/// Send a notice to every subscriber, the subscribers whose
+/// receivers are gone are dropped.
+fn broadcast(subscribers: &Mutex<Vec<Sender<Notice>>>, notice: Notice) {
+    subscribers
+        .lock()
+        .unwrap()
+        .retain(|tx| tx.send(notice.clone()).is_ok());
+}
+
 // request all methods in PlayQueue must be immutable
 pub struct PlayQueue<S> {
     current: Mutex<(S, Option<String>)>,
-    play_list: Arc<Mutex<VecDeque<String>>>,
-    listened_list: Mutex<Vec<String>>,
+    play_list: Mutex<Lanes>,
+    history: Mutex<History>,
+    resume: Mutex<ResumeStore>,
+    chapters: Arc<Mutex<Vec<Chapter>>>,
+    /// bumped for every song loaded, so the chapters read
+    /// for a song no longer current are thrown away
+    chapters_of: Arc<AtomicU64>,
+    /// where the current song came from, if it was loaded
+    /// paused and has not been played yet, its history entry
+    /// is started once it is
+    unstarted: Mutex<Option<PlaySource>>,
+    subscribers: Arc<Mutex<Vec<Sender<Notice>>>>,
     control: Control,
-    duration_tick: AtomicU32,
+    /// samples into the current song, moved by seeking
+    duration_tick: AtomicU64,
+    /// samples played of the current song, unlike the
+    /// duration ticks they are not moved by seeking
+    listened_tick: AtomicU64,
     total_duration: Mutex<Option<Duration>>,
     channels: AtomicU16,
     sample_rate: AtomicU32
 }
 
 impl<S> PlayQueue<S> {
+    /// Return a snapshot of both lanes, the internal
+    /// deques are never handed out.
+    #[inline]
+    pub fn get_playlist(&self) -> QueueSnapshot {
+        self.play_list.lock().unwrap().snapshot()
+    }
+
+    /// Every subscriber gets its own channel, so a notice
+    /// is received by all of them rather than by one.
+    pub fn subscribe(&self) -> Receiver<Notice> {
+        let (tx, rx) = unbounded::<Notice>();
+        self.subscribers.lock().unwrap().push(tx);
+        rx
+    }
+
+    #[inline]
+    fn notify(&self, notice: Notice) {
+        broadcast(&self.subscribers, notice);
+    }
+
+    #[inline]
+    fn queue_changed(&self) {
+        self.notify(Notice::QueueChanged(self.get_playlist()));
+    }
+
+    /// How long the current song has been played.
+    fn played(&self) -> Duration {
+        let sample_rate = self.sample_rate.load(Ordering::Acquire) as u64;
+        let channels = self.channels.load(Ordering::Acquire) as u64;
+        let ticks = self.duration_tick.load(Ordering::Acquire);
+
+        if sample_rate == 0 || channels == 0 {
+            return Duration::ZERO;
+        }
+
+        Duration::from_secs(ticks / sample_rate / channels)
+    }
+
+    /// How long the current song has been listened to.
+    fn listened(&self) -> Duration {
+        let sample_rate = self.sample_rate.load(Ordering::Acquire) as u64;
+        let channels = self.channels.load(Ordering::Acquire) as u64;
+        let ticks = self.listened_tick.load(Ordering::Acquire);
+
+        if sample_rate == 0 || channels == 0 {
+            return Duration::ZERO;
+        }
+
+        Duration::from_secs(ticks / sample_rate / channels)
+    }
+
+    #[inline]
+    pub fn volume(&self) -> f32 {
+        f32::from_bits(self.control.volume.load(Ordering::Acquire))
+    }
+
+    #[inline]
+    pub fn paused(&self) -> bool {
+        self.control.paused.load(Ordering::Acquire)
+    }
+
     #[inline]
-    pub fn get_playlist(&self) -> Arc<Mutex<VecDeque<String>>> {
-        self.play_list.clone()
+    pub fn repeat(&self) -> bool {
+        self.control.repeat.load(Ordering::Acquire)
+    }
+
+    #[inline]
+    pub fn shuffle(&self) -> bool {
+        self.control.shuffle.load(Ordering::Acquire)
+    }
+
+    /// Take a snapshot of everything needed to resume later.
+    pub fn session(&self) -> Session {
+        let current = self.current.lock().unwrap().1.clone();
+        let play_list = self.play_list.lock().unwrap();
+        let snapshot = play_list.snapshot();
+
+        Session {
+            user: snapshot.user,
+            context: snapshot.context,
+            current_lane: play_list.current_lane(),
+            history_cursor: self.history.lock().unwrap().cursor(),
+            offset_secs: match current {
+                None => 0,
+                Some(_) => self.played().as_secs()
+            },
+            current,
+            repeat: self.control.repeat.load(Ordering::Acquire),
+            shuffle: self.control.shuffle.load(Ordering::Acquire),
+            volume: self.volume()
+        }
+    }
+
+    /// Finish the current song in the history, and
+    /// let the subscribers know about it.
+    fn finish_current(&self, path: &str, status: PlayStatus) {
+        self.remember_position(path, status == PlayStatus::Completed);
+
+        let finished = self.history
+            .lock()
+            .unwrap()
+            .finish(self.listened(), status);
+
+        if let Some(entry) = finished {
+            self.notify(Notice::Played(entry));
+        }
+    }
+
+    /// Save the session on exit. The current song is finished
+    /// in the history, as completed if it was listened to its
+    /// end and as skipped else.
+    pub fn close(&self) -> std::io::Result<()> {
+        let path = self.current.lock().unwrap().1.clone();
+        if let Some(path) = path {
+            let total = *self.total_duration.lock().unwrap();
+            let completed = total.is_some_and(|total| self.listened() >= total);
+            self.finish_current(&path, match completed {
+                true => PlayStatus::Completed,
+                false => PlayStatus::Skipped
+            });
+        }
+
+        // nothing is told after closing, the threads following
+        // the notices end once they got the last ones
+        self.subscribers.lock().unwrap().clear();
+        self.save_session()
+    }
+
+    /// The resume positions are saved along with the session.
+    pub fn save_session(&self) -> std::io::Result<()> {
+        if let Some(ref path) = self.current.lock().unwrap().1 {
+            self.remember_position(path, false);
+        }
+        self.resume.lock().unwrap().save()?;
+
+        match xdg::state_file(SESSION_FILE) {
+            None => Ok(()),
+            Some(path) => self.session().save(&path)
+        }
+    }
+
+    #[inline]
+    fn is_long(total_duration: Option<Duration>) -> bool {
+        let min_secs = config().resume.min_duration_secs;
+        total_duration.is_some_and(|d| d.as_secs() >= min_secs)
+    }
+
+    /// Remember where a long file is stopped, or forget
+    /// it if the file is played to its end.
+    fn remember_position(&self, path: &str, completed: bool) {
+        if !Self::is_long(*self.total_duration.lock().unwrap()) {
+            return;
+        }
+
+        let mut resume = self.resume.lock().unwrap();
+        match completed {
+            true => resume.clear_position(path),
+            false => resume.set_position(path, self.played())
+        }
+    }
+
+    fn bookmarks_changed(&self, path: Option<&str>) {
+        let bookmarks = match path {
+            None => Vec::new(),
+            Some(path) => self.resume.lock().unwrap().bookmarks(path)
+        };
+        self.notify(Notice::BookmarksChanged(bookmarks));
+    }
+
+    pub fn bookmarks(&self) -> Vec<Bookmark> {
+        match self.current.lock().unwrap().1 {
+            None => Vec::new(),
+            Some(ref path) => self.resume.lock().unwrap().bookmarks(path)
+        }
+    }
+
+    /// Put a bookmark at the current position of the current song.
+    pub fn add_bookmark(&self, name: String) {
+        let path = match self.current.lock().unwrap().1 {
+            None => return,
+            Some(ref path) => path.clone()
+        };
+
+        self.resume.lock().unwrap().add_bookmark(&path, Bookmark {
+            name,
+            position_secs: self.played().as_secs()
+        });
+        self.bookmarks_changed(Some(&path));
+    }
+
+    pub fn remove_bookmark(&self, index: usize) -> Result<(), PlayerError> {
+        let path = match self.current.lock().unwrap().1 {
+            None => return Ok(()),
+            Some(ref path) => path.clone()
+        };
+
+        if self.resume.lock().unwrap().remove_bookmark(&path, index).is_none() {
+            return Err(PlayerError::IndexOutOfRange(index));
+        }
+        self.bookmarks_changed(Some(&path));
+        Ok(())
+    }
+
+    #[inline]
+    pub fn chapters(&self) -> Vec<Chapter> {
+        self.chapters.lock().unwrap().clone()
+    }
+
+    /// The chapters of a song are read in the background, as
+    /// the song is loaded on the audio thread with the current
+    /// song locked. They are cleared meanwhile.
+    fn set_chapters(&self, path: Option<&str>) {
+        let generation = self.chapters_of.fetch_add(1, Ordering::SeqCst) + 1;
+        let had_chapters = !std::mem::take(&mut *self.chapters.lock().unwrap()).is_empty();
+        if had_chapters {
+            self.notify(Notice::ChaptersChanged(Vec::new()));
+        }
+
+        let path = match path {
+            None => return,
+            Some(path) => path.to_string()
+        };
+        let (chapters, chapters_of, subscribers) = (
+            self.chapters.clone(),
+            self.chapters_of.clone(),
+            self.subscribers.clone()
+        );
+        let _ = std::thread::spawn(move || {
+            let read = chapters::read_chapters(Path::new(&path));
+            if read.is_empty() {
+                return;
+            }
+
+            let mut chapters = chapters.lock().unwrap();
+            if chapters_of.load(Ordering::SeqCst) == generation {
+                *chapters = read.clone();
+                broadcast(&subscribers, Notice::ChaptersChanged(read));
+            }
+        });
+    }
+
+    /// A CUE sheet inserts all of its tracks at `index`.
+    pub fn insert(&self, lane: Lane, index: usize, path: String) -> Result<(), PlayerError> {
+        let entries = self.expand(path)?;
+
+        if !self.play_list.lock().unwrap().insert(lane, index, entries) {
+            return Err(PlayerError::IndexOutOfRange(index));
+        }
+        self.queue_changed();
+        Ok(())
+    }
+
+    pub fn remove(&self, lane: Lane, index: usize) -> Result<String, PlayerError> {
+        let path = match self.play_list.lock().unwrap().lane_mut(lane).remove(index) {
+            None => return Err(PlayerError::IndexOutOfRange(index)),
+            Some(path) => path
+        };
+
+        self.queue_changed();
+        Ok(path)
+    }
+
+    /// Move the song at `from` to `to` within a lane,
+    /// songs in between are shifted by one.
+    pub fn move_item(&self, lane: Lane, from: usize, to: usize) -> Result<(), PlayerError> {
+        {
+            let mut play_list = self.play_list.lock().unwrap();
+            let songs = play_list.lane_mut(lane);
+            let len = songs.len();
+
+            if from >= len {
+                return Err(PlayerError::IndexOutOfRange(from));
+            }
+            if to >= len {
+                return Err(PlayerError::IndexOutOfRange(to));
+            }
+            if from == to {
+                return Ok(());
+            }
+
+            let path = songs.remove(from).unwrap();
+            songs.insert(to, path);
+        }
+
+        self.queue_changed();
+        Ok(())
+    }
+
+    pub fn clear(&self, lane: Lane) {
+        self.play_list.lock().unwrap().lane_mut(lane).clear();
+        self.queue_changed();
+    }
+
+    /// The entries under `from` follow the files moved to `to`,
+    /// the current song included.
+    pub fn repoint(&self, from: &str, to: &str) {
+        {
+            let mut current = self.current.lock().unwrap();
+            if let Some(new) = current.1.as_deref().and_then(|path| repointed(path, from, to)) {
+                current.1 = Some(new);
+            }
+        }
+
+        self.play_list.lock().unwrap().rewrite(|path| repointed(path, from, to));
+        self.queue_changed();
+    }
+
+    /// Remove repeated songs, only the first occurrence is kept.
+    pub fn dedupe(&self) {
+        self.play_list.lock().unwrap().dedupe();
+        self.queue_changed();
+    }
+
+    /// Expand a path into queue entries, the missing entries
+    /// of a playlist are reported to the subscribers.
+    fn expand(&self, path: String) -> Result<Vec<String>, PlayerError> {
+        let playlist = path.clone();
+        let expanded = super::expand(path)?;
+
+        if !expanded.missing.is_empty() {
+            self.notify(Notice::MissingEntries {
+                playlist,
+                missing: expanded.missing
+            });
+        }
+        Ok(expanded.entries)
+    }
+
+    fn expand_all(&self, paths: Vec<String>) -> Result<Vec<String>, PlayerError> {
+        let mut entries = Vec::with_capacity(paths.len());
+        for path in paths {
+            entries.extend(self.expand(path)?);
+        }
+        Ok(entries)
+    }
+
+    /// Append a bunch of songs to the context at once,
+    /// nothing is appended if any of them is not playable.
+    pub fn append_many(&self, paths: Vec<String>) -> Result<(), PlayerError> {
+        let entries = self.expand_all(paths)?;
+
+        self.play_list.lock().unwrap().lane_mut(Lane::Context).extend(entries);
+        self.queue_changed();
+        Ok(())
+    }
+
+    /// Replace the context with a new album or playlist,
+    /// the user lane is left untouched.
+    pub fn set_context(&self, paths: Vec<String>) -> Result<(), PlayerError> {
+        let entries = self.expand_all(paths)?;
+
+        *self.play_list.lock().unwrap().lane_mut(Lane::Context) = entries.into();
+        self.queue_changed();
+        Ok(())
     }
 }
 
@@ -74,15 +475,30 @@ impl<I> PlayQueue<Box<dyn Source<Item = I> + Send>>
 where I: Sample + Send + 'static + FromSample<i16> + Sized
 {
     pub fn new() -> Self {
+        Self::with_stores(
+            History::open(xdg::state_file("history.jsonl")),
+            ResumeStore::open(xdg::state_file(RESUME_FILE))
+        )
+    }
+
+    fn with_stores(history: History, resume: ResumeStore) -> Self {
         Self {
             current: Mutex::new((Box::new(Empty::<I>::new()) as Box<_>, None)),
-            play_list: Arc::new(Mutex::new(VecDeque::new())),
-            listened_list: Mutex::new(Vec::new()),
+            play_list: Mutex::new(Lanes::default()),
+            history: Mutex::new(history),
+            resume: Mutex::new(resume),
+            chapters: Arc::new(Mutex::new(Vec::new())),
+            chapters_of: Arc::new(AtomicU64::new(0)),
+            unstarted: Mutex::new(None),
+            subscribers: Arc::new(Mutex::new(Vec::new())),
             control: Control { 
                 paused: AtomicBool::new(false), 
-                repeat: AtomicBool::new(false)
+                repeat: AtomicBool::new(false),
+                shuffle: AtomicBool::new(false),
+                volume: AtomicU32::new(1.0f32.to_bits())
             },
-            duration_tick: AtomicU32::new(0),
+            duration_tick: AtomicU64::new(0),
+            listened_tick: AtomicU64::new(0),
             total_duration: Mutex::new(None),
             sample_rate: AtomicU32::new(0),
             channels: AtomicU16::new(0)
@@ -96,7 +512,14 @@ where I: Sample + Send + 'static + FromSample<i16> + Sized
         }
 
         let _ = self.duration_tick.fetch_add(1, Ordering::SeqCst);
-        self.current.lock().unwrap().0.next()
+        let _ = self.listened_tick.fetch_add(1, Ordering::SeqCst);
+        let volume = self.volume();
+        self.current
+            .lock()
+            .unwrap()
+            .0
+            .next()
+            .map(|sample| sample.amplify(volume))
     }
 
     pub fn next(&self) -> Option<I> {
@@ -105,7 +528,8 @@ where I: Sample + Send + 'static + FromSample<i16> + Sized
                 return Some(sample);
             }
 
-            let _ = self.go_next().unwrap();
+            // the songs failing to load are skipped by go_next
+            let _ = self.go_next();
         }
     }
 
@@ -116,13 +540,13 @@ where I: Sample + Send + 'static + FromSample<i16> + Sized
 
     // implement next_chunk to avoid acquiring mutex lock frequently
     pub fn next_chunk(&self, chunk_size: usize) -> Vec<I> {
-        (0..chunk_size).into_iter().map(|_| {
+        (0..chunk_size).map(|_| {
             loop {
                 if let Some(sample) = self.next_sample() {
                     return sample;
                 }
 
-                let _ = self.go_next().unwrap();
+                let _ = self.go_next();
             }
         }).collect::<Vec<I>>()
     }
@@ -132,22 +556,33 @@ where I: Sample + Send + 'static + FromSample<i16> + Sized
         self.play_list.lock().unwrap().is_empty()
     }
 
+    /// Songs appended go to the context lane.
     pub fn append(&self, path: String) -> Result<(), PlayerError> {
-        if !Path::new(&path).is_file() {
-            Err(PlayerError::WrongFileType(path))
-        } else {
-            self.play_list.lock().unwrap().push_back(path);
-            Ok(())
-        }
+        let entries = self.expand(path)?;
+        self.play_list.lock().unwrap().lane_mut(Lane::Context).extend(entries);
+        self.queue_changed();
+        Ok(())
     }
 
-    pub fn get_song(&self) -> Option<String> {
+    #[inline]
+    pub fn current_entry(&self) -> Option<String> {
         self.current.lock().unwrap().1.clone()
     }
 
-    #[inline]
+    pub fn get_song(&self) -> Option<TrackInfo> {
+        self.current_entry().map(|entry| metadata::track_info(&entry))
+    }
+
     pub fn set_paused(&self, paused: bool) {
         self.control.paused.store(paused, Ordering::Release);
+        if paused {
+            return;
+        }
+
+        let source = self.unstarted.lock().unwrap().take();
+        if let (Some(source), Some(path)) = (source, self.current_entry()) {
+            self.history.lock().unwrap().start(path, source, self.total_duration());
+        }
     }
 
     #[inline]
@@ -155,9 +590,19 @@ where I: Sample + Send + 'static + FromSample<i16> + Sized
         self.control.repeat.store(repeat, Ordering::Release);
     }
 
+    #[inline]
+    pub fn set_shuffle(&self, shuffle: bool) {
+        self.control.shuffle.store(shuffle, Ordering::Release);
+    }
+
+    #[inline]
+    pub fn set_volume(&self, volume: f32) {
+        self.control.volume.store(volume.max(0.0).to_bits(), Ordering::Release);
+    }
+
     #[inline]
     pub fn total_duration(&self) -> Option<Duration> {
-        self.total_duration.lock().unwrap().clone()
+        *self.total_duration.lock().unwrap()
     }
 
     pub fn progress(&self) -> Option<Duration> {
@@ -165,66 +610,336 @@ where I: Sample + Send + 'static + FromSample<i16> + Sized
             return None;
         }
 
-        let sample_rate = self.sample_rate.load(Ordering::Acquire);
-        let channels = self.channels.load(Ordering::Acquire) as u32;
-        let ticks = self.duration_tick.load(Ordering::Acquire);
+        Some(self.played())
+    }
 
-        Some(Duration::from_secs((ticks / sample_rate / channels) as u64))
+    #[inline]
+    pub fn get_history(&self) -> Vec<HistoryEntry> {
+        self.history.lock().unwrap().entries().to_vec()
     }
 
+    /// Play a song right away, the current song is finished
+    /// as skipped and both lanes stay as they are. For a CUE
+    /// sheet, its first track is played and the rest are put
+    /// in front of the user lane.
     pub fn play(&self, path: String) -> Result<(), PlayerError> {
-        let _ = self.play_next(path)?;
-        self.go_next_ignore_repeat(true)
+        let mut entries = self.expand(path)?;
+        if entries.is_empty() {
+            return Ok(());
+        }
+        let path = entries.remove(0);
+
+        let mut current = self.current.lock().unwrap();
+        if let Some(curr_path) = current.1.take() {
+            self.finish_current(&curr_path, PlayStatus::Skipped);
+        }
+        self.history.lock().unwrap().reset_cursor();
+
+        {
+            let mut play_list = self.play_list.lock().unwrap();
+            entries.into_iter().rev().for_each(|entry| play_list.push_front(Lane::User, entry));
+            play_list.set_current_lane(Some(Lane::User));
+        }
+        self.load(&mut current, Some(path), PlaySource::Queue)
     }
 
+    /// Queue a song in the user lane, songs queued this way
+    /// are played in the order they are queued.
     pub fn play_next(&self, path: String) -> Result<(), PlayerError> {
-        if !Path::new(&path).is_file() {
-            return Err(PlayerError::WrongFileType(path));
-        }
+        let entries = self.expand(path)?;
 
-        self.play_list.lock().unwrap().push_front(path);
+        self.play_list.lock().unwrap().lane_mut(Lane::User).extend(entries);
+        self.queue_changed();
         Ok(())
     }
 
+    /// Play the song at `index` of a lane right away,
+    /// the songs before it stay in the lane.
+    pub fn jump_to(&self, lane: Lane, index: usize) -> Result<(), PlayerError> {
+        let mut current = self.current.lock().unwrap();
+
+        let path = {
+            let mut play_list = self.play_list.lock().unwrap();
+            let path = match play_list.lane_mut(lane).remove(index) {
+                None => return Err(PlayerError::IndexOutOfRange(index)),
+                Some(path) => path
+            };
+            play_list.set_current_lane(Some(lane));
+            path
+        };
+
+        if let Some(curr_path) = current.1.take() {
+            self.finish_current(&curr_path, PlayStatus::Skipped);
+        }
+        self.history.lock().unwrap().reset_cursor();
+
+        self.load(&mut current, Some(path), lane.into())
+    }
+
+    /// `ignore` is only true when the user skips the current
+    /// song, so the song is finished as skipped. Otherwise
+    /// the song came to its end and is completed.
     pub fn go_next_ignore_repeat(&self, ignore: bool) -> Result<(), PlayerError> {
         let mut current = self.current.lock().unwrap();
+        let idle = current.1.is_none();
+
+        if let Some(ref curr_path) = current.1 {
+            self.finish_current(curr_path, match ignore {
+                true => PlayStatus::Skipped,
+                false => PlayStatus::Completed
+            });
+        }
+
+        let mut play_list = self.play_list.lock().unwrap();
 
         if let Some(path) = current.1.take() {
             if self.control.repeat.load(Ordering::Acquire) && !ignore {
-                self.play_list.lock().unwrap().push_front(path);
-            } else {
-                self.listened_list.lock().unwrap().push(path);
+                let lane = play_list.current_lane().unwrap_or(Lane::Context);
+                play_list.push_front(lane, path);
             }
         }
 
-        *current = match self.play_list.lock().unwrap().pop_front() {
-            None => (Box::new(Zero::<I>::new_samples(1, 44100, THRESHOLD)) as Box<_>, None),
-            Some(path_string) => {
-                let buf = BufReader::new(match 
-                    File::open(Path::new(&path_string)) {
-                    Ok(f) => f,
-                    Err(e) => return Err(PlayerError::IOError(e))
-                });
+        drop(play_list);
 
-                (Box::new(match Decoder::new(buf) {
-                    Ok(decoded) => decoded,
-                    Err(e) => return Err(PlayerError::DecoderError(e))
-                }.convert_samples()), Some(path_string))
+        // a song failing to load is skipped and reported, the
+        // audio thread has nobody else to hand the error to
+        loop {
+            let mut play_list = self.play_list.lock().unwrap();
+
+            // replay the history first if the user went back before
+            let next = match self.history.lock().unwrap().forward() {
+                Some(path) => {
+                    play_list.set_current_lane(None);
+                    Some((path, PlaySource::Context))
+                },
+                None => play_list
+                    .pop_front(self.control.shuffle.load(Ordering::Acquire))
+                    .map(|(path, lane)| (path, lane.into()))
+            };
+            drop(play_list);
+
+            match next {
+                // the silence ran out with nothing queued, nothing
+                // changed so nobody is told about it again
+                None if idle => {
+                    *current = Self::silence();
+                    self.set_ticks(&current, Duration::ZERO);
+                    return Ok(());
+                },
+                None => return self.load(&mut current, None, PlaySource::Context),
+                Some((path, source)) => match self.load(&mut current, Some(path.clone()), source) {
+                    Ok(()) => return Ok(()),
+                    Err(error) => self.notify(Notice::Unplayable {
+                        entry: path,
+                        error: Arc::new(error)
+                    })
+                }
             }
+        }
+    }
+
+    /// What is played while nothing is queued.
+    #[inline]
+    fn silence() -> (Box<dyn Source<Item = I> + Send>, Option<String>) {
+        (Box::new(Zero::<I>::new_samples(1, 44100, THRESHOLD)) as Box<_>, None)
+    }
+
+    #[inline]
+    fn load(
+        &self,
+        current: &mut (Box<dyn Source<Item = I> + Send>, Option<String>),
+        next: Option<String>,
+        source: PlaySource
+    ) -> Result<(), PlayerError> {
+        self.load_at(current, next, source, Duration::ZERO)
+    }
+
+    /// Decode `next` and make it the current song,
+    /// starting `offset` into it.
+    fn load_at(
+        &self,
+        current: &mut (Box<dyn Source<Item = I> + Send>, Option<String>),
+        next: Option<String>,
+        source: PlaySource,
+        offset: Duration
+    ) -> Result<(), PlayerError> {
+        let mut offset = offset;
+        let mut total_duration = next.as_deref().and_then(Self::duration_of);
+
+        // a long file starts where it was stopped last time, only
+        // the positions of long files are kept in the first place
+        if let (Some(path_string), true) = (next.as_ref(), offset.is_zero()) {
+            offset = self.resume
+                .lock()
+                .unwrap()
+                .position(path_string)
+                .unwrap_or(offset);
+        }
+
+        *current = match next {
+            None => Self::silence(),
+            Some(path_string) => (Self::decode(&path_string, offset)?, Some(path_string))
         };
 
-        *self.total_duration.lock().unwrap() = match current.1 {
-            None => None,
-            Some(ref path_string) => 
-                mp3_duration::from_path(Path::new(path_string)).ok()
+        // the decoder only knows what is left after the offset,
+        // the playlist the song came from is the last resort
+        if let (None, Some(path_string)) = (total_duration, current.1.as_ref()) {
+            total_duration = current.0
+                .total_duration()
+                .map(|left| left + offset)
+                .or_else(|| playlist::hint(path_string).and_then(|hint| hint.duration));
+        }
+        *self.total_duration.lock().unwrap() = total_duration;
+
+        // a song loaded paused, like a restored one, is not
+        // recorded until it is played
+        let paused = self.control.paused.load(Ordering::Acquire);
+        *self.unstarted.lock().unwrap() = match current.1 {
+            Some(_) if paused => Some(source),
+            Some(ref path_string) => {
+                self.history.lock().unwrap().start(path_string.clone(), source, total_duration);
+                None
+            },
+            None => None
         };
 
-        self.duration_tick.store(0, Ordering::Release);
+        self.listened_tick.store(0, Ordering::Release);
+        self.set_ticks(current, offset);
+        self.queue_changed();
+        self.bookmarks_changed(current.1.as_deref());
+        self.set_chapters(current.1.as_deref());
+        Ok(())
+    }
+
+    /// The duration of an entry, if it can be told without decoding.
+    fn duration_of(entry: &str) -> Option<Duration> {
+        match cue::resolve(entry) {
+            None => mp3_duration::from_path(Path::new(entry)).ok(),
+            Some(track) => match track.end {
+                Some(end) => Some(end.saturating_sub(track.start)),
+                None => mp3_duration::from_path(&track.file)
+                    .ok()
+                    .map(|total| total.saturating_sub(track.start))
+            }
+        }
+    }
+
+    /// Save the current song followed by both lanes.
+    pub fn save_playlist(&self, path: &str) -> Result<(), PlayerError> {
+        let current = self.current_entry();
+        let snapshot = self.get_playlist();
+
+        let entries = current.into_iter()
+            .chain(snapshot.user)
+            .chain(snapshot.context)
+            .map(|entry| PlaylistEntry {
+                title: Some(display_name(&entry)),
+                duration: Self::duration_of(&entry),
+                path: entry
+            })
+            .collect();
+
+        Playlist { entries }.save(path)
+    }
+
+    /// Decode an entry starting `offset` into it, a track of
+    /// a CUE sheet is cut out of its backing file. See
+    /// decode.rs for how the offset is reached.
+    fn decode(path: &str, offset: Duration) -> Result<Box<dyn Source<Item = I> + Send>, PlayerError> {
+        let (file, start, end) = match cue::resolve(path) {
+            None => (Path::new(path).to_path_buf(), Duration::ZERO, None),
+            Some(track) => (track.file, track.start, track.end)
+        };
+
+        let buf = BufReader::new(match File::open(&file) {
+            Ok(f) => f,
+            Err(e) => return Err(PlayerError::IOError(e))
+        });
+
+        let decoded: Box<dyn Source<Item = I> + Send> = match SeekableDecoder::open(&file, start + offset) {
+            Some(decoded) => Box::new(decoded.convert_samples()),
+            None => match Decoder::new(buf) {
+                Ok(decoded) => Box::new(decoded.convert_samples().skip_duration(start + offset)),
+                Err(e) => return Err(PlayerError::DecoderError(e))
+            }
+        };
+
+        Ok(match end {
+            None => Box::new(decoded),
+            Some(end) => Box::new(decoded.take_duration(end.saturating_sub(start + offset)))
+        })
+    }
+
+    /// Reset the duration ticks to `offset` into the current song.
+    fn set_ticks(
+        &self,
+        current: &(Box<dyn Source<Item = I> + Send>, Option<String>),
+        offset: Duration
+    ) {
         let sample_rate = current.0.sample_rate();
         let channels = current.0.channels();
+        let offset_ticks = offset.as_secs() * sample_rate as u64 * channels as u64;
+        self.duration_tick.store(offset_ticks, Ordering::Release);
         self.sample_rate.store(sample_rate, Ordering::Release);
         self.channels.store(channels, Ordering::Release);
+    }
+
+    pub fn jump_to_bookmark(&self, index: usize) -> Result<(), PlayerError> {
+        match self.bookmarks().get(index) {
+            None => Err(PlayerError::IndexOutOfRange(index)),
+            Some(bookmark) => self.seek(Duration::from_secs(bookmark.position_secs))
+        }
+    }
+
+    /// Seek to the start of the chapter after the current one.
+    pub fn next_chapter(&self) -> Result<(), PlayerError> {
+        let played = self.played();
+        let next = self.chapters
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|chapter| chapter.start > played)
+            .map(|chapter| chapter.start);
+
+        match next {
+            None => Ok(()),
+            Some(start) => self.seek(start)
+        }
+    }
+
+    /// Seek to the start of the current chapter, or to the
+    /// previous chapter if the current one has just started.
+    pub fn prev_chapter(&self) -> Result<(), PlayerError> {
+        let played = self.played();
+        let start = {
+            let chapters = self.chapters.lock().unwrap();
+            match chapters::chapter_at(&chapters, played) {
+                None => return Ok(()),
+                Some(i) if played - chapters[i].start < CHAPTER_RESTART && i > 0 =>
+                    chapters[i - 1].start,
+                Some(i) => chapters[i].start
+            }
+        };
 
+        self.seek(start)
+    }
+
+    /// Play the current song from `position`, the history
+    /// is not touched. The song is opened without holding the
+    /// lock, so the samples keep flowing meanwhile.
+    pub fn seek(&self, position: Duration) -> Result<(), PlayerError> {
+        let path = match self.current_entry() {
+            None => return Ok(()),
+            Some(path) => path
+        };
+
+        let decoded = Self::decode(&path, position)?;
+        let mut current = self.current.lock().unwrap();
+        // another song may have been loaded meanwhile
+        if current.1.as_ref() == Some(&path) {
+            current.0 = decoded;
+            self.set_ticks(&current, position);
+        }
         Ok(())
     }
 
@@ -233,19 +948,209 @@ where I: Sample + Send + 'static + FromSample<i16> + Sized
         self.go_next_ignore_repeat(false)
     }
 
+    /// Move the history cursor one song back and play it,
+    /// nothing in the history or the lanes is removed.
     pub fn go_prev(&self) -> Result<(), PlayerError> {
-        if let Some(prev_path) = self.listened_list.lock().unwrap().pop() {
-            let mut current = self.current.lock().unwrap();
+        let mut current = self.current.lock().unwrap();
 
-            if let Some(curr_path) = current.1.take() {
-                self.play_list.lock().unwrap().push_front(curr_path);
-            }
+        if let Some(ref curr_path) = current.1 {
+            self.remember_position(curr_path, false);
+        }
 
-            self.play_list.lock().unwrap().push_front(prev_path);
+        let listened = self.listened();
+        let (prev_path, finished) = match self.history.lock().unwrap().back(listened) {
+            None => return Ok(()),
+            Some(prev) => prev
+        };
 
-            self.go_next_ignore_repeat(true)?;
+        if let Some(entry) = finished {
+            self.notify(Notice::Played(entry));
         }
 
-        Ok(())
+        current.1 = None;
+        self.play_list.lock().unwrap().set_current_lane(None);
+        self.load(&mut current, Some(prev_path), PlaySource::Context)
     }
+
+    /// Bring back a saved session, the current song is loaded
+    /// paused at the saved offset and only recorded in the
+    /// history once it is played.
+    pub fn restore(&self, mut session: Session) -> Result<(), PlayerError> {
+        session.retain_existing();
+
+        self.set_repeat(session.repeat);
+        self.set_shuffle(session.shuffle);
+        self.set_volume(session.volume);
+        self.history.lock().unwrap().set_cursor(session.history_cursor);
+
+        let mut current = self.current.lock().unwrap();
+        let source = session.current_lane.map(PlaySource::from).unwrap_or(PlaySource::Context);
+        self.play_list
+            .lock()
+            .unwrap()
+            .restore(session.user, session.context, session.current_lane);
+
+        if session.current.is_some() {
+            self.set_paused(true);
+        }
+
+        self.load_at(
+            &mut current,
+            session.current,
+            source,
+            Duration::from_secs(session.offset_secs)
+        )
+    }
+}
+
+/// Write `names` as a tenth of a second of WAV each,
+/// see decode::test_wav, into a new directory.
+#[cfg(test)]
+fn test_songs<const N: usize>(dir: &str, names: [&str; N]) -> (std::path::PathBuf, [String; N]) {
+    let dir = std::env::temp_dir().join(format!("tmusic_{}_{}", dir, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let songs = names.map(|name| {
+        let path = dir.join(format!("{}.wav", name));
+        super::decode::test_wav(&path, 0.1);
+        path.to_string_lossy().into_owned()
+    });
+    (dir, songs)
+}
+
+#[test]
+fn test_queue_editing() {
+    let (dir, songs) = test_songs("queue", ["a", "b", "c", "d"]);
+    let queue = PlayQueue::<Box<dyn Source<Item = i16> + Send>>::with_stores(
+        History::open(None),
+        ResumeStore::open(None)
+    );
+    let notices = queue.subscribe();
+    let context = || queue.get_playlist().context;
+    let songs_at = |indices: &[usize]| indices.iter()
+        .map(|&i| songs[i].clone())
+        .collect::<Vec<String>>();
+
+    queue.append_many(songs.to_vec()).unwrap();
+    assert!(queue.append_many(vec![dir.join("e.wav").to_string_lossy().into_owned()]).is_err());
+    assert_eq!(context(), songs);
+    match notices.try_recv() {
+        Ok(Some(Notice::QueueChanged(snapshot))) => assert_eq!(snapshot.context, songs),
+        _ => panic!("no QueueChanged after append_many")
+    }
+
+    queue.move_item(Lane::Context, 0, 2).unwrap();
+    assert_eq!(context(), songs_at(&[1, 2, 0, 3]));
+    assert!(queue.move_item(Lane::Context, 0, 4).is_err());
+
+    assert_eq!(queue.remove(Lane::Context, 2).unwrap(), songs[0]);
+    assert!(queue.remove(Lane::User, 0).is_err());
+
+    queue.insert(Lane::Context, 1, songs[0].clone()).unwrap();
+    assert_eq!(context(), songs_at(&[1, 0, 2, 3]));
+    assert!(queue.insert(Lane::User, 1, songs[0].clone()).is_err());
+    assert!(queue.insert(Lane::Context, 0, dir.join("e.wav").to_string_lossy().into_owned()).is_err());
+    assert_eq!(queue.remove(Lane::Context, 1).unwrap(), songs[0]);
+
+    queue.play_next(songs[1].clone()).unwrap();
+    queue.dedupe();
+    assert_eq!(queue.get_playlist().user, songs_at(&[1]));
+    assert_eq!(context(), songs_at(&[2, 3]));
+
+    // the songs before the one jumped to stay in the lane
+    queue.jump_to(Lane::Context, 1).unwrap();
+    assert_eq!(queue.current_entry().as_ref(), Some(&songs[3]));
+    assert_eq!(context(), songs_at(&[2]));
+    assert!(queue.jump_to(Lane::Context, 1).is_err());
+
+    queue.clear(Lane::Context);
+    assert!(context().is_empty());
+    let last = std::iter::from_fn(|| notices.try_recv().ok().flatten())
+        .filter_map(|notice| match notice {
+            Notice::QueueChanged(snapshot) => Some(snapshot),
+            _ => None
+        })
+        .last()
+        .unwrap();
+    assert_eq!(last.user, songs_at(&[1]));
+    assert!(last.context.is_empty());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_skip_unplayable() {
+    let (dir, songs) = test_songs("unplayable", ["a"]);
+    let broken = dir.join("broken.wav").to_string_lossy().into_owned();
+    std::fs::write(&broken, b"not a wav").unwrap();
+    let queue = PlayQueue::<Box<dyn Source<Item = i16> + Send>>::with_stores(
+        History::open(None),
+        ResumeStore::open(None)
+    );
+    let notices = queue.subscribe();
+
+    queue.append_many(vec![broken.clone(), songs[0].clone()]).unwrap();
+    queue.go_next().unwrap();
+    assert_eq!(queue.current_entry().as_ref(), Some(&songs[0]));
+    assert!(queue.get_playlist().context.is_empty());
+    assert!(std::iter::from_fn(|| notices.try_recv().ok().flatten())
+        .any(|notice| matches!(notice, Notice::Unplayable { entry, .. } if entry == broken)));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_session_round_trip() {
+    let (dir, songs) = test_songs("session", ["a", "b", "c"]);
+    let queue = || PlayQueue::<Box<dyn Source<Item = i16> + Send>>::with_stores(
+        History::open(None),
+        ResumeStore::open(None)
+    );
+
+    let session = Session {
+        user: vec![songs[1].clone()],
+        context: vec![songs[2].clone(), dir.join("gone.wav").to_string_lossy().into_owned()],
+        current_lane: Some(Lane::User),
+        history_cursor: Some(1),
+        current: Some(songs[0].clone()),
+        offset_secs: 42,
+        repeat: true,
+        shuffle: true,
+        volume: 0.5
+    };
+
+    let restored = queue();
+    {
+        let mut history = restored.history.lock().unwrap();
+        for song in &songs {
+            history.start(song.clone(), PlaySource::Context, None);
+            history.finish(Duration::from_secs(1), PlayStatus::Completed);
+        }
+    }
+    restored.restore(session.clone()).unwrap();
+
+    // restored paused, nothing is recorded until it is played
+    assert!(restored.control.paused.load(Ordering::Acquire));
+    assert_eq!(restored.get_history().len(), songs.len());
+    let expected = Session {
+        context: vec![songs[2].clone()],
+        ..session
+    };
+    assert_eq!(restored.session(), expected);
+
+    let file = dir.join(SESSION_FILE);
+    restored.session().save(&file).unwrap();
+    assert_eq!(Session::load(&file), Some(expected));
+
+    // the song restored is recorded once it is played, and
+    // finished when the queue is closed
+    let played = queue();
+    played.restore(Session::load(&file).unwrap()).unwrap();
+    played.history.lock().unwrap().set_cursor(None);
+    played.set_paused(false);
+    assert_eq!(played.get_history().last().map(|entry| entry.status), Some(PlayStatus::Playing));
+    played.close().unwrap();
+    assert_eq!(played.get_history().last().map(|entry| entry.status), Some(PlayStatus::Skipped));
+
+    let _ = std::fs::remove_dir_all(&dir);
 }
@@ -3,11 +3,12 @@
 // Author: https://github.com/xiaoqixian
 
 use std::{
-    sync::{Arc, Mutex},
-    collections::VecDeque,
+    sync::Arc,
     time::Duration
 };
 
+use crate::xdg;
+
 use rodio::{
     OutputStream,
     OutputStreamHandle,
@@ -16,18 +17,29 @@ use rodio::{
     cpal::FromSample
 };
 
-use kanal::bounded;
+use kanal::{bounded, Receiver};
 
 use super::{
     PlayerError,
     Playback,
+    Notice,
+    Lane,
+    QueueSnapshot,
+    TrackInfo,
+    history::HistoryEntry,
+    resume::Bookmark,
+    chapters::Chapter,
     play_queue::PlayQueue,
     source_stream::SourceStream,
     RequestType,
     ResponseType,
-    listener::{BUFFER_SIZE, NoticeListener}
+    listener::{BUFFER_SIZE, NoticeListener},
+    session::{Session, SESSION_FILE}
 };
 
+/// How often the session is saved while playing.
+const SESSION_SAVE_INTERVAL: Duration = Duration::from_secs(30);
+
 pub struct Player<S> {
     play_queue: Arc<PlayQueue<S>>,
     _stream: OutputStream,
@@ -39,7 +51,7 @@ where
     I: Sample + Send + FromSample<i16> + 'static,
     f32: FromSample<I>
 {
-    fn new() -> Self {
+    pub fn new() -> Self {
         let (_stream, stream_handle) = OutputStream::try_default().unwrap();
 
         let (request_sender, request_receiver) = bounded::<RequestType>(0);
@@ -54,6 +66,21 @@ where
 
         let play_queue = Arc::new(PlayQueue::<Box<dyn Source<Item = I> + Send>>::new());
 
+        // resume the last session, paused
+        if let Some(session) = xdg::state_file(SESSION_FILE)
+            .and_then(|path| Session::load(&path))
+        {
+            let _ = play_queue.restore(session);
+        }
+
+        // save the session periodically, so not much is lost
+        // even if tmusic is killed.
+        let saver_queue = play_queue.clone();
+        let _ = std::thread::spawn(move || loop {
+            std::thread::sleep(SESSION_SAVE_INTERVAL);
+            let _ = saver_queue.save_session();
+        });
+
         let mut listener = NoticeListener::<Box<dyn Source<Item = I> + Send>, I>::new(
             play_queue.clone(),
             response_sender,
@@ -64,7 +91,7 @@ where
         // spawn a notice listener
         let _ = std::thread::spawn(move || listener.run());
 
-        let _ = stream_handle.play_raw(source_stream).unwrap();
+        stream_handle.play_raw(source_stream).unwrap();
 
         Self {
             play_queue,
@@ -79,24 +106,84 @@ where
     I: Sample + Send + FromSample<i16> + 'static,
     f32: FromSample<I>
 {
-    type ListContainer = VecDeque<String>;
-    type ListHandle = Arc<Mutex<Self::ListContainer>>;
-
     #[inline]
-    fn get_song(&self) -> Option<String> {
+    fn get_song(&self) -> Option<TrackInfo> {
         self.play_queue.get_song()
     }
 
     #[inline]
-    fn get_playlist(&self) -> Self::ListHandle {
+    fn get_playlist(&self) -> QueueSnapshot {
         self.play_queue.get_playlist()
     }
 
+    #[inline]
+    fn save_playlist(&self, path: String) -> Result<(), PlayerError> {
+        self.play_queue.save_playlist(&path)
+    }
+
+    #[inline]
+    fn get_history(&self) -> Vec<HistoryEntry> {
+        self.play_queue.get_history()
+    }
+
+    #[inline]
+    fn subscribe(&self) -> Receiver<Notice> {
+        self.play_queue.subscribe()
+    }
+
     #[inline]
     fn append_list(&mut self, path: String) -> Result<(), PlayerError>{
         self.play_queue.append(path)
     }
 
+    #[inline]
+    fn append_many(&mut self, paths: Vec<String>) -> Result<(), PlayerError> {
+        self.play_queue.append_many(paths)
+    }
+
+    #[inline]
+    fn set_context(&mut self, paths: Vec<String>) -> Result<(), PlayerError> {
+        self.play_queue.set_context(paths)
+    }
+
+    #[inline]
+    fn insert(&mut self, lane: Lane, index: usize, path: String) -> Result<(), PlayerError> {
+        self.play_queue.insert(lane, index, path)
+    }
+
+    #[inline]
+    fn remove(&mut self, lane: Lane, index: usize) -> Result<String, PlayerError> {
+        self.play_queue.remove(lane, index)
+    }
+
+    #[inline]
+    fn move_item(&mut self, lane: Lane, from: usize, to: usize) -> Result<(), PlayerError> {
+        self.play_queue.move_item(lane, from, to)
+    }
+
+    #[inline]
+    fn clear(&mut self, lane: Lane) -> Result<(), PlayerError> {
+        self.play_queue.clear(lane);
+        Ok(())
+    }
+
+    #[inline]
+    fn dedupe(&mut self) -> Result<(), PlayerError> {
+        self.play_queue.dedupe();
+        Ok(())
+    }
+
+    #[inline]
+    fn repoint(&mut self, from: String, to: String) -> Result<(), PlayerError> {
+        self.play_queue.repoint(&from, &to);
+        Ok(())
+    }
+
+    #[inline]
+    fn jump_to(&mut self, lane: Lane, index: usize) -> Result<(), PlayerError> {
+        self.play_queue.jump_to(lane, index)
+    }
+
     #[inline]
     fn go_next(&mut self) -> Result<(), PlayerError> {
         self.play_queue.go_next_ignore_repeat(true)
@@ -123,6 +210,85 @@ where
         Ok(())
     }
 
+    #[inline]
+    fn set_repeat(&mut self, repeat: bool) -> Result<(), PlayerError> {
+        self.play_queue.set_repeat(repeat);
+        Ok(())
+    }
+
+    #[inline]
+    fn set_shuffle(&mut self, shuffle: bool) -> Result<(), PlayerError> {
+        self.play_queue.set_shuffle(shuffle);
+        Ok(())
+    }
+
+    #[inline]
+    fn set_volume(&mut self, volume: f32) -> Result<(), PlayerError> {
+        self.play_queue.set_volume(volume);
+        Ok(())
+    }
+
+    #[inline]
+    fn volume(&self) -> f32 {
+        self.play_queue.volume()
+    }
+
+    #[inline]
+    fn paused(&self) -> bool {
+        self.play_queue.paused()
+    }
+
+    #[inline]
+    fn repeat(&self) -> bool {
+        self.play_queue.repeat()
+    }
+
+    #[inline]
+    fn shuffle(&self) -> bool {
+        self.play_queue.shuffle()
+    }
+
+    #[inline]
+    fn seek(&mut self, position: Duration) -> Result<(), PlayerError> {
+        self.play_queue.seek(position)
+    }
+
+    #[inline]
+    fn add_bookmark(&mut self, name: String) -> Result<(), PlayerError> {
+        self.play_queue.add_bookmark(name);
+        Ok(())
+    }
+
+    #[inline]
+    fn remove_bookmark(&mut self, index: usize) -> Result<(), PlayerError> {
+        self.play_queue.remove_bookmark(index)
+    }
+
+    #[inline]
+    fn jump_to_bookmark(&mut self, index: usize) -> Result<(), PlayerError> {
+        self.play_queue.jump_to_bookmark(index)
+    }
+
+    #[inline]
+    fn bookmarks(&self) -> Vec<Bookmark> {
+        self.play_queue.bookmarks()
+    }
+
+    #[inline]
+    fn next_chapter(&mut self) -> Result<(), PlayerError> {
+        self.play_queue.next_chapter()
+    }
+
+    #[inline]
+    fn prev_chapter(&mut self) -> Result<(), PlayerError> {
+        self.play_queue.prev_chapter()
+    }
+
+    #[inline]
+    fn chapters(&self) -> Vec<Chapter> {
+        self.play_queue.chapters()
+    }
+
     #[inline]
     fn total_duration(&self) -> Option<Duration> {
         self.play_queue.total_duration()
@@ -133,3 +299,9 @@ where
         self.play_queue.progress()
     }
 }
+
+impl<S> Drop for Player<S> {
+    fn drop(&mut self) {
+        let _ = self.play_queue.close();
+    }
+}
@@ -0,0 +1,93 @@
+// Date: Mon Nov 13 20:36:52 2023
+// Mail: lunar_ubuntu@qq.com
+// Author: https://github.com/xiaoqixian
+
+/*
+ * Extended M3U:
+ *
+ *   #EXTM3U
+ *   #EXTINF:254,李宗盛 - 寂寞难耐
+ *   李宗盛/寂寞难耐.mp3
+ *
+ * Plain M3U is just the locations, one a line.
+ */
+
+use std::{path::Path, time::Duration};
+
+use super::{Playlist, PlaylistEntry, PlaylistFormat, resolve, relative};
+
+pub struct M3u;
+
+impl PlaylistFormat for M3u {
+    fn extensions(&self) -> &'static [&'static str] {
+        &["m3u", "m3u8"]
+    }
+
+    fn parse(&self, content: &str, dir: &Path) -> Playlist {
+        let mut entries = Vec::new();
+        let mut info: Option<(Option<Duration>, Option<String>)> = None;
+
+        for line in content.trim_start_matches('\u{feff}').lines().map(str::trim) {
+            if let Some(extinf) = line.strip_prefix("#EXTINF:") {
+                // the duration may be followed by attributes
+                let (duration, title) = extinf.split_once(',').unwrap_or((extinf, ""));
+                let duration = duration
+                    .split_whitespace()
+                    .next()
+                    .and_then(|secs| secs.parse::<i64>().ok())
+                    .filter(|&secs| secs >= 0)
+                    .map(|secs| Duration::from_secs(secs as u64));
+                let title = Some(title.trim()).filter(|t| !t.is_empty()).map(String::from);
+                info = Some((duration, title));
+                continue;
+            }
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (duration, title) = info.take().unwrap_or_default();
+            if let Some(path) = resolve(line, dir) {
+                entries.push(PlaylistEntry { path, title, duration });
+            }
+        }
+
+        Playlist { entries }
+    }
+
+    fn write(&self, playlist: &Playlist, dir: &Path) -> String {
+        let mut content = String::from("#EXTM3U\n");
+
+        for entry in playlist.entries.iter() {
+            if entry.title.is_some() || entry.duration.is_some() {
+                let secs = entry.duration.map_or(-1, |d| d.as_secs() as i64);
+                content.push_str(&format!("#EXTINF:{},{}\n", secs, entry.title.as_deref().unwrap_or("")));
+            }
+            content.push_str(&relative(&entry.path, dir));
+            content.push('\n');
+        }
+
+        content
+    }
+}
+
+#[test]
+fn test_m3u_round_trip() {
+    let content = "#EXTM3U\n\
+        #EXTINF:254,李宗盛 - 寂寞难耐\n\
+        李宗盛/寂寞难耐.mp3\n\
+        \n\
+        /abs/山丘.flac\n\
+        file:///abs/%E5%B1%B1%E4%B8%98.mp3\n\
+        http://example.com/stream\n";
+
+    let dir = Path::new("/music");
+    let playlist = M3u.parse(content, dir);
+    let paths = playlist.entries.iter().map(|e| e.path.as_str()).collect::<Vec<&str>>();
+    assert_eq!(paths, ["/music/李宗盛/寂寞难耐.mp3", "/abs/山丘.flac", "/abs/山丘.mp3"]);
+    assert_eq!(playlist.entries[0].duration, Some(Duration::from_secs(254)));
+    assert_eq!(playlist.entries[0].title.as_deref(), Some("李宗盛 - 寂寞难耐"));
+    assert_eq!(playlist.entries[1].title, None);
+
+    assert_eq!(M3u.parse(&M3u.write(&playlist, dir), dir), playlist);
+}
@@ -0,0 +1,181 @@
+// Date: Mon Nov 13 20:11:26 2023
+// Mail: lunar_ubuntu@qq.com
+// Author: https://github.com/xiaoqixian
+
+/*
+ * Playlist files, loaded into the queue and saved from it.
+ * Every file format implements PlaylistFormat, and the format
+ * of a file is told by its extension.
+ *
+ * Relative paths in a playlist are resolved against the
+ * directory of the playlist itself.
+ *
+ * The titles and durations a playlist carries are kept as
+ * hints, and shown until the real metadata of the songs
+ * is read.
+ */
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf, Component},
+    sync::{Mutex, OnceLock},
+    time::Duration
+};
+
+use super::PlayerError;
+
+mod m3u;
+mod pls;
+mod xspf;
+mod store;
+
+pub use store::PlaylistStore;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PlaylistEntry {
+    pub path: String,
+    pub title: Option<String>,
+    pub duration: Option<Duration>
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Playlist {
+    pub entries: Vec<PlaylistEntry>
+}
+
+pub trait PlaylistFormat: Sync {
+    /// Lowercase extensions of the format.
+    fn extensions(&self) -> &'static [&'static str];
+
+    /// Relative locations are resolved against `dir`.
+    fn parse(&self, content: &str, dir: &Path) -> Playlist;
+
+    /// Paths inside `dir` are written relative to it.
+    fn write(&self, playlist: &Playlist, dir: &Path) -> String;
+}
+
+static FORMATS: [&dyn PlaylistFormat; 3] = [&m3u::M3u, &pls::Pls, &xspf::Xspf];
+
+/// Find the format of a playlist file by its extension.
+pub fn format_of(path: &str) -> Option<&'static dyn PlaylistFormat> {
+    let ext = Path::new(path)
+        .extension()?
+        .to_string_lossy()
+        .to_ascii_lowercase();
+
+    FORMATS.iter()
+        .copied()
+        .find(|format| format.extensions().contains(&ext.as_str()))
+}
+
+#[inline]
+pub fn is_playlist(path: &str) -> bool {
+    format_of(path).is_some()
+}
+
+/// Resolve a location in a playlist to a path, locations
+/// other than local files are not supported.
+fn resolve(location: &str, dir: &Path) -> Option<String> {
+    let path = match location.strip_prefix("file://") {
+        Some(url) => PathBuf::from(percent_decode(url)),
+        None if location.contains("://") => return None,
+        None => PathBuf::from(location)
+    };
+
+    Some(dir.join(path).to_string_lossy().into_owned())
+}
+
+/// Make a path relative to `dir` if it is inside it.
+fn relative(path: &str, dir: &Path) -> String {
+    match Path::new(path).strip_prefix(dir) {
+        Ok(rel) if rel.components().all(|c| matches!(c, Component::Normal(_))) =>
+            rel.to_string_lossy().into_owned(),
+        _ => String::from(path)
+    }
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            },
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Escape everything but the unreserved characters and '/'.
+fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' =>
+                (b as char).to_string(),
+            _ => format!("%{:02X}", b)
+        })
+        .collect()
+}
+
+impl Playlist {
+    pub fn load(path: &str) -> Result<Self, PlayerError> {
+        let format = match format_of(path) {
+            None => return Err(PlayerError::WrongFileType(String::from(path))),
+            Some(format) => format
+        };
+
+        let content = match fs::read(path) {
+            Ok(content) => content,
+            Err(e) => return Err(PlayerError::IOError(e))
+        };
+        let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+
+        Ok(format.parse(&String::from_utf8_lossy(&content), dir))
+    }
+
+    /// The format is chosen by the extension of `path`.
+    pub fn save(&self, path: &str) -> Result<(), PlayerError> {
+        let format = match format_of(path) {
+            None => return Err(PlayerError::WrongFileType(String::from(path))),
+            Some(format) => format
+        };
+        let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+
+        fs::write(path, format.write(self, dir)).map_err(PlayerError::IOError)
+    }
+
+    /// Remember the titles and durations of the entries.
+    pub fn keep_hints(&self) {
+        let mut hints = hints().lock().unwrap();
+        self.entries
+            .iter()
+            .filter(|entry| entry.title.is_some() || entry.duration.is_some())
+            .for_each(|entry| {
+                hints.insert(entry.path.clone(), entry.clone());
+            });
+    }
+}
+
+fn hints() -> &'static Mutex<HashMap<String, PlaylistEntry>> {
+    static HINTS: OnceLock<Mutex<HashMap<String, PlaylistEntry>>> = OnceLock::new();
+    HINTS.get_or_init(Default::default)
+}
+
+/// What the playlists a song was loaded from tell about it.
+pub fn hint(path: &str) -> Option<PlaylistEntry> {
+    hints().lock().unwrap().get(path).cloned()
+}
@@ -0,0 +1,108 @@
+// Date: Tue Nov 14 19:52:08 2023
+// Mail: lunar_ubuntu@qq.com
+// Author: https://github.com/xiaoqixian
+
+/*
+ * PLS is an ini file with a single [playlist] section:
+ *
+ *   [playlist]
+ *   File1=李宗盛/寂寞难耐.mp3
+ *   Title1=李宗盛 - 寂寞难耐
+ *   Length1=254
+ *   NumberOfEntries=1
+ *   Version=2
+ */
+
+use std::{collections::BTreeMap, path::Path, time::Duration};
+
+use super::{Playlist, PlaylistEntry, PlaylistFormat, resolve, relative};
+
+pub struct Pls;
+
+impl PlaylistFormat for Pls {
+    fn extensions(&self) -> &'static [&'static str] {
+        &["pls"]
+    }
+
+    fn parse(&self, content: &str, dir: &Path) -> Playlist {
+        // entries by their numbers, which may not be in order
+        let mut entries = BTreeMap::<u32, PlaylistEntry>::new();
+
+        for line in content.trim_start_matches('\u{feff}').lines() {
+            let (key, value) = match line.split_once('=') {
+                None => continue,
+                Some((key, value)) => (key.trim(), value.trim())
+            };
+
+            let split = key.find(|c: char| c.is_ascii_digit()).unwrap_or(key.len());
+            let (field, number) = match key[split..].parse::<u32>() {
+                Err(_) => continue,
+                Ok(number) => (key[..split].to_ascii_lowercase(), number)
+            };
+
+            let entry = entries.entry(number).or_default();
+            match field.as_str() {
+                "file" => entry.path = resolve(value, dir).unwrap_or_default(),
+                "title" if !value.is_empty() => entry.title = Some(String::from(value)),
+                "length" => entry.duration = value
+                    .parse::<i64>()
+                    .ok()
+                    .filter(|&secs| secs >= 0)
+                    .map(|secs| Duration::from_secs(secs as u64)),
+                _ => {}
+            }
+        }
+
+        Playlist {
+            entries: entries
+                .into_values()
+                .filter(|entry| !entry.path.is_empty())
+                .collect()
+        }
+    }
+
+    fn write(&self, playlist: &Playlist, dir: &Path) -> String {
+        let mut content = String::from("[playlist]\n");
+
+        for (i, entry) in playlist.entries.iter().enumerate().map(|(i, e)| (i + 1, e)) {
+            content.push_str(&format!("File{}={}\n", i, relative(&entry.path, dir)));
+            if let Some(ref title) = entry.title {
+                content.push_str(&format!("Title{}={}\n", i, title));
+            }
+            let secs = entry.duration.map_or(-1, |d| d.as_secs() as i64);
+            content.push_str(&format!("Length{}={}\n", i, secs));
+        }
+
+        content.push_str(&format!("NumberOfEntries={}\nVersion=2\n", playlist.entries.len()));
+        content
+    }
+}
+
+#[test]
+fn test_pls_round_trip() {
+    let content = "[playlist]\n\
+        File2=/abs/山丘.flac\n\
+        File1=李宗盛/寂寞难耐.mp3\n\
+        Title1=李宗盛 - 寂寞难耐\n\
+        Length1=254\n\
+        Length2=-1\n\
+        NumberOfEntries=2\n\
+        Version=2\n";
+
+    let dir = Path::new("/music");
+    let playlist = Pls.parse(content, dir);
+    assert_eq!(playlist.entries, vec![
+        PlaylistEntry {
+            path: String::from("/music/李宗盛/寂寞难耐.mp3"),
+            title: Some(String::from("李宗盛 - 寂寞难耐")),
+            duration: Some(Duration::from_secs(254))
+        },
+        PlaylistEntry {
+            path: String::from("/abs/山丘.flac"),
+            title: None,
+            duration: None
+        }
+    ]);
+
+    assert_eq!(Pls.parse(&Pls.write(&playlist, dir), dir), playlist);
+}
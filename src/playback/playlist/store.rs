@@ -0,0 +1,178 @@
+// Date: Wed Nov 15 20:42:17 2023
+// Mail: lunar_ubuntu@qq.com
+// Author: https://github.com/xiaoqixian
+
+/*
+ * Named playlists are kept as M3U8 files in
+ * $XDG_DATA_HOME/tmusic/playlists, one file a playlist,
+ * named after the playlist. Nothing is cached, every
+ * change goes to the disk right away, so a playlist
+ * changed anywhere is seen everywhere.
+ */
+
+use std::{
+    fs,
+    path::PathBuf
+};
+
+use super::{Playlist, PlaylistEntry};
+use crate::{playback::PlayerError, xdg};
+
+const PLAYLIST_DIR: &str = "playlists";
+const EXTENSION: &str = "m3u8";
+
+#[derive(Debug, Clone)]
+pub struct PlaylistStore {
+    dir: Option<PathBuf>
+}
+
+impl PlaylistStore {
+    pub fn open() -> Self {
+        let dir = xdg::data_dir()
+            .map(|dir| dir.join(PLAYLIST_DIR))
+            .filter(|dir| fs::create_dir_all(dir).is_ok());
+
+        Self { dir }
+    }
+
+    fn dir(&self) -> Result<&PathBuf, PlayerError> {
+        self.dir.as_ref().ok_or_else(|| PlayerError::IOError(
+            std::io::Error::new(std::io::ErrorKind::NotFound, "no data directory")
+        ))
+    }
+
+    /// A name must be usable as a file name.
+    fn check_name(name: &str) -> Result<(), PlayerError> {
+        let name = name.trim();
+        match name.is_empty() || name.starts_with('.') || name.contains(['/', '\\', '\0']) {
+            true => Err(PlayerError::InvalidPlaylistName(String::from(name))),
+            false => Ok(())
+        }
+    }
+
+    /// The file of a playlist, it may not exist.
+    pub fn path(&self, name: &str) -> Result<String, PlayerError> {
+        let file = self.dir()?.join(format!("{}.{}", name.trim(), EXTENSION));
+        Ok(file.to_string_lossy().into_owned())
+    }
+
+    /// Names of all playlists, sorted.
+    pub fn names(&self) -> Vec<String> {
+        let entries = match self.dir.as_ref().map(fs::read_dir) {
+            Some(Ok(entries)) => entries,
+            _ => return Vec::new()
+        };
+
+        let mut names = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == EXTENSION))
+            .filter_map(|path| path.file_stem().map(|stem| stem.to_string_lossy().into_owned()))
+            .collect::<Vec<String>>();
+
+        names.sort();
+        names
+    }
+
+    pub fn load(&self, name: &str) -> Result<Playlist, PlayerError> {
+        Playlist::load(&self.path(name)?)
+    }
+
+    fn save(&self, name: &str, playlist: &Playlist) -> Result<(), PlayerError> {
+        playlist.save(&self.path(name)?)
+    }
+
+    pub fn create(&self, name: &str) -> Result<(), PlayerError> {
+        Self::check_name(name)?;
+        if self.names().iter().any(|n| n == name.trim()) {
+            return Err(PlayerError::InvalidPlaylistName(String::from(name)));
+        }
+
+        self.save(name, &Playlist::default())
+    }
+
+    pub fn rename(&self, old: &str, new: &str) -> Result<(), PlayerError> {
+        Self::check_name(new)?;
+        if self.names().iter().any(|n| n == new.trim()) {
+            return Err(PlayerError::InvalidPlaylistName(String::from(new)));
+        }
+
+        // the relative paths in it stay valid in the same directory
+        fs::rename(self.path(old)?, self.path(new)?).map_err(PlayerError::IOError)
+    }
+
+    pub fn delete(&self, name: &str) -> Result<(), PlayerError> {
+        fs::remove_file(self.path(name)?).map_err(PlayerError::IOError)
+    }
+
+    pub fn append(&self, name: &str, path: String) -> Result<(), PlayerError> {
+        let mut playlist = self.load(name)?;
+        playlist.entries.push(PlaylistEntry {
+            path,
+            ..Default::default()
+        });
+        self.save(name, &playlist)
+    }
+
+    pub fn remove_entry(&self, name: &str, index: usize) -> Result<(), PlayerError> {
+        let mut playlist = self.load(name)?;
+        if index >= playlist.entries.len() {
+            return Err(PlayerError::IndexOutOfRange(index));
+        }
+
+        playlist.entries.remove(index);
+        self.save(name, &playlist)
+    }
+
+    /// Move the entry at `from` to `to`, the entries in
+    /// between are shifted by one.
+    pub fn move_entry(&self, name: &str, from: usize, to: usize) -> Result<(), PlayerError> {
+        let mut playlist = self.load(name)?;
+        let len = playlist.entries.len();
+        if from >= len || to >= len {
+            return Err(PlayerError::IndexOutOfRange(std::cmp::max(from, to)));
+        }
+
+        let entry = playlist.entries.remove(from);
+        playlist.entries.insert(to, entry);
+        self.save(name, &playlist)
+    }
+}
+
+#[test]
+fn test_store() {
+    let dir = std::env::temp_dir().join(format!("tmusic_playlists_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let store = PlaylistStore { dir: Some(dir.clone()) };
+    let paths = |name: &str| store.load(name).unwrap()
+        .entries
+        .into_iter()
+        .map(|entry| entry.path)
+        .collect::<Vec<String>>();
+
+    store.create("夜曲").unwrap();
+    store.create("散步").unwrap();
+    assert!(matches!(store.create("夜曲"), Err(PlayerError::InvalidPlaylistName(_))));
+    assert!(matches!(store.create(" "), Err(PlayerError::InvalidPlaylistName(_))));
+    assert!(matches!(store.create("a/b"), Err(PlayerError::InvalidPlaylistName(_))));
+    assert_eq!(store.names(), vec!["夜曲", "散步"]);
+
+    store.rename("散步", "跑步").unwrap();
+    assert!(matches!(store.rename("夜曲", "跑步"), Err(PlayerError::InvalidPlaylistName(_))));
+    assert_eq!(store.names(), vec!["夜曲", "跑步"]);
+
+    let songs = ["a.mp3", "b.mp3", "c.mp3"].map(|song| dir.join(song).to_string_lossy().into_owned());
+    songs.iter().for_each(|song| store.append("夜曲", song.clone()).unwrap());
+    assert_eq!(paths("夜曲"), songs);
+
+    store.move_entry("夜曲", 0, 2).unwrap();
+    assert_eq!(paths("夜曲"), [songs[1].as_str(), &songs[2], &songs[0]]);
+    assert!(matches!(store.move_entry("夜曲", 0, 3), Err(PlayerError::IndexOutOfRange(3))));
+    store.remove_entry("夜曲", 1).unwrap();
+    assert_eq!(paths("夜曲"), [songs[1].as_str(), &songs[0]]);
+
+    store.delete("跑步").unwrap();
+    assert!(store.append("跑步", songs[0].clone()).is_err());
+    assert_eq!(store.names(), vec!["夜曲"]);
+    let _ = fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,191 @@
+// Date: Tue Nov 14 21:15:43 2023
+// Mail: lunar_ubuntu@qq.com
+// Author: https://github.com/xiaoqixian
+
+/*
+ * XSPF, the XML Shareable Playlist Format:
+ *
+ *   <playlist version="1" xmlns="http://xspf.org/ns/0/">
+ *     <trackList>
+ *       <track>
+ *         <location>%E6%9D%8E%E5%AE%97%E7%9B%9B/...</location>
+ *         <title>寂寞难耐</title>
+ *         <creator>李宗盛</creator>
+ *         <duration>254000</duration>
+ *       </track>
+ *     </trackList>
+ *   </playlist>
+ *
+ * Locations are URIs, durations are in milliseconds.
+ */
+
+use std::{path::Path, time::Duration};
+
+use quick_xml::{
+    events::Event,
+    escape::escape,
+    Reader
+};
+
+use super::{
+    Playlist,
+    PlaylistEntry,
+    PlaylistFormat,
+    resolve,
+    relative,
+    percent_decode,
+    percent_encode
+};
+
+pub struct Xspf;
+
+#[derive(Default)]
+struct Track {
+    location: Option<String>,
+    title: Option<String>,
+    creator: Option<String>,
+    duration: Option<Duration>
+}
+
+impl Track {
+    fn into_entry(self, dir: &Path) -> Option<PlaylistEntry> {
+        let location = self.location?;
+        // relative locations are relative URIs
+        let path = match location.contains("://") {
+            true => resolve(&location, dir)?,
+            false => resolve(&percent_decode(&location), dir)?
+        };
+
+        let title = match (self.creator, self.title) {
+            (Some(creator), Some(title)) => Some(format!("{} - {}", creator, title)),
+            (None, title) => title,
+            (creator, None) => creator
+        };
+
+        Some(PlaylistEntry {
+            path,
+            title,
+            duration: self.duration
+        })
+    }
+}
+
+impl PlaylistFormat for Xspf {
+    fn extensions(&self) -> &'static [&'static str] {
+        &["xspf"]
+    }
+
+    fn parse(&self, content: &str, dir: &Path) -> Playlist {
+        let mut reader = Reader::from_str(content);
+        reader.trim_text(true);
+
+        let mut entries = Vec::new();
+        let mut track: Option<Track> = None;
+        let mut element = Vec::<u8>::new();
+
+        loop {
+            match reader.read_event() {
+                // a broken document keeps what is read so far
+                Err(_) | Ok(Event::Eof) => break,
+                Ok(Event::Start(e)) => {
+                    element = e.local_name().as_ref().to_vec();
+                    if element == b"track" {
+                        track = Some(Track::default());
+                    }
+                },
+                Ok(Event::End(e)) => {
+                    if e.local_name().as_ref() == b"track" {
+                        if let Some(entry) = track.take().and_then(|t| t.into_entry(dir)) {
+                            entries.push(entry);
+                        }
+                    }
+                    element.clear();
+                },
+                Ok(Event::Text(text)) => {
+                    let (Some(track), Ok(text)) = (track.as_mut(), text.unescape()) else {
+                        continue;
+                    };
+                    let text = text.into_owned();
+
+                    match element.as_slice() {
+                        b"location" if track.location.is_none() => track.location = Some(text),
+                        b"title" => track.title = Some(text),
+                        b"creator" => track.creator = Some(text),
+                        b"duration" => track.duration = text
+                            .parse::<u64>()
+                            .ok()
+                            .map(Duration::from_millis),
+                        _ => {}
+                    }
+                },
+                _ => {}
+            }
+        }
+
+        Playlist { entries }
+    }
+
+    fn write(&self, playlist: &Playlist, dir: &Path) -> String {
+        let mut content = String::from(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+            <playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">\n  <trackList>\n"
+        );
+
+        for entry in playlist.entries.iter() {
+            let path = relative(&entry.path, dir);
+            let location = match Path::new(&path).is_absolute() {
+                true => format!("file://{}", percent_encode(&path)),
+                false => percent_encode(&path)
+            };
+
+            content.push_str("    <track>\n");
+            content.push_str(&format!("      <location>{}</location>\n", escape(&location)));
+            if let Some(ref title) = entry.title {
+                content.push_str(&format!("      <title>{}</title>\n", escape(title)));
+            }
+            if let Some(duration) = entry.duration {
+                content.push_str(&format!("      <duration>{}</duration>\n", duration.as_millis()));
+            }
+            content.push_str("    </track>\n");
+        }
+
+        content.push_str("  </trackList>\n</playlist>\n");
+        content
+    }
+}
+
+#[test]
+fn test_xspf_round_trip() {
+    let content = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <playlist version="1" xmlns="http://xspf.org/ns/0/">
+          <title>theme &amp; songs</title>
+          <trackList>
+            <track>
+              <location>%E6%9D%8E%E5%AE%97%E7%9B%9B/a%20b.mp3</location>
+              <title>寂寞难耐</title>
+              <creator>李宗盛</creator>
+              <duration>254000</duration>
+            </track>
+            <track>
+              <location>file:///abs/%E5%B1%B1%E4%B8%98.flac</location>
+            </track>
+          </trackList>
+        </playlist>"#;
+
+    let dir = Path::new("/music");
+    let playlist = Xspf.parse(content, dir);
+    assert_eq!(playlist.entries, vec![
+        PlaylistEntry {
+            path: String::from("/music/李宗盛/a b.mp3"),
+            title: Some(String::from("李宗盛 - 寂寞难耐")),
+            duration: Some(Duration::from_secs(254))
+        },
+        PlaylistEntry {
+            path: String::from("/abs/山丘.flac"),
+            title: None,
+            duration: None
+        }
+    ]);
+
+    assert_eq!(Xspf.parse(&Xspf.write(&playlist, dir), dir), playlist);
+}
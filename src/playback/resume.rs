@@ -0,0 +1,192 @@
+// Date: Wed Nov  8 20:12:45 2023
+// Mail: lunar_ubuntu@qq.com
+// Author: https://github.com/xiaoqixian
+
+/*
+ * ResumeStore remembers where each long file was stopped,
+ * and the bookmarks the user put in it. Audiobooks and
+ * podcasts are resumed there the next time they are played.
+ *
+ * Records are keyed by the device and inode of the file,
+ * which survive renames within a filesystem. The last known
+ * path is kept as well, so a record is still found after
+ * the file is copied or moved across filesystems.
+ */
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    time::Duration
+};
+
+use serde::{Serialize, Deserialize};
+
+pub const RESUME_FILE: &str = "resume.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bookmark {
+    pub name: String,
+    pub position_secs: u64
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct ResumeRecord {
+    path: String,
+    position_secs: u64,
+    bookmarks: Vec<Bookmark>
+}
+
+#[derive(Debug, Default)]
+pub struct ResumeStore {
+    records: HashMap<String, ResumeRecord>,
+    file: Option<PathBuf>
+}
+
+/// A stable identity of a file, None if it does not exist.
+#[cfg(unix)]
+pub fn file_key(path: &Path) -> Option<String> {
+    use std::os::unix::fs::MetadataExt;
+
+    let meta = fs::metadata(path).ok()?;
+    Some(format!("{}:{}", meta.dev(), meta.ino()))
+}
+
+#[cfg(not(unix))]
+pub fn file_key(path: &Path) -> Option<String> {
+    fs::canonicalize(path)
+        .ok()
+        .map(|path| path.to_string_lossy().into_owned())
+}
+
+impl ResumeStore {
+    pub fn open(file: Option<PathBuf>) -> Self {
+        let records = file.as_ref()
+            .and_then(|file| fs::read_to_string(file).ok())
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+
+        Self {
+            records,
+            file
+        }
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let file = match self.file {
+            None => return Ok(()),
+            Some(ref file) => file
+        };
+
+        let tmp = file.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_string(&self.records).unwrap())?;
+        fs::rename(&tmp, file)
+    }
+
+    /// Find the record of a file, by its identity first and
+    /// by its last known path then. A record found by path
+    /// is moved to the new identity.
+    fn record_mut(&mut self, path: &str) -> Option<&mut ResumeRecord> {
+        let key = file_key(Path::new(path))?;
+
+        if !self.records.contains_key(&key) {
+            let old_key = self.records
+                .iter()
+                .find(|(_, record)| record.path == path)
+                .map(|(old_key, _)| old_key.clone())?;
+            let record = self.records.remove(&old_key).unwrap();
+            self.records.insert(key.clone(), record);
+        }
+
+        let record = self.records.get_mut(&key).unwrap();
+        record.path = String::from(path);
+        Some(record)
+    }
+
+    fn record_or_insert(&mut self, path: &str) -> Option<&mut ResumeRecord> {
+        if self.record_mut(path).is_none() {
+            let key = file_key(Path::new(path))?;
+            self.records.insert(key, ResumeRecord {
+                path: String::from(path),
+                ..Default::default()
+            });
+        }
+        self.record_mut(path)
+    }
+
+    pub fn position(&mut self, path: &str) -> Option<Duration> {
+        self.record_mut(path)
+            .map(|record| Duration::from_secs(record.position_secs))
+            .filter(|pos| !pos.is_zero())
+    }
+
+    pub fn set_position(&mut self, path: &str, position: Duration) {
+        if let Some(record) = self.record_or_insert(path) {
+            record.position_secs = position.as_secs();
+        }
+    }
+
+    /// Forget the position of a file played to its end,
+    /// the record is dropped if no bookmark is left in it.
+    pub fn clear_position(&mut self, path: &str) {
+        let key = match file_key(Path::new(path)) {
+            None => return,
+            Some(key) => key
+        };
+
+        if let Some(record) = self.record_mut(path) {
+            record.position_secs = 0;
+            if record.bookmarks.is_empty() {
+                self.records.remove(&key);
+            }
+        }
+    }
+
+    pub fn bookmarks(&mut self, path: &str) -> Vec<Bookmark> {
+        self.record_mut(path)
+            .map(|record| record.bookmarks.clone())
+            .unwrap_or_default()
+    }
+
+    /// Bookmarks are kept sorted by their positions.
+    pub fn add_bookmark(&mut self, path: &str, bookmark: Bookmark) {
+        if let Some(record) = self.record_or_insert(path) {
+            let index = record.bookmarks
+                .partition_point(|b| b.position_secs <= bookmark.position_secs);
+            record.bookmarks.insert(index, bookmark);
+        }
+    }
+
+    pub fn remove_bookmark(&mut self, path: &str, index: usize) -> Option<Bookmark> {
+        let record = self.record_mut(path)?;
+        if index < record.bookmarks.len() {
+            Some(record.bookmarks.remove(index))
+        } else {
+            None
+        }
+    }
+}
+
+#[test]
+fn test_bookmarks_sorted() {
+    let mut store = ResumeStore::open(None);
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml");
+
+    [("b", 90), ("a", 30), ("c", 600)].into_iter().for_each(|(name, secs)| {
+        store.add_bookmark(path, Bookmark {
+            name: String::from(name),
+            position_secs: secs
+        });
+    });
+
+    let names = store.bookmarks(path)
+        .into_iter()
+        .map(|b| b.name)
+        .collect::<Vec<String>>();
+    assert_eq!(names, ["a", "b", "c"]);
+
+    store.set_position(path, Duration::from_secs(42));
+    store.clear_position(path);
+    assert_eq!(store.position(path), None);
+    assert_eq!(store.bookmarks(path).len(), 3);
+}
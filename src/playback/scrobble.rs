@@ -0,0 +1,259 @@
+// Date: Tue Dec  5 19:48:27 2023
+// Mail: lunar_ubuntu@qq.com
+// Author: https://github.com/xiaoqixian
+
+/*
+ * Plays are logged for scrobbling later from another
+ * machine, in the .scrobbler.log format of Rockbox, which
+ * is Audioscrobbler 1.1:
+ *
+ *   #AUDIOSCROBBLER/1.1
+ *   #TZ/UTC
+ *   #CLIENT/tmusic 0.1.0
+ *   artist	album	title	track	duration	L	timestamp	mbid
+ *
+ * with the fields separated by tabs, the album, the track
+ * number and the MusicBrainz id left empty if unknown, and
+ * the timestamp when the song was started.
+ *
+ * A play is logged by the rules of Last.fm: the song is
+ * longer than 30 seconds, and was listened to for half of
+ * it or for 4 minutes. The time listened is counted in the
+ * samples played, so the time paused does not count, and
+ * seeking neither adds nor takes away. Songs without an
+ * artist or a title cannot be scrobbled and are left out.
+ *
+ * The log is only ever appended to, the scrobbles in it
+ * can be exported as a ListenBrainz import.
+ */
+
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    thread::JoinHandle
+};
+
+use kanal::Receiver;
+
+use super::{
+    Notice,
+    metadata::{self, Tags},
+    history::HistoryEntry
+};
+use crate::config::config;
+
+pub const CLIENT: &str = "tmusic";
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Songs this short are never scrobbled.
+const MIN_DURATION_SECS: u64 = 30;
+/// Listened this long, a song is scrobbled even if it is
+/// not half way through.
+const ENOUGH_SECS: u64 = 4 * 60;
+
+/// Whether a finished song was listened to long enough.
+pub fn qualifies(entry: &HistoryEntry) -> bool {
+    let listened = entry.played_secs;
+    match entry.duration_secs {
+        Some(duration) => duration > MIN_DURATION_SECS
+            && (listened * 2 >= duration || listened >= ENOUGH_SECS),
+        // long enough is longer than 30 seconds anyway
+        None => listened >= ENOUGH_SECS
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scrobble {
+    pub artist: String,
+    pub album: Option<String>,
+    pub title: String,
+    pub track: Option<u32>,
+    pub duration_secs: u64,
+    /// when the song was started, in unix seconds
+    pub timestamp: u64
+}
+
+/// Tabs and line breaks would break the line.
+fn field(text: &str) -> String {
+    text.replace(['\t', '\n', '\r'], " ")
+}
+
+impl Scrobble {
+    /// None if the song was not listened to long enough,
+    /// or the tags tell too little.
+    pub fn of(entry: &HistoryEntry, tags: &Tags) -> Option<Self> {
+        if !qualifies(entry) {
+            return None;
+        }
+
+        Some(Self {
+            artist: tags.artist.clone()?,
+            album: tags.album.clone(),
+            title: tags.title.clone()?,
+            track: tags.track,
+            duration_secs: entry.duration_secs.unwrap_or(entry.played_secs),
+            timestamp: entry.started_at
+        })
+    }
+
+    /// The line in the log, without the line break.
+    pub fn line(&self) -> String {
+        [
+            field(&self.artist),
+            self.album.as_deref().map(field).unwrap_or_default(),
+            field(&self.title),
+            self.track.map(|track| track.to_string()).unwrap_or_default(),
+            self.duration_secs.to_string(),
+            String::from("L"),
+            self.timestamp.to_string(),
+            String::new()
+        ].join("\t")
+    }
+
+    /// Parse a line of the log, None for the headers, the
+    /// songs skipped and anything not understood.
+    pub fn parse(line: &str) -> Option<Self> {
+        let fields = line.split('\t').collect::<Vec<&str>>();
+        match fields.as_slice() {
+            [artist, album, title, track, duration, "L", timestamp, ..] if !line.starts_with('#') =>
+                Some(Self {
+                    artist: String::from(*artist),
+                    album: Some(String::from(*album)).filter(|album| !album.is_empty()),
+                    title: String::from(*title),
+                    track: track.parse().ok(),
+                    duration_secs: duration.parse().ok()?,
+                    timestamp: timestamp.parse().ok()?
+                }),
+            _ => None
+        }
+    }
+
+    /// The listen ListenBrainz takes, see
+    /// https://listenbrainz.readthedocs.io/en/latest/users/json.html
+    fn listen(&self) -> serde_json::Value {
+        let mut listen = serde_json::json!({
+            "listened_at": self.timestamp,
+            "track_metadata": {
+                "artist_name": self.artist,
+                "track_name": self.title,
+                "additional_info": {
+                    "duration_ms": self.duration_secs * 1000,
+                    "media_player": CLIENT,
+                    "submission_client": CLIENT,
+                    "submission_client_version": VERSION
+                }
+            }
+        });
+
+        let metadata = &mut listen["track_metadata"];
+        if let Some(ref album) = self.album {
+            metadata["release_name"] = serde_json::json!(album);
+        }
+        if let Some(track) = self.track {
+            metadata["additional_info"]["tracknumber"] = serde_json::json!(track);
+        }
+        listen
+    }
+}
+
+/// Append a scrobble to the log, the headers go first
+/// if the log is new.
+pub fn append(log: &Path, scrobble: &Scrobble) -> io::Result<()> {
+    let new = fs::metadata(log).map_or(true, |meta| meta.len() == 0);
+    let mut f = OpenOptions::new().create(true).append(true).open(log)?;
+
+    if new {
+        write!(f, "#AUDIOSCROBBLER/1.1\n#TZ/UTC\n#CLIENT/{} {}\n", CLIENT, VERSION)?;
+    }
+    writeln!(f, "{}", scrobble.line())
+}
+
+/// The scrobbles in the log, in the order they were logged.
+pub fn read_log(log: &Path) -> io::Result<Vec<Scrobble>> {
+    Ok(fs::read_to_string(log)?
+        .lines()
+        .filter_map(Scrobble::parse)
+        .collect())
+}
+
+/// The scrobbles as a ListenBrainz import, which is what
+/// its API takes to submit listens from the past.
+pub fn listenbrainz(scrobbles: &[Scrobble]) -> String {
+    let import = serde_json::json!({
+        "listen_type": "import",
+        "payload": scrobbles.iter().map(Scrobble::listen).collect::<Vec<_>>()
+    });
+    serde_json::to_string_pretty(&import).unwrap_or_default()
+}
+
+/// Log the songs finished from now on that qualify, until
+/// the player lets its subscribers go. Nothing is spawned
+/// without a log.
+pub fn spawn(notices: Receiver<Notice>) -> Option<JoinHandle<()>> {
+    let log = config().scrobble.log_path()?;
+
+    Some(std::thread::spawn(move || {
+        while let Ok(notice) = notices.recv() {
+            let entry = match notice {
+                Notice::Played(entry) if qualifies(&entry) => entry,
+                _ => continue
+            };
+
+            let tags = metadata::track_info(&entry.path).tags;
+            if let Some(scrobble) = Scrobble::of(&entry, &tags) {
+                let _ = append(&log, &scrobble);
+            }
+        }
+    }))
+}
+
+#[test]
+fn test_scrobble_log() {
+    use super::history::{PlaySource, PlayStatus};
+
+    let entry = |played_secs, duration_secs| HistoryEntry {
+        path: String::from("/m/fishmans/long season.flac"),
+        started_at: 1700000000,
+        played_secs,
+        duration_secs,
+        status: PlayStatus::Skipped,
+        source: PlaySource::Context
+    };
+    assert!(qualifies(&entry(100, Some(200))));
+    assert!(!qualifies(&entry(99, Some(200))));
+    assert!(qualifies(&entry(240, Some(2100))));
+    assert!(!qualifies(&entry(20, Some(25))));
+    assert!(!qualifies(&entry(200, None)));
+
+    let tags = Tags {
+        title: Some(String::from("Long\tSeason")),
+        artist: Some(String::from("Fishmans")),
+        track: Some(1),
+        ..Tags::default()
+    };
+    let scrobble = Scrobble::of(&entry(1200, Some(2100)), &tags).unwrap();
+    assert_eq!(scrobble.line(), "Fishmans\t\tLong Season\t1\t2100\tL\t1700000000\t");
+    assert!(Scrobble::of(&entry(1200, Some(2100)), &Tags::default()).is_none());
+
+    let log = std::env::temp_dir().join(format!("tmusic_scrobbler_{}.log", std::process::id()));
+    let _ = fs::remove_file(&log);
+    append(&log, &scrobble).unwrap();
+    append(&log, &scrobble).unwrap();
+    let content = fs::read_to_string(&log).unwrap();
+    assert!(content.starts_with("#AUDIOSCROBBLER/1.1\n#TZ/UTC\n"));
+    assert_eq!(content.lines().count(), 5);
+
+    let read = read_log(&log).unwrap();
+    assert_eq!(read.len(), 2);
+    assert_eq!(read[0].title, "Long Season");
+    assert_eq!(read[0].album, None);
+    let _ = fs::remove_file(&log);
+
+    let import = serde_json::from_str::<serde_json::Value>(&listenbrainz(&read)).unwrap();
+    assert_eq!(import["listen_type"], "import");
+    let listen = &import["payload"][0];
+    assert_eq!(listen["listened_at"], 1700000000);
+    assert_eq!(listen["track_metadata"]["additional_info"]["tracknumber"], 1);
+    assert!(listen["track_metadata"].get("release_name").is_none());
+}
@@ -0,0 +1,82 @@
+// Date: Mon Nov  6 21:48:02 2023
+// Mail: lunar_ubuntu@qq.com
+// Author: https://github.com/xiaoqixian
+
+/*
+ * A Session is everything needed to bring the PlayQueue
+ * back to where the user left: both lanes, the history
+ * cursor, the current song and its offset, and the modes.
+ * It is saved on exit and periodically, and restored
+ * paused at the next launch.
+ */
+
+use std::{
+    fs,
+    io,
+    path::Path
+};
+
+use serde::{Serialize, Deserialize};
+
+use super::{Lane, playable};
+
+pub const SESSION_FILE: &str = "session.json";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    pub user: Vec<String>,
+    pub context: Vec<String>,
+    pub current_lane: Option<Lane>,
+    pub history_cursor: Option<usize>,
+    pub current: Option<String>,
+    pub offset_secs: u64,
+    pub repeat: bool,
+    pub shuffle: bool,
+    pub volume: f32
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self {
+            user: Vec::new(),
+            context: Vec::new(),
+            current_lane: None,
+            history_cursor: None,
+            current: None,
+            offset_secs: 0,
+            repeat: false,
+            shuffle: false,
+            volume: 1.0
+        }
+    }
+}
+
+impl Session {
+    /// None if there is no session saved, or it is broken.
+    pub fn load(path: &Path) -> Option<Self> {
+        let content = fs::read_to_string(path).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    /// The session is written to a temporary file first and
+    /// then renamed, so a crash never leaves half a session.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let tmp = path.with_extension("json.tmp");
+        let content = serde_json::to_string(self).map_err(io::Error::other)?;
+
+        fs::write(&tmp, content)?;
+        fs::rename(&tmp, path)
+    }
+
+    /// Drop the songs that are gone since the session was saved.
+    pub fn retain_existing(&mut self) {
+        let exists = |path: &String| playable(path);
+
+        self.user.retain(exists);
+        self.context.retain(exists);
+        if !self.current.as_ref().is_some_and(exists) {
+            self.current = None;
+            self.offset_secs = 0;
+        }
+    }
+}
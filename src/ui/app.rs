@@ -4,7 +4,6 @@
 
 use super::nested::Nested;
 use tui::layout::Constraint;
-use super::component::Component;
 
 pub fn new() -> Nested {
     Nested::new(Constraint::Min(10))
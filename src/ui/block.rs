@@ -66,6 +66,16 @@ where C: Component
         }
     }
 
+    /// The title given, or else the one of the inner component.
+    fn shown_title(&self) -> Option<String> {
+        if self.title.is_some() {
+            return self.title.clone();
+        }
+        match self.inner.query(Query::Title) {
+            QueryResponse::Title(title) => title,
+            _ => None
+        }
+    }
 }
 
 impl<C> Component for Block<C>
@@ -74,7 +84,7 @@ where C: Component
     fn query(&self, q: Query) -> QueryResponse {
         match q {
             Query::Constraint => 
-                QueryResponse::Constraint(self.constraint.clone()),
+                QueryResponse::Constraint(self.constraint),
             q => self.inner.query(q)
         }
     }
@@ -103,11 +113,7 @@ where C: Component
             .borders(Borders::ALL)
             .border_style(self.border_style())
             .border_type(self.border_type())
-            .title(
-                self.title.as_ref()
-                .map(|title| title.as_str())
-                .unwrap_or("")
-            );
+            .title(self.shown_title().unwrap_or_default());
 
         block.render(area, buffer);
         self.inner.render(buffer);
@@ -129,11 +135,8 @@ where C: Component
         match self.inner.alter_mode(mode) {
             None => None,
             Some(comp_state) => {
-                match comp_state {
-                    CompState::Exit => {
-                        self.mode = CompMode::Hover;
-                    },
-                    _ => {}
+                if let CompState::Exit = comp_state {
+                    self.mode = CompMode::Hover;
                 }
                 Some(comp_state)
             }
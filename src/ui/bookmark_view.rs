@@ -0,0 +1,185 @@
+// Date: Thu Nov  9 21:03:38 2023
+// Mail: lunar_ubuntu@qq.com
+// Author: https://github.com/xiaoqixian
+
+use crossterm::event::{Event, KeyCode};
+
+use tui::{
+    layout::{Constraint, Rect},
+    widgets::{List, ListItem, ListState, StatefulWidget},
+    style::{Style, Color, Modifier},
+    text::{Span, Spans}
+};
+
+use kanal::{Receiver, Sender};
+
+use crate::playback::{
+    Notice,
+    Command,
+    resume::Bookmark
+};
+
+use super::component::{
+    CompState,
+    Component,
+    CompMode,
+    Query,
+    QueryResponse,
+    Attribution
+};
+
+/// BookmarkView lists the bookmarks of the current song.
+/// When entered, j/k moves the selection, Enter jumps to
+/// the selected bookmark, `a` bookmarks the current position
+/// and `d` deletes the selected bookmark.
+pub struct BookmarkView {
+    constraint: Constraint,
+    area: Option<Rect>,
+    mode: CompMode,
+    notices: Receiver<Notice>,
+    commands: Sender<Command>,
+    bookmarks: Vec<Bookmark>,
+    state: ListState
+}
+
+pub fn format_secs(secs: u64) -> String {
+    match secs {
+        0..=3599 => format!("{}:{:02}", secs / 60, secs % 60),
+        _ => format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    }
+}
+
+impl BookmarkView {
+    pub fn new(
+        c: Constraint,
+        bookmarks: Vec<Bookmark>,
+        notices: Receiver<Notice>,
+        commands: Sender<Command>
+    ) -> Self {
+        Self {
+            constraint: c,
+            area: None,
+            mode: CompMode::Leave,
+            notices,
+            commands,
+            bookmarks,
+            state: ListState::default()
+        }
+    }
+
+    fn drain_notices(&mut self) {
+        while let Ok(Some(notice)) = self.notices.try_recv() {
+            if let Notice::BookmarksChanged(bookmarks) = notice {
+                self.bookmarks = bookmarks;
+                self.fix_selection();
+            }
+        }
+    }
+
+    fn fix_selection(&mut self) {
+        let len = self.bookmarks.len();
+        match self.state.selected() {
+            Some(_) if len == 0 => self.state.select(None),
+            Some(i) if i >= len => self.state.select(Some(len - 1)),
+            None if len > 0 && matches!(self.mode, CompMode::Enter) =>
+                self.state.select(Some(0)),
+            _ => {}
+        }
+    }
+}
+
+impl Component for BookmarkView {
+    fn query(&self, q: Query) -> QueryResponse {
+        match q {
+            Query::Title => QueryResponse::Title(Some(String::from("书签"))),
+            Query::Constraint =>
+                QueryResponse::Constraint(self.constraint),
+            Query::UpdateDuration =>
+                QueryResponse::UpdateDuration(Some(std::time::Duration::from_millis(500)))
+        }
+    }
+
+    fn set_attr(&mut self, attr: Attribution) -> Option<CompState> {
+        match attr {
+            Attribution::Area(area) => {
+                self.area = Some(area);
+                None
+            },
+            Attribution::Mode(mode) => self.alter_mode(mode)
+        }
+    }
+
+    fn feed_event(&mut self, event: Event) -> CompState {
+        let len = self.bookmarks.len();
+
+        if let Event::Key(key_event) = event {
+            match key_event.code {
+                KeyCode::Esc => return CompState::Exit,
+                KeyCode::Down | KeyCode::Char('j') if len > 0 => {
+                    let selected = self.state.selected()
+                        .map(|i| std::cmp::min(len - 1, i + 1))
+                        .unwrap_or(0);
+                    self.state.select(Some(selected));
+                },
+                KeyCode::Up | KeyCode::Char('k') if len > 0 => {
+                    let selected = self.state.selected()
+                        .map(|i| std::cmp::max(1, i) - 1)
+                        .unwrap_or(0);
+                    self.state.select(Some(selected));
+                },
+                KeyCode::Enter => {
+                    if let Some(i) = self.state.selected() {
+                        let _ = self.commands.send(Command::JumpToBookmark(i));
+                    }
+                },
+                KeyCode::Char('a') => {
+                    let name = format!("书签 {}", len + 1);
+                    let _ = self.commands.send(Command::AddBookmark(name));
+                },
+                KeyCode::Char('d') => {
+                    if let Some(i) = self.state.selected() {
+                        let _ = self.commands.send(Command::RemoveBookmark(i));
+                    }
+                },
+                _ => {}
+            }
+        }
+
+        CompState::Stay
+    }
+
+    fn render(&mut self, buffer: &mut tui::buffer::Buffer) {
+        self.drain_notices();
+
+        let area = match self.area {
+            None => return,
+            Some(area) => area
+        };
+
+        let items = self.bookmarks
+            .iter()
+            .map(|bookmark| ListItem::new(Spans::from(vec![
+                Span::styled(
+                    format!("{:>8} ", format_secs(bookmark.position_secs)),
+                    Style::default().fg(Color::DarkGray)
+                ),
+                Span::raw(bookmark.name.clone())
+            ])))
+            .collect::<Vec<ListItem>>();
+
+        let highlight = match self.mode {
+            CompMode::Enter => Style::default().add_modifier(Modifier::REVERSED),
+            _ => Style::default()
+        };
+
+        List::new(items)
+            .highlight_style(highlight)
+            .render(area, buffer, &mut self.state);
+    }
+
+    fn alter_mode(&mut self, mode: CompMode) -> Option<CompState> {
+        self.mode = mode;
+        self.fix_selection();
+        None
+    }
+}
@@ -0,0 +1,355 @@
+// Date: Sat Dec  2 15:40:26 2023
+// Mail: lunar_ubuntu@qq.com
+// Author: https://github.com/xiaoqixian
+
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    sync::{Arc, RwLock}
+};
+
+use crossterm::event::{Event, KeyCode};
+
+use tui::{
+    layout::{Constraint, Direction, Rect},
+    widgets::{List, ListItem, ListState, StatefulWidget},
+    style::{Style, Color, Modifier}
+};
+
+use kanal::Sender;
+
+use crate::{
+    playback::{Command, display_name},
+    library::{
+        Library,
+        browse::{self, Album, Facet}
+    }
+};
+
+use super::{
+    nested::Nested,
+    component::{
+        CompState,
+        Component,
+        CompMode,
+        Query,
+        QueryResponse,
+        Attribution
+    }
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Pane {
+    Facets,
+    Artists,
+    Albums,
+    Tracks
+}
+
+impl Pane {
+    #[inline]
+    fn index(&self) -> usize {
+        *self as usize
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            Pane::Facets => "分类",
+            Pane::Artists => "艺术家",
+            Pane::Albums => "专辑",
+            Pane::Tracks => "曲目"
+        }
+    }
+}
+
+/// What the panes of the browser share: the albums of the
+/// selected facet, and the selection of every pane. A pane
+/// selecting something starts the panes after it over.
+struct Catalog {
+    library: Arc<RwLock<Library>>,
+    commands: Sender<Command>,
+    /// the generation of the library the albums are of
+    generation: Option<u64>,
+    /// genres if false, years if true
+    by_year: bool,
+    facets: Vec<Facet>,
+    albums: Vec<Album>,
+    artists: Vec<String>,
+    selected: [usize; 4]
+}
+
+impl Catalog {
+    fn refresh(&mut self) {
+        // the library is only being written for a moment
+        let library = self.library.clone();
+        let library = match library.try_read() {
+            Ok(library) => library,
+            Err(_) => return
+        };
+        if self.generation == Some(library.generation()) {
+            return;
+        }
+        self.generation = Some(library.generation());
+
+        let facet = self.facet();
+        self.facets = std::iter::once(Facet::All)
+            .chain(match self.by_year {
+                true => browse::years(&library),
+                false => browse::genres(&library)
+            })
+            .collect();
+        self.selected[0] = self.facets.iter().position(|f| *f == facet).unwrap_or(0);
+
+        self.albums = browse::albums(&library, &self.facet());
+        self.artists = browse::artists(&self.albums);
+        self.fix_selection();
+    }
+
+    fn facet(&self) -> Facet {
+        self.facets.get(self.selected[0]).cloned().unwrap_or(Facet::All)
+    }
+
+    fn artist_albums(&self) -> impl Iterator<Item = &Album> {
+        let artist = self.artists.get(self.selected[1]);
+        self.albums.iter().filter(move |album| Some(&album.artist) == artist)
+    }
+
+    fn album(&self) -> Option<&Album> {
+        self.artist_albums().nth(self.selected[2])
+    }
+
+    fn len(&self, pane: Pane) -> usize {
+        match pane {
+            Pane::Facets => self.facets.len(),
+            Pane::Artists => self.artists.len(),
+            Pane::Albums => self.artist_albums().count(),
+            Pane::Tracks => self.album().map_or(0, |album| album.tracks.len())
+        }
+    }
+
+    fn fix_selection(&mut self) {
+        for pane in [Pane::Facets, Pane::Artists, Pane::Albums, Pane::Tracks] {
+            let len = self.len(pane);
+            let selected = &mut self.selected[pane.index()];
+            *selected = std::cmp::min(*selected, len.saturating_sub(1));
+        }
+    }
+
+    fn select(&mut self, pane: Pane, index: usize) {
+        if self.selected[pane.index()] == index {
+            return;
+        }
+        self.selected[pane.index()] = index;
+        self.selected[pane.index() + 1..].iter_mut().for_each(|i| *i = 0);
+
+        // another facet takes other albums
+        if pane == Pane::Facets {
+            self.generation = None;
+            self.refresh();
+        }
+    }
+
+    fn toggle_facets(&mut self) {
+        self.by_year = !self.by_year;
+        self.selected = [0; 4];
+        self.generation = None;
+        self.refresh();
+    }
+
+    fn items(&self, pane: Pane) -> Vec<String> {
+        let unknown = |text: &str| match text {
+            "" => String::from("<未知>"),
+            text => String::from(text)
+        };
+
+        match pane {
+            Pane::Facets => self.facets
+                .iter()
+                .map(|facet| match facet {
+                    Facet::All => String::from("全部"),
+                    Facet::Genre(genre) => genre.clone(),
+                    Facet::Year(year) => year.to_string()
+                })
+                .collect(),
+            Pane::Artists => self.artists.iter().map(|artist| unknown(artist)).collect(),
+            Pane::Albums => self.artist_albums()
+                .map(|album| match album.year {
+                    Some(year) => format!("{} ({})", unknown(&album.title), year),
+                    None => unknown(&album.title)
+                })
+                .collect(),
+            Pane::Tracks => self.album()
+                .map(|album| album.tracks
+                    .iter()
+                    .map(|track| {
+                        let title = track.tags.title.clone()
+                            .unwrap_or_else(|| display_name(&track.path));
+                        match (track.tags.disc, track.tags.track) {
+                            (Some(disc), Some(number)) => format!("{}-{:02} {}", disc, number, title),
+                            (None, Some(number)) => format!("{:02} {}", number, title),
+                            _ => title
+                        }
+                    })
+                    .collect())
+                .unwrap_or_default()
+        }
+    }
+
+    /// The songs under the selection of a pane.
+    fn songs(&self, pane: Pane) -> Vec<String> {
+        match pane {
+            Pane::Facets => self.albums.iter().flat_map(Album::paths).collect(),
+            Pane::Artists => self.artist_albums().flat_map(Album::paths).collect(),
+            Pane::Albums => self.album().map(|album| album.paths().collect()).unwrap_or_default(),
+            Pane::Tracks => self.album()
+                .and_then(|album| album.tracks.get(self.selected[3]))
+                .map(|track| vec![track.path.clone()])
+                .unwrap_or_default()
+        }
+    }
+}
+
+/// A pane of the browser, a list over the catalog.
+///
+/// When entered, j/k moves the selection, `a` appends the
+/// songs under it to the queue and `p` replaces the context
+/// with them. On the facets `g` switches between genres and
+/// years, and on the tracks Enter plays the selected one.
+struct BrowsePane {
+    pane: Pane,
+    catalog: Rc<RefCell<Catalog>>,
+    constraint: Constraint,
+    area: Option<Rect>,
+    mode: CompMode,
+    state: ListState
+}
+
+impl Component for BrowsePane {
+    fn query(&self, q: Query) -> QueryResponse {
+        match q {
+            Query::Title => QueryResponse::Title(Some(String::from(self.pane.title()))),
+            Query::Constraint =>
+                QueryResponse::Constraint(self.constraint),
+            Query::UpdateDuration =>
+                QueryResponse::UpdateDuration(Some(std::time::Duration::from_secs(1)))
+        }
+    }
+
+    fn set_attr(&mut self, attr: Attribution) -> Option<CompState> {
+        match attr {
+            Attribution::Area(area) => {
+                self.area = Some(area);
+                None
+            },
+            Attribution::Mode(mode) => self.alter_mode(mode)
+        }
+    }
+
+    fn feed_event(&mut self, event: Event) -> CompState {
+        let code = match event {
+            Event::Key(key_event) => key_event.code,
+            _ => return CompState::Stay
+        };
+
+        let mut catalog = self.catalog.borrow_mut();
+        let len = catalog.len(self.pane);
+        let selected = catalog.selected[self.pane.index()];
+
+        match code {
+            KeyCode::Esc => return CompState::Exit,
+            KeyCode::Down | KeyCode::Char('j') if len > 0 =>
+                catalog.select(self.pane, std::cmp::min(len - 1, selected + 1)),
+            KeyCode::Up | KeyCode::Char('k') =>
+                catalog.select(self.pane, selected.saturating_sub(1)),
+            KeyCode::Char('g') if self.pane == Pane::Facets => catalog.toggle_facets(),
+            KeyCode::Char('a') | KeyCode::Char('p') => {
+                let paths = catalog.songs(self.pane);
+                if !paths.is_empty() {
+                    let _ = catalog.commands.send(Command::LoadSongs {
+                        paths,
+                        replace: code == KeyCode::Char('p')
+                    });
+                }
+            },
+            KeyCode::Enter if self.pane == Pane::Tracks => {
+                if let Some(path) = catalog.songs(self.pane).pop() {
+                    let _ = catalog.commands.send(Command::Play(path));
+                }
+            },
+            _ => {}
+        }
+
+        CompState::Stay
+    }
+
+    fn render(&mut self, buffer: &mut tui::buffer::Buffer) {
+        let area = match self.area {
+            None => return,
+            Some(area) => area
+        };
+
+        // the first pane brings the catalog up to date for all
+        let mut catalog = self.catalog.borrow_mut();
+        if self.pane == Pane::Facets {
+            catalog.refresh();
+        }
+
+        let items = catalog.items(self.pane)
+            .into_iter()
+            .map(ListItem::new)
+            .collect::<Vec<ListItem>>();
+        self.state.select(match items.is_empty() {
+            true => None,
+            false => Some(catalog.selected[self.pane.index()])
+        });
+        drop(catalog);
+
+        let highlight = match self.mode {
+            CompMode::Enter => Style::default().add_modifier(Modifier::REVERSED),
+            _ => Style::default().fg(Color::Yellow)
+        };
+
+        let list = List::new(items).highlight_style(highlight);
+        StatefulWidget::render(list, area, buffer, &mut self.state);
+    }
+
+    fn alter_mode(&mut self, mode: CompMode) -> Option<CompState> {
+        self.mode = mode;
+        None
+    }
+}
+
+/// The library browser: facets, artists, albums and tracks
+/// side by side, moved over by the hjkl of Nested.
+pub fn new(c: Constraint, library: Arc<RwLock<Library>>, commands: Sender<Command>) -> Nested {
+    let catalog = Rc::new(RefCell::new(Catalog {
+        library,
+        commands,
+        generation: None,
+        by_year: false,
+        facets: Vec::new(),
+        albums: Vec::new(),
+        artists: Vec::new(),
+        selected: [0; 4]
+    }));
+
+    let mut browser = Nested::new(c).direction(Direction::Horizontal);
+    let panes = [
+        (Pane::Facets, 18),
+        (Pane::Artists, 24),
+        (Pane::Albums, 28),
+        (Pane::Tracks, 30)
+    ];
+    for (pane, percentage) in panes {
+        browser.registrate(BrowsePane {
+            pane,
+            catalog: catalog.clone(),
+            constraint: Constraint::Percentage(percentage),
+            area: None,
+            mode: CompMode::Leave,
+            state: ListState::default()
+        }.block_with_title(String::from(pane.title())));
+    }
+
+    browser
+}
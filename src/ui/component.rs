@@ -128,47 +128,3 @@ pub trait Component {
             .title(title)
     }
 }
-
-/// A stateful component is a component 
-/// that carries a CompMode member.
-pub trait StatefulComponent {
-    fn comp_mode(&self) -> CompMode;
-}
-
-/// Provide a default border type/style getter
-/// for stateful components that need a frame
-/// whose border type/style is able to alternate
-/// with the component state.
-use tui::{
-    widgets::BorderType,
-    style::{Style, Color}
-};
-pub trait DefaultFrameStyle {
-    fn border_type(&self) -> BorderType;
-
-    fn border_style(&self) -> Style;
-}
-
-impl<C> DefaultFrameStyle for C
-where C: Component + StatefulComponent
-{
-    fn border_type(&self) -> BorderType {
-        match self.comp_mode() {
-            CompMode::Enter |
-            CompMode::Leave => BorderType::Rounded,
-
-            CompMode::Hover => BorderType::Double
-        }
-    }
-
-    fn border_style(&self) -> Style {
-        Style::default().fg(
-            match self.comp_mode() {
-                CompMode::Enter |
-                CompMode::Hover => Color::Blue,
-
-                CompMode::Leave => Color::White
-            }
-        )
-    }
-}
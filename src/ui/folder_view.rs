@@ -0,0 +1,379 @@
+// Date: Sun Dec  3 16:12:50 2023
+// Mail: lunar_ubuntu@qq.com
+// Author: https://github.com/xiaoqixian
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::{Duration, Instant, UNIX_EPOCH}
+};
+
+use crossterm::event::{Event, KeyCode};
+
+use tui::{
+    layout::{Constraint, Rect},
+    widgets::{List, ListItem, ListState, StatefulWidget},
+    style::{Style, Color, Modifier},
+    text::Span
+};
+
+use kanal::Sender;
+
+use crate::{
+    config::config,
+    playback::{Command, appendable, metadata},
+    library::{self, Library}
+};
+
+use super::component::{
+    CompState,
+    Component,
+    CompMode,
+    Query,
+    QueryResponse,
+    Attribution
+};
+
+/// The directory is read again at most this often,
+/// to show the files that come and go.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(2);
+
+struct Entry {
+    path: PathBuf,
+    /// what it is shown as
+    name: String,
+    is_dir: bool,
+    /// seconds since the unix epoch
+    mtime: u64
+}
+
+/// FolderView walks the filesystem from the configured root,
+/// or the working directory, whether the files are in the
+/// library or not. It lists the directories and the files
+/// the queue takes, the songs by their tags if the library
+/// or the tag cache has them and by their file names else.
+///
+/// When entered, j/k moves, Enter/l opens a directory or
+/// plays a song, h/Backspace goes to the parent, `a` appends
+/// the selected song, or every song under the selected
+/// directory, to the queue and `p` replaces the context with
+/// them. `.` shows the hidden files and `s` sorts by name or
+/// by modification time.
+pub struct FolderView {
+    constraint: Constraint,
+    area: Option<Rect>,
+    mode: CompMode,
+    library: Arc<RwLock<Library>>,
+    commands: Sender<Command>,
+    dir: PathBuf,
+    entries: Vec<Entry>,
+    state: ListState,
+    show_hidden: bool,
+    by_mtime: bool,
+    refreshed: Option<Instant>
+}
+
+impl FolderView {
+    pub fn new(c: Constraint, library: Arc<RwLock<Library>>, commands: Sender<Command>) -> Self {
+        Self {
+            constraint: c,
+            area: None,
+            mode: CompMode::Leave,
+            library,
+            commands,
+            dir: config().browser.root_path(),
+            entries: Vec::new(),
+            state: ListState::default(),
+            show_hidden: false,
+            by_mtime: false,
+            refreshed: None
+        }
+    }
+
+    fn name_of(&self, path: &Path) -> String {
+        let entry = path.to_string_lossy();
+        let tags = self.library
+            .try_read()
+            .ok()
+            .and_then(|library| library.get(&entry).map(|track| track.tags.clone()))
+            .or_else(|| metadata::cached_info(&entry).map(|info| info.tags));
+
+        match tags {
+            Some(tags) if tags.title.is_some() => metadata::TrackInfo {
+                path: entry.into_owned(),
+                tags
+            }.name(),
+            _ => path.file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        }
+    }
+
+    fn refresh(&mut self) {
+        let selected = self.state.selected()
+            .and_then(|i| self.entries.get(i))
+            .map(|entry| entry.path.clone());
+
+        let read = match fs::read_dir(&self.dir) {
+            Ok(read) => read,
+            Err(_) => {
+                self.entries.clear();
+                self.state.select(None);
+                self.refreshed = Some(Instant::now());
+                return;
+            }
+        };
+
+        let mut entries = read
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| self.show_hidden || !entry.file_name().to_string_lossy().starts_with('.'))
+            .filter_map(|entry| {
+                let path = entry.path();
+                // symlinks are followed, unlike in the library scan
+                let meta = fs::metadata(&path).ok()?;
+                let is_dir = meta.is_dir();
+                if !is_dir && !appendable(&path) {
+                    return None;
+                }
+
+                let mtime = meta.modified()
+                    .ok()
+                    .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                    .map_or(0, |d| d.as_secs());
+                let name = match is_dir {
+                    true => format!("{}/", entry.file_name().to_string_lossy()),
+                    false => self.name_of(&path)
+                };
+
+                Some(Entry { path, name, is_dir, mtime })
+            })
+            .collect::<Vec<Entry>>();
+
+        // directories first, the latest first by mtime
+        match self.by_mtime {
+            true => entries.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then(b.mtime.cmp(&a.mtime))),
+            false => entries.sort_by_cached_key(|entry| (
+                !entry.is_dir,
+                entry.path.file_name().map(|name| name.to_string_lossy().to_lowercase())
+            ))
+        }
+
+        self.entries = entries;
+        self.state.select(match self.entries.is_empty() {
+            true => None,
+            false => Some(selected
+                .and_then(|path| self.entries.iter().position(|entry| entry.path == path))
+                .unwrap_or(0))
+        });
+        self.refreshed = Some(Instant::now());
+    }
+
+    fn open(&mut self, dir: PathBuf) {
+        self.dir = dir;
+        self.state.select(None);
+        self.refresh();
+    }
+
+    /// Go up, with the directory left selected.
+    fn back(&mut self) {
+        let left = self.dir.clone();
+        if let Some(parent) = left.parent() {
+            self.open(parent.to_path_buf());
+            let index = self.entries.iter().position(|entry| entry.path == left);
+            self.state.select(index.or(self.state.selected()));
+        }
+    }
+
+    fn selected(&self) -> Option<&Entry> {
+        self.state.selected().and_then(|i| self.entries.get(i))
+    }
+
+    /// The selected song, or the songs under the selected
+    /// directory in the order of their paths.
+    fn songs(&self) -> Vec<String> {
+        match self.selected() {
+            None => Vec::new(),
+            Some(entry) if entry.is_dir => {
+                let mut files = Vec::new();
+                library::walk(&entry.path, &mut files);
+                files.sort();
+                files.into_iter()
+                    .map(|file| file.to_string_lossy().into_owned())
+                    .collect()
+            },
+            Some(entry) => vec![entry.path.to_string_lossy().into_owned()]
+        }
+    }
+}
+
+impl Component for FolderView {
+    fn query(&self, q: Query) -> QueryResponse {
+        match q {
+            Query::Title => QueryResponse::Title(Some(String::from("文件夹"))),
+            Query::Constraint =>
+                QueryResponse::Constraint(self.constraint),
+            Query::UpdateDuration =>
+                QueryResponse::UpdateDuration(Some(REFRESH_INTERVAL))
+        }
+    }
+
+    fn set_attr(&mut self, attr: Attribution) -> Option<CompState> {
+        match attr {
+            Attribution::Area(area) => {
+                self.area = Some(area);
+                None
+            },
+            Attribution::Mode(mode) => self.alter_mode(mode)
+        }
+    }
+
+    fn feed_event(&mut self, event: Event) -> CompState {
+        let code = match event {
+            Event::Key(key_event) => key_event.code,
+            _ => return CompState::Stay
+        };
+
+        let len = self.entries.len();
+        match code {
+            KeyCode::Esc => return CompState::Exit,
+            KeyCode::Down | KeyCode::Char('j') if len > 0 => {
+                let selected = self.state.selected()
+                    .map(|i| std::cmp::min(len - 1, i + 1))
+                    .unwrap_or(0);
+                self.state.select(Some(selected));
+            },
+            KeyCode::Up | KeyCode::Char('k') if len > 0 => {
+                let selected = self.state.selected()
+                    .map(|i| std::cmp::max(1, i) - 1)
+                    .unwrap_or(0);
+                self.state.select(Some(selected));
+            },
+            KeyCode::Enter | KeyCode::Right | KeyCode::Char('l') => match self.selected() {
+                Some(entry) if entry.is_dir => {
+                    let dir = entry.path.clone();
+                    self.open(dir);
+                },
+                Some(entry) => {
+                    let path = entry.path.to_string_lossy().into_owned();
+                    let _ = self.commands.send(Command::Play(path));
+                },
+                None => {}
+            },
+            KeyCode::Backspace | KeyCode::Left | KeyCode::Char('h') => self.back(),
+            KeyCode::Char('a') | KeyCode::Char('p') => {
+                let paths = self.songs();
+                if !paths.is_empty() {
+                    let _ = self.commands.send(Command::LoadSongs {
+                        paths,
+                        replace: code == KeyCode::Char('p')
+                    });
+                }
+            },
+            KeyCode::Char('.') => {
+                self.show_hidden = !self.show_hidden;
+                self.refresh();
+            },
+            KeyCode::Char('s') => {
+                self.by_mtime = !self.by_mtime;
+                self.refresh();
+            },
+            _ => {}
+        }
+
+        CompState::Stay
+    }
+
+    fn render(&mut self, buffer: &mut tui::buffer::Buffer) {
+        if self.refreshed.is_none_or(|at| at.elapsed() >= REFRESH_INTERVAL) {
+            self.refresh();
+        }
+
+        let area = match self.area {
+            None => return,
+            Some(area) => area
+        };
+
+        // the directory and how it is listed go at the top
+        let area = match area.height > 1 {
+            true => {
+                let flags = [
+                    self.show_hidden.then_some(" [隐藏]"),
+                    self.by_mtime.then_some(" [按时间]")
+                ];
+                let header = Span::styled(
+                    format!("{}{}", self.dir.display(), flags.into_iter().flatten().collect::<String>()),
+                    Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)
+                );
+                buffer.set_span(area.x, area.y, &header, area.width);
+                Rect { y: area.y + 1, height: area.height - 1, ..area }
+            },
+            false => area
+        };
+
+        let items = self.entries
+            .iter()
+            .map(|entry| match entry.is_dir {
+                true => ListItem::new(Span::styled(entry.name.clone(), Style::default().fg(Color::Blue))),
+                false => ListItem::new(entry.name.clone())
+            })
+            .collect::<Vec<ListItem>>();
+
+        let highlight = match self.mode {
+            CompMode::Enter => Style::default().add_modifier(Modifier::REVERSED),
+            _ => Style::default()
+        };
+
+        let list = List::new(items).highlight_style(highlight);
+        StatefulWidget::render(list, area, buffer, &mut self.state);
+    }
+
+    fn alter_mode(&mut self, mode: CompMode) -> Option<CompState> {
+        self.mode = mode;
+        None
+    }
+}
+
+#[test]
+fn test_listed_files() {
+    use crate::playback::expand;
+
+    let dir = std::env::temp_dir().join(format!("tmusic_folder_{}", std::process::id()));
+    fs::create_dir_all(dir.join("专辑")).unwrap();
+    let sheet = |backing: &str| format!("FILE \"{}\" WAVE\nTRACK 01 AUDIO\nINDEX 01 00:00:00\n", backing);
+    let files = [
+        ("01.wav", String::new()),
+        ("02.MP3", String::new()),
+        ("cover.jpg", String::new()),
+        ("notes.txt", String::new()),
+        ("list.m3u8", String::from("01.wav\n")),
+        ("album.flac", String::new()),
+        ("album.ape", String::new()),
+        ("flac.cue", sheet("album.flac")),
+        ("ape.cue", sheet("album.ape")),
+        ("missing.cue", sheet("missing.flac")),
+        (".hidden.wav", String::new())
+    ];
+    files.iter().for_each(|(name, content)| fs::write(dir.join(name), content).unwrap());
+
+    // the view lists exactly what the queue takes
+    for (name, _) in files.iter() {
+        let path = dir.join(name);
+        assert_eq!(appendable(&path), expand(path.to_string_lossy().into_owned()).is_ok(), "{}", name);
+    }
+
+    let (tx, _rx) = kanal::unbounded();
+    let mut view = FolderView::new(Constraint::Min(1), Arc::new(RwLock::new(Library::open(None))), tx);
+    view.open(dir.clone());
+    let listed = |view: &FolderView| view.entries
+        .iter()
+        .map(|entry| entry.path.file_name().unwrap().to_string_lossy().into_owned())
+        .collect::<Vec<String>>();
+    assert_eq!(listed(&view), ["专辑", "01.wav", "02.MP3", "album.flac", "flac.cue", "list.m3u8"]);
+
+    view.feed_event(Event::Key(KeyCode::Char('.').into()));
+    assert_eq!(listed(&view)[1], ".hidden.wav");
+    view.feed_event(Event::Key(KeyCode::Char('.').into()));
+    assert_eq!(listed(&view).len(), 6);
+    let _ = fs::remove_dir_all(&dir);
+}
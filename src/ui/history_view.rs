@@ -0,0 +1,223 @@
+// Date: Sat Nov  4 20:37:44 2023
+// Mail: lunar_ubuntu@qq.com
+// Author: https://github.com/xiaoqixian
+
+use crossterm::event::{Event, KeyCode};
+
+use tui::{
+    layout::{Constraint, Rect},
+    widgets::{List, ListItem, ListState, StatefulWidget},
+    style::{Style, Color, Modifier},
+    text::{Span, Spans}
+};
+
+use kanal::{Receiver, Sender};
+
+use crate::playback::{
+    display_name,
+    Notice,
+    Command,
+    history::{HistoryEntry, PlaySource, PlayStatus, unix_now}
+};
+
+use super::component::{
+    CompState,
+    Component,
+    CompMode,
+    Query,
+    QueryResponse,
+    Attribution
+};
+
+/// HistoryView lists the recently played songs, the
+/// latest on top. When entered, j/k moves the selection,
+/// Enter requeues the selected song and `s` opens the
+/// statistics of the history.
+pub struct HistoryView {
+    constraint: Constraint,
+    area: Option<Rect>,
+    mode: CompMode,
+    notices: Receiver<Notice>,
+    commands: Sender<Command>,
+    dashboard: Sender<()>,
+    entries: Vec<HistoryEntry>,
+    state: ListState
+}
+
+/// Format how long ago a unix timestamp is.
+fn elapsed_since(timestamp: u64) -> String {
+    let secs = unix_now().saturating_sub(timestamp);
+
+    match secs {
+        0..=59 => String::from("刚刚"),
+        60..=3599 => format!("{}分钟前", secs / 60),
+        3600..=86399 => format!("{}小时前", secs / 3600),
+        _ => format!("{}天前", secs / 86400)
+    }
+}
+
+impl HistoryView {
+    pub fn new(
+        c: Constraint,
+        entries: Vec<HistoryEntry>,
+        notices: Receiver<Notice>,
+        commands: Sender<Command>,
+        dashboard: Sender<()>
+    ) -> Self {
+        Self {
+            constraint: c,
+            area: None,
+            mode: CompMode::Leave,
+            notices,
+            commands,
+            dashboard,
+            entries,
+            state: ListState::default()
+        }
+    }
+
+    fn drain_notices(&mut self) {
+        while let Ok(Some(notice)) = self.notices.try_recv() {
+            if let Notice::Played(entry) = notice {
+                self.entries.push(entry);
+                // keep the selection on the same entry
+                if let Some(selected) = self.state.selected() {
+                    self.state.select(Some(selected + 1));
+                }
+            }
+        }
+    }
+
+    /// Entries are shown from the latest to the oldest.
+    #[inline]
+    fn entry_at(&self, index: usize) -> Option<&HistoryEntry> {
+        self.entries.iter().rev().nth(index)
+    }
+
+    fn item<'a>(entry: &HistoryEntry) -> ListItem<'a> {
+        let name = display_name(&entry.path);
+
+        let (status, color) = match entry.status {
+            PlayStatus::Completed => ("完成", Color::Green),
+            PlayStatus::Skipped => ("跳过", Color::Red),
+            PlayStatus::Playing => ("播放中", Color::Yellow)
+        };
+
+        let source = match entry.source {
+            PlaySource::Queue => "队列",
+            PlaySource::Context => "歌单",
+            PlaySource::Radio => "电台"
+        };
+
+        ListItem::new(Spans::from(vec![
+            Span::styled(
+                format!("{:<8}", elapsed_since(entry.started_at)),
+                Style::default().fg(Color::DarkGray)
+            ),
+            Span::raw(name),
+            Span::styled(
+                format!(" {}:{:02} ", entry.played_secs / 60, entry.played_secs % 60),
+                Style::default().fg(Color::DarkGray)
+            ),
+            Span::styled(status, Style::default().fg(color)),
+            Span::styled(format!(" {}", source), Style::default().fg(Color::DarkGray))
+        ]))
+    }
+}
+
+impl Component for HistoryView {
+    fn query(&self, q: Query) -> QueryResponse {
+        match q {
+            Query::Title => QueryResponse::Title(Some(String::from("最近播放"))),
+            Query::Constraint =>
+                QueryResponse::Constraint(self.constraint),
+            Query::UpdateDuration =>
+                QueryResponse::UpdateDuration(Some(std::time::Duration::from_secs(1)))
+        }
+    }
+
+    fn set_attr(&mut self, attr: Attribution) -> Option<CompState> {
+        match attr {
+            Attribution::Area(area) => {
+                self.area = Some(area);
+                None
+            },
+            Attribution::Mode(mode) => self.alter_mode(mode)
+        }
+    }
+
+    fn feed_event(&mut self, event: Event) -> CompState {
+        let len = self.entries.len();
+
+        if let Event::Key(key_event) = event {
+            match key_event.code {
+                KeyCode::Esc => return CompState::Exit,
+                KeyCode::Down | KeyCode::Char('j') if len > 0 => {
+                    let selected = self.state.selected()
+                        .map(|i| std::cmp::min(len - 1, i + 1))
+                        .unwrap_or(0);
+                    self.state.select(Some(selected));
+                },
+                KeyCode::Up | KeyCode::Char('k') if len > 0 => {
+                    let selected = self.state.selected()
+                        .map(|i| std::cmp::max(1, i) - 1)
+                        .unwrap_or(0);
+                    self.state.select(Some(selected));
+                },
+                KeyCode::Enter => {
+                    let path = self.state.selected()
+                        .and_then(|i| self.entry_at(i))
+                        .map(|entry| entry.path.clone());
+
+                    if let Some(path) = path {
+                        let _ = self.commands.send(Command::PlayNext(path));
+                    }
+                },
+                KeyCode::Char('s') => {
+                    let _ = self.dashboard.send(());
+                },
+                _ => {}
+            }
+        }
+
+        CompState::Stay
+    }
+
+    fn render(&mut self, buffer: &mut tui::buffer::Buffer) {
+        self.drain_notices();
+
+        let area = match self.area {
+            None => return,
+            Some(area) => area
+        };
+
+        // no need to build the items far below the selection
+        let shown = self.state.selected().unwrap_or(0) + area.height as usize;
+        let items = self.entries
+            .iter()
+            .rev()
+            .take(shown)
+            .map(Self::item)
+            .collect::<Vec<ListItem>>();
+
+        let highlight = match self.mode {
+            CompMode::Enter => Style::default().add_modifier(Modifier::REVERSED),
+            _ => Style::default()
+        };
+
+        List::new(items)
+            .highlight_style(highlight)
+            .render(area, buffer, &mut self.state);
+    }
+
+    fn alter_mode(&mut self, mode: CompMode) -> Option<CompState> {
+        self.mode = mode;
+
+        if let CompMode::Enter = mode {
+            if self.state.selected().is_none() && !self.entries.is_empty() {
+                self.state.select(Some(0));
+            }
+        }
+        None
+    }
+}
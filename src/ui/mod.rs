@@ -2,7 +2,11 @@
 // Mail: lunar_ubuntu@qq.com
 // Author: https://github.com/xiaoqixian
 
-use std::io;
+use std::{
+    io,
+    thread::JoinHandle,
+    time::{Duration, Instant}
+};
 
 use crossterm::{
     event::{self, DisableMouseCapture, EnableMouseCapture, Event},
@@ -17,34 +21,74 @@ use tui::{
 };
 
 
-use self::{progress_bar::ProgressBar, white_panel::WhitePanel};
+use self::progress_bar::{ProgressBar, Progress};
+
+use std::sync::{Arc, RwLock};
 
-use super::playback::PlayerError;
+use super::{
+    playback::{self, PlayerError, Playback, Command},
+    library::{self, Library, stats::{self, StatsBook}}
+};
 
 mod component;
 mod app;
 mod search_box;
+mod search_view;
+mod browse_view;
+mod folder_view;
 mod progress_bar;
 mod naked_nested;
 mod nested;
 mod block;
-mod white_panel;
+mod queue_view;
+mod history_view;
+mod bookmark_view;
+mod popup;
+mod playlist_view;
+mod playlist_picker;
+mod tag_editor;
+mod stats_dashboard;
 //mod single_widget;
 //mod time_sensitive;
 
 use component::{CompState, Component};
-use search_box::SearchBox;
+use search_box::{SearchBox, SearchEvent};
+use search_view::SearchView;
+use folder_view::FolderView;
+use queue_view::QueueView;
+use history_view::HistoryView;
+use bookmark_view::BookmarkView;
+use playlist_view::PlaylistView;
+use playlist_picker::PlaylistPicker;
+use tag_editor::TagEditor;
+use stats_dashboard::StatsDashboard;
+
+/// How long a failed command is shown in the progress bar.
+const FAILURE_SHOWN: Duration = Duration::from_secs(5);
 
 #[derive(Debug)]
 enum Error {
-    PlayerError(PlayerError),
     IOError(std::io::Error)
 }
 
+/// Like PlaylistView::report, a failed command is told
+/// to the user rather than bringing the whole ui down.
+fn describe(e: &PlayerError) -> String {
+    match e {
+        PlayerError::IOError(e) => format!("出错了: {}", e),
+        PlayerError::InvalidPlaylistName(name) => format!("歌单名不可用: {}", name),
+        PlayerError::TagError(e) => format!("标签出错: {}", e),
+        PlayerError::UnsupportedFormat(file) => format!("不支持的格式: {}", file),
+        PlayerError::WrongFileType(file) => format!("无法播放: {}", file),
+        PlayerError::DecoderError(e) => format!("解码出错: {}", e),
+        PlayerError::IndexOutOfRange(index) => format!("没有第 {} 项", index + 1)
+    }
+}
+
 pub fn run() {
-    let _ = enable_raw_mode().expect("enable_raw_mode failed");
+    enable_raw_mode().expect("enable_raw_mode failed");
     let mut stdout = io::stdout();
-    let _ = execute!(stdout, EnterAlternateScreen, EnableMouseCapture).unwrap();
+    execute!(stdout, EnterAlternateScreen, EnableMouseCapture).unwrap();
     let mut terminal = Terminal::new(CrosstermBackend::new(stdout)).expect("create terminal failed");
 
     let res = inner_run(&mut terminal);
@@ -57,12 +101,24 @@ pub fn run() {
     ).expect("leave execution failed");
     terminal.show_cursor().expect("show cursor failed");
 
-    if let Err(e) = res {
-        panic!("error: {:?}", e);
+    if let Err(Error::IOError(e)) = res {
+        panic!("error: {}", e);
     }
 
 }
 
+/// Threads joined when dropped.
+#[derive(Default)]
+struct Joined(Vec<JoinHandle<()>>);
+
+impl Drop for Joined {
+    fn drop(&mut self) {
+        self.0.drain(..).for_each(|handle| {
+            let _ = handle.join();
+        });
+    }
+}
+
 fn inner_run<B: Backend>(terminal: &mut Terminal<B>) -> Result<(), Error> {
     let size = match terminal.size() {
         Err(e) => return Err(Error::IOError(e)),
@@ -70,38 +126,153 @@ fn inner_run<B: Backend>(terminal: &mut Terminal<B>) -> Result<(), Error> {
     };
 
     let mut app = app::new();
-    let sb = SearchBox::new(Constraint::Length(3))
-        .block_with_title(String::from("搜索栏"));
-    //let pb = ProgressBar::new(Constraint::Length(3));
-    let pb = WhitePanel::new(Constraint::Length(3)).block();
     let mut panel = naked_nested::NakedNested::new(Constraint::Min(3))
         .direction(tui::layout::Direction::Horizontal);
 
-    let wp1 = WhitePanel::new(Constraint::Percentage(30))
-        .block();
-    let wp2 = WhitePanel::new(Constraint::Percentage(70))
+    // the threads following the notices, declared before the
+    // player so that they are joined after it is dropped and
+    // the last song finished is counted before tmusic exits
+    let mut followers = Joined::default();
+    // the player must live as long as the ui does
+    let mut player = playback::player();
+
+    let (command_sender, command_receiver) = kanal::unbounded::<Command>();
+    let (progress_sender, progress_receiver) = kanal::unbounded::<Progress>();
+    let (pick_sender, pick_receiver) = kanal::unbounded::<String>();
+    let (edit_sender, edit_receiver) = kanal::unbounded::<Vec<String>>();
+    let (search_sender, search_receiver) = kanal::unbounded::<SearchEvent>();
+    let (dashboard_sender, dashboard_receiver) = kanal::unbounded::<()>();
+    let mut picker = PlaylistPicker::new(pick_receiver);
+
+    // bring the library index up to date in the background,
+    // and let the queue follow the files moved afterwards
+    let library = Arc::new(RwLock::new(Library::open_default()));
+    let move_sender = command_sender.clone();
+    library::spawn(library.clone(), move |from, to| {
+        let _ = move_sender.send(Command::Repoint { from, to });
+    });
+    let mut editor = TagEditor::new(edit_receiver, library.clone());
+    let mut dashboard = StatsDashboard::new(
+        dashboard_receiver,
+        player.get_history(),
+        player.subscribe(),
+        library.clone()
+    );
+
+    // ratings and play counts kept so far, and the
+    // counts of the songs finished from now on
+    let stats = Arc::new(RwLock::new(StatsBook::open_default(&player.get_history())));
+    followers.0.push(stats::spawn(stats.clone(), player.subscribe()));
+    // the plays qualifying for scrobbling are logged
+    followers.0.extend(playback::scrobble::spawn(player.subscribe()));
+
+    let sb = SearchBox::new(Constraint::Length(4), search_sender)
+        .block_with_title(String::from("搜索栏"));
+
+    let pb = ProgressBar::new(
+        Constraint::Length(3),
+        player.chapters(),
+        progress_receiver,
+        player.subscribe(),
+        command_sender.clone(),
+        pick_sender,
+        stats.clone()
+    ).block();
+
+    let mut queue_panel = naked_nested::NakedNested::new(Constraint::Percentage(30));
+    let qv = QueueView::new(
+        Constraint::Percentage(60),
+        player.get_playlist(),
+        player.subscribe(),
+        command_sender.clone(),
+        edit_sender
+    )
+        .block_with_title(String::from("播放队列"));
+    let plv = PlaylistView::new(
+        Constraint::Percentage(40),
+        command_sender.clone(),
+        library.clone(),
+        stats.clone()
+    ).block_with_title(String::from("歌单"));
+    queue_panel.registrate(qv);
+    queue_panel.registrate(plv);
+    let mut middle_panel = naked_nested::NakedNested::new(Constraint::Percentage(45));
+    let sv = SearchView::new(
+        Constraint::Percentage(30),
+        search_receiver,
+        command_sender.clone(),
+        library.clone(),
+        stats.clone()
+    ).block_with_title(String::from("搜索结果"));
+    // titled by the pane it shows
+    let browser = browse_view::new(Constraint::Percentage(40), library.clone(), command_sender.clone())
         .block();
+    let fv = FolderView::new(Constraint::Percentage(30), library.clone(), command_sender.clone())
+        .block_with_title(String::from("文件夹"));
+    middle_panel.registrate(sv);
+    middle_panel.registrate(browser);
+    middle_panel.registrate(fv);
+    let mut side_panel = naked_nested::NakedNested::new(Constraint::Percentage(25));
+    let hv = HistoryView::new(
+        Constraint::Percentage(70),
+        player.get_history(),
+        player.subscribe(),
+        command_sender.clone(),
+        dashboard_sender
+    ).block_with_title(String::from("最近播放"));
+    let bv = BookmarkView::new(
+        Constraint::Percentage(30),
+        player.bookmarks(),
+        player.subscribe(),
+        command_sender.clone()
+    ).block_with_title(String::from("书签"));
+
+    side_panel.registrate(hv);
+    side_panel.registrate(bv);
 
-    panel.registrate(wp1);
-    panel.registrate(wp2);
+    panel.registrate(queue_panel);
+    panel.registrate(middle_panel);
+    panel.registrate(side_panel);
 
     app.registrate(sb);
     app.registrate(pb);
     app.registrate(panel);
-    app.set_area(terminal.size().unwrap());
+    app.set_area(size);
     app.alter_mode(component::CompMode::Enter);
     
+    // the last command failed and when
+    let mut failure: Option<(String, Instant)> = None;
     'run: loop {
+        // commands come from the components and the library
+        // watcher, so they are carried out every round
+        while let Ok(Some(command)) = command_receiver.try_recv() {
+            if let Err(e) = command.apply(&mut player) {
+                failure = Some((describe(&e), Instant::now()));
+            }
+        }
+        if failure.as_ref().is_some_and(|(_, at)| at.elapsed() >= FAILURE_SHOWN) {
+            failure = None;
+        }
+
+        let _ = progress_sender.send(Progress {
+            failure: failure.as_ref().map(|(failure, _)| failure.clone()),
+            ..Progress::of(&player)
+        });
         app.render(terminal.current_buffer_mut());
+        picker.render(terminal.current_buffer_mut());
+        editor.render(terminal.current_buffer_mut());
+        dashboard.render(terminal.current_buffer_mut());
         let min_update_duration = app.update_duration()
-            .unwrap_or(std::time::Duration::from_secs(100));
+            .unwrap_or(Duration::from_secs(100));
 
         if let Err(e) = terminal.draw(|_| {}) {
             return Err(Error::IOError(e));
         }
 
         match event::poll(min_update_duration) {
-            Ok(_) => match event::read() {
+            // nothing happened, just render again
+            Ok(false) => continue 'run,
+            Ok(true) => match event::read() {
                 Err(e) => return Err(Error::IOError(e)),
                 Ok(ev) => {
                     if let Event::Resize(width, height) = ev {
@@ -110,10 +281,17 @@ fn inner_run<B: Backend>(terminal: &mut Terminal<B>) -> Result<(), Error> {
                         continue 'run;
                     }
 
-                    match app.feed_event(ev) {
-                        CompState::Exit => break 'run,
-                        _ => {}
+                    // the popups take all events while they are open
+                    if picker.is_open() {
+                        picker.feed_event(ev);
+                    } else if editor.is_open() {
+                        editor.feed_event(ev);
+                    } else if dashboard.is_open() {
+                        dashboard.feed_event(ev);
+                    } else if let CompState::Exit = app.feed_event(ev) {
+                        break 'run;
                     }
+
                 }
             },
             Err(e) => return Err(Error::IOError(e))
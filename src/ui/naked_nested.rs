@@ -51,7 +51,7 @@ impl NakedNested {
         self
     }
 
-    pub fn registrate<T>(&mut self, mut comp: T) 
+    pub fn registrate<T>(&mut self, comp: T) 
     where T: Component + Sized + 'static
     {
         self.inner_comps
@@ -133,7 +133,7 @@ impl Component for NakedNested {
     fn query(&self, q: Query) -> QueryResponse {
         match q {
             Query::Title => QueryResponse::Title(None),
-            Query::Constraint => QueryResponse::Constraint(self.constraint.clone()),
+            Query::Constraint => QueryResponse::Constraint(self.constraint),
             Query::UpdateDuration => {
                 QueryResponse::UpdateDuration(self.update_duration())
             }
@@ -185,16 +185,15 @@ impl Component for NakedNested {
             Direction::Horizontal => false
         };
 
-        match event {
-            Event::Key(key_event) => match key_event.code {
+        if let Event::Key(key_event) = event {
+            match key_event.code {
                 KeyCode::Esc => return CompState::ExitIgnore,
                 KeyCode::Enter => {
                     if let Some(comp_state) = self.inner_comps[hover_index]
                         .alter_mode(CompMode::Enter)
                     {
-                        match comp_state {
-                            CompState::Exit => {},
-                            _ => self.cursor = CursorMode::Entered(hover_index)
+                        if !matches!(comp_state, CompState::Exit) {
+                            self.cursor = CursorMode::Entered(hover_index);
                         }
                     }
                 },
@@ -217,8 +216,7 @@ impl Component for NakedNested {
                 _ => {
                     return CompState::ExitIgnore;
                 }
-            },
-            _ => {}
+            }
         }
 
         CompState::Stay
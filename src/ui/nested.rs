@@ -130,7 +130,7 @@ impl Component for Nested {
     fn query(&self, q: Query) -> QueryResponse {
         match q {
             Query::Title => QueryResponse::Title(None),
-            Query::Constraint => QueryResponse::Constraint(self.constraint.clone()),
+            Query::Constraint => QueryResponse::Constraint(self.constraint),
             Query::UpdateDuration => {
                 QueryResponse::UpdateDuration(self.update_duration())
             }
@@ -176,16 +176,15 @@ impl Component for Nested {
             CursorMode::Hover(i) => i
         };
 
-        match event {
-            Event::Key(key_event) => match key_event.code {
+        if let Event::Key(key_event) = event {
+            match key_event.code {
                 KeyCode::Esc => return CompState::Exit,
                 KeyCode::Enter => {
                     if let Some(comp_state) = self.inner_comps[hover_index]
                         .alter_mode(CompMode::Enter)
                     {
-                        match comp_state {
-                            CompState::Exit => {},
-                            _ => self.cursor = CursorMode::Entered(hover_index)
+                        if !matches!(comp_state, CompState::Exit) {
+                            self.cursor = CursorMode::Entered(hover_index);
                         }
                     }
                 },
@@ -217,8 +216,7 @@ impl Component for Nested {
                     self.leave(hover_index, new_hover);
                 }
                 _ => {}
-            },
-            _ => {}
+            }
         }
 
         CompState::Stay
@@ -226,7 +224,7 @@ impl Component for Nested {
 
     #[inline]
     fn render(&mut self, buffer: &mut tui::buffer::Buffer) {
-        if let None = self.area {
+        if self.area.is_none() {
             return;
         }
         self.inner_comps
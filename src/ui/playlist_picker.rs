@@ -0,0 +1,124 @@
+// Date: Thu Nov 16 20:08:14 2023
+// Mail: lunar_ubuntu@qq.com
+// Author: https://github.com/xiaoqixian
+
+use crossterm::event::{Event, KeyCode};
+
+use tui::{
+    widgets::{Block, Borders, BorderType, List, ListItem, Widget},
+    style::{Style, Color, Modifier}
+};
+
+use kanal::Receiver;
+
+use crate::playback::{display_name, playlist::PlaylistStore};
+
+use super::{describe, popup::Popup};
+
+/// PlaylistPicker pops up over the whole ui to pick the
+/// playlist a song is added to. It is opened by sending
+/// the song through its channel, and while it is open it
+/// takes all key events: j/k moves, Enter adds the song
+/// to the selected playlist and Esc cancels. If the song
+/// cannot be added, it stays open and tells why.
+///
+/// It is not part of the component tree, the ui loop
+/// renders it last so nothing is drawn over it.
+pub struct PlaylistPicker {
+    requests: Receiver<String>,
+    store: PlaylistStore,
+    song: Option<String>,
+    names: Vec<String>,
+    selected: usize,
+    error: Option<String>
+}
+
+impl PlaylistPicker {
+    pub fn new(requests: Receiver<String>) -> Self {
+        Self {
+            requests,
+            store: PlaylistStore::open(),
+            song: None,
+            names: Vec::new(),
+            selected: 0,
+            error: None
+        }
+    }
+
+    #[inline]
+    pub fn is_open(&self) -> bool {
+        self.song.is_some()
+    }
+
+    fn open(&mut self, song: String) {
+        self.song = Some(song);
+        self.names = self.store.names();
+        self.selected = 0;
+        self.error = None;
+    }
+
+    pub fn feed_event(&mut self, event: Event) {
+        let code = match event {
+            Event::Key(key_event) => key_event.code,
+            _ => return
+        };
+
+        match code {
+            KeyCode::Esc => self.song = None,
+            KeyCode::Down | KeyCode::Char('j') if !self.names.is_empty() =>
+                self.selected = std::cmp::min(self.names.len() - 1, self.selected + 1),
+            KeyCode::Up | KeyCode::Char('k') =>
+                self.selected = std::cmp::max(1, self.selected) - 1,
+            KeyCode::Enter => {
+                let (song, name) = match (self.song.take(), self.names.get(self.selected)) {
+                    (Some(song), Some(name)) => (song, name),
+                    _ => return
+                };
+                if let Err(e) = self.store.append(name, song.clone()) {
+                    self.error = Some(describe(&e));
+                    self.song = Some(song);
+                }
+            },
+            _ => {}
+        }
+    }
+
+    pub fn render(&mut self, buffer: &mut tui::buffer::Buffer) {
+        if let Ok(Some(song)) = self.requests.try_recv() {
+            self.open(song);
+        }
+
+        let song = match self.song {
+            None => return,
+            Some(ref song) => song
+        };
+
+        let mut items = match self.names.is_empty() {
+            true => vec![ListItem::new("还没有歌单, 在歌单栏按 n 新建")],
+            false => self.names
+                .iter()
+                .enumerate()
+                .map(|(i, name)| {
+                    let style = match i == self.selected {
+                        true => Style::default().add_modifier(Modifier::REVERSED),
+                        false => Style::default()
+                    };
+                    ListItem::new(name.clone()).style(style)
+                })
+                .collect()
+        };
+
+        if let Some(ref error) = self.error {
+            items.push(ListItem::new(error.clone()).style(Style::default().fg(Color::Red)));
+        }
+
+        let list = List::new(items).block(Block::default()
+            .title(format!("添加 {} 到歌单", display_name(song)))
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded)
+            .border_style(Style::default().fg(Color::Blue)));
+
+        let area = buffer.area;
+        Popup::new(list, 40, 50).render(area, buffer);
+    }
+}
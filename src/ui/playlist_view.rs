@@ -0,0 +1,585 @@
+// Date: Wed Nov 15 21:30:52 2023
+// Mail: lunar_ubuntu@qq.com
+// Author: https://github.com/xiaoqixian
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant}
+};
+
+use crossterm::event::{Event, KeyCode};
+
+use tui::{
+    layout::{Constraint, Rect},
+    widgets::{List, ListItem, ListState, StatefulWidget},
+    style::{Style, Color, Modifier},
+    text::{Span, Spans}
+};
+
+use kanal::Sender;
+
+use crate::{
+    playback::{
+        Command,
+        PlayerError,
+        display_name,
+        playable,
+        playlist::{PlaylistStore, PlaylistEntry}
+    },
+    library::{
+        Library,
+        search::SearchIndex,
+        smart::{SmartPlaylist, SmartStore},
+        stats::StatsBook
+    }
+};
+
+use super::component::{
+    CompState,
+    Component,
+    CompMode,
+    Query,
+    QueryResponse,
+    Attribution
+};
+
+/// The playlists are read again from the disk at most this
+/// often, as they may be changed by the playlist picker.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(2);
+
+enum Input {
+    Create(String),
+    Rename(String, String),
+    /// a new playlist the queue is saved to
+    Save(String),
+    /// `name = query`, in place of the smart playlist
+    /// named by the first if there is one
+    Smart(Option<String>, String)
+}
+
+/// What the view shows, the playlists or the entries of one.
+enum Level {
+    Playlists,
+    Entries(String),
+    /// the songs of a smart playlist
+    Smart(String)
+}
+
+/// PlaylistView manages the named playlists.
+///
+/// On the playlists: j/k moves, Enter/l opens the selected
+/// playlist, `p` loads it replacing the current context,
+/// `a` appends it to the queue, `n` creates a playlist,
+/// `r` renames and `D` deletes the selected one, `w` saves
+/// the current song and the queue as a new playlist.
+///
+/// On the entries of a playlist: j/k moves, J/K moves the
+/// selected entry down/up, `d` removes it, h/Esc goes back.
+///
+/// Smart playlists, see library/smart.rs, are listed after
+/// the named ones. `s` creates one as `name = query`, `e`
+/// edits the selected one, and they are opened, loaded and
+/// deleted like the named ones. Their songs are evaluated
+/// again when the library or the play statistics change.
+pub struct PlaylistView {
+    constraint: Constraint,
+    area: Option<Rect>,
+    mode: CompMode,
+    commands: Sender<Command>,
+    store: PlaylistStore,
+    smart_store: SmartStore,
+    library: Arc<RwLock<Library>>,
+    stats: Arc<RwLock<StatsBook>>,
+    /// built when there is a smart playlist to evaluate
+    index: Option<SearchIndex>,
+    level: Level,
+    names: Vec<String>,
+    smart: Vec<SmartPlaylist>,
+    /// the songs of the smart playlists, or why a query
+    /// does not parse
+    songs: HashMap<String, Result<Vec<String>, String>>,
+    /// generations of the library and the stats evaluated
+    evaluated: Option<(u64, u64)>,
+    entries: Vec<PlaylistEntry>,
+    state: ListState,
+    input: Option<Input>,
+    error: Option<String>,
+    refreshed: Option<Instant>
+}
+
+impl PlaylistView {
+    pub fn new(
+        c: Constraint,
+        commands: Sender<Command>,
+        library: Arc<RwLock<Library>>,
+        stats: Arc<RwLock<StatsBook>>
+    ) -> Self {
+        Self {
+            constraint: c,
+            area: None,
+            mode: CompMode::Leave,
+            commands,
+            store: PlaylistStore::open(),
+            smart_store: SmartStore::open(),
+            library,
+            stats,
+            index: None,
+            level: Level::Playlists,
+            names: Vec::new(),
+            smart: Vec::new(),
+            songs: HashMap::new(),
+            evaluated: None,
+            entries: Vec::new(),
+            state: ListState::default(),
+            input: None,
+            error: None,
+            refreshed: None
+        }
+    }
+
+    fn refresh(&mut self) {
+        self.names = self.store.names();
+
+        let smart = self.smart_store.list();
+        if smart != self.smart {
+            self.smart = smart;
+            self.evaluated = None;
+        }
+        self.evaluate();
+
+        if let Level::Smart(ref name) = self.level {
+            if !self.songs.contains_key(name) {
+                self.level = Level::Playlists;
+            }
+        }
+
+        if let Level::Entries(ref name) = self.level {
+            match self.store.load(name) {
+                Ok(playlist) => self.entries = playlist.entries,
+                // deleted or renamed elsewhere
+                Err(_) => {
+                    self.level = Level::Playlists;
+                    self.entries.clear();
+                }
+            }
+        }
+
+        self.refreshed = Some(Instant::now());
+        self.fix_selection();
+    }
+
+    /// Evaluate the smart playlists again if anything
+    /// they depend on changed since the last time.
+    fn evaluate(&mut self) {
+        if self.smart.is_empty() {
+            self.songs.clear();
+            return;
+        }
+
+        // the library is only being written for a moment
+        let library = match self.library.try_read() {
+            Ok(library) => library,
+            Err(_) => return
+        };
+        let stats = self.stats.read().unwrap();
+        let generations = (library.generation(), stats.generation());
+        if self.evaluated == Some(generations) {
+            return;
+        }
+
+        if self.index.as_ref().is_none_or(|index| index.generation() != library.generation()) {
+            self.index = Some(SearchIndex::build(&library));
+        }
+        drop(library);
+
+        let index = self.index.as_ref().unwrap();
+        self.songs = self.smart
+            .iter()
+            .map(|playlist| (
+                playlist.name.clone(),
+                playlist.evaluate(index, &stats).map_err(|e| e.to_string())
+            ))
+            .collect();
+        self.evaluated = Some(generations);
+    }
+
+    /// The songs of a smart playlist, none if its query is broken.
+    fn smart_songs(&self, name: &str) -> &[String] {
+        match self.songs.get(name) {
+            Some(Ok(songs)) => songs,
+            _ => &[]
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self.level {
+            Level::Playlists => self.names.len() + self.smart.len(),
+            Level::Entries(_) => self.entries.len(),
+            Level::Smart(ref name) => self.smart_songs(name).len()
+        }
+    }
+
+    fn fix_selection(&mut self) {
+        let len = self.len();
+        match self.state.selected() {
+            Some(_) if len == 0 => self.state.select(None),
+            Some(i) if i >= len => self.state.select(Some(len - 1)),
+            None if len > 0 && matches!(self.mode, CompMode::Enter) =>
+                self.state.select(Some(0)),
+            _ => {}
+        }
+    }
+
+    fn selected_name(&self) -> Option<String> {
+        self.state.selected().and_then(|i| self.names.get(i)).cloned()
+    }
+
+    /// The smart playlists are listed after the named ones.
+    fn selected_smart(&self) -> Option<SmartPlaylist> {
+        self.state.selected()
+            .and_then(|i| i.checked_sub(self.names.len()))
+            .and_then(|i| self.smart.get(i))
+            .cloned()
+    }
+
+    /// Errors are shown in the view rather than
+    /// bringing the whole ui down.
+    fn report(&mut self, res: Result<(), PlayerError>) {
+        self.error = match res {
+            Ok(_) => None,
+            Err(PlayerError::InvalidPlaylistName(name)) =>
+                Some(format!("歌单名不可用: {}", name)),
+            Err(e) => Some(format!("{:?}", e))
+        };
+        self.refresh();
+    }
+
+    fn feed_input(&mut self, code: KeyCode) {
+        let input = match self.input {
+            None => return,
+            Some(ref mut input) => input
+        };
+        let text = match input {
+            Input::Create(text) | Input::Rename(_, text) | Input::Save(text) | Input::Smart(_, text) => text
+        };
+
+        match code {
+            KeyCode::Esc => self.input = None,
+            KeyCode::Char(c) => text.push(c),
+            KeyCode::Backspace => {
+                let _ = text.pop();
+            },
+            KeyCode::Enter => {
+                let res = match self.input.take().unwrap() {
+                    Input::Create(name) => self.store.create(&name),
+                    Input::Rename(old, new) => self.store.rename(&old, &new),
+                    Input::Save(name) => self.save_queue(&name),
+                    Input::Smart(old, text) => return self.save_smart(old, text)
+                };
+                self.report(res);
+            },
+            _ => {}
+        }
+    }
+
+    /// The playlist is created first, so that the queue
+    /// is never saved over one that exists.
+    fn save_queue(&self, name: &str) -> Result<(), PlayerError> {
+        self.store.create(name)?;
+        let _ = self.commands.send(Command::SavePlaylist(self.store.path(name)?));
+        Ok(())
+    }
+
+    fn save_smart(&mut self, old: Option<String>, text: String) {
+        let (name, query) = text.split_once('=').unwrap_or((&text, ""));
+
+        match SmartPlaylist::parse(name, query) {
+            Ok(playlist) => {
+                let res = self.smart_store.put(old.as_deref(), playlist);
+                self.report(res);
+            },
+            // the input is kept for the query to be fixed
+            Err(e) => {
+                self.error = Some(format!("查询有误: {}", e));
+                self.input = Some(Input::Smart(old, text));
+            }
+        }
+    }
+
+    fn feed_smart(&mut self, code: KeyCode, playlist: SmartPlaylist) {
+        match code {
+            KeyCode::Enter | KeyCode::Char('l') => {
+                self.level = Level::Smart(playlist.name);
+                self.state.select(None);
+                self.refresh();
+            },
+            KeyCode::Char('p') | KeyCode::Char('a') => {
+                let _ = self.commands.send(Command::LoadSongs {
+                    paths: self.smart_songs(&playlist.name).to_vec(),
+                    replace: code == KeyCode::Char('p')
+                });
+            },
+            KeyCode::Char('e') => {
+                let text = format!("{} = {}", playlist.name, playlist.text());
+                self.input = Some(Input::Smart(Some(playlist.name), text));
+            },
+            KeyCode::Char('D') => {
+                let res = self.smart_store.delete(&playlist.name);
+                self.report(res);
+            },
+            _ => {}
+        }
+    }
+
+    fn feed_playlists(&mut self, code: KeyCode) {
+        if code == KeyCode::Char('s') {
+            self.input = Some(Input::Smart(None, String::new()));
+            return;
+        }
+        if let Some(playlist) = self.selected_smart() {
+            return self.feed_smart(code, playlist);
+        }
+
+        let selected = self.selected_name();
+
+        match (code, selected) {
+            (KeyCode::Enter | KeyCode::Char('l'), Some(name)) => {
+                self.level = Level::Entries(name);
+                self.state.select(None);
+                self.refresh();
+            },
+            (KeyCode::Char('p'), Some(name)) | (KeyCode::Char('a'), Some(name)) => {
+                match self.store.path(&name) {
+                    Ok(path) => {
+                        let _ = self.commands.send(Command::LoadPlaylist {
+                            path,
+                            replace: code == KeyCode::Char('p')
+                        });
+                    },
+                    Err(e) => self.report(Err(e))
+                }
+            },
+            (KeyCode::Char('n'), _) => self.input = Some(Input::Create(String::new())),
+            (KeyCode::Char('w'), _) => self.input = Some(Input::Save(String::new())),
+            (KeyCode::Char('r'), Some(name)) =>
+                self.input = Some(Input::Rename(name.clone(), name)),
+            (KeyCode::Char('D'), Some(name)) => {
+                let res = self.store.delete(&name);
+                self.report(res);
+            },
+            _ => {}
+        }
+    }
+
+    fn feed_entries(&mut self, code: KeyCode, name: String) {
+        let selected = self.state.selected();
+        let len = self.entries.len();
+
+        let res = match (code, selected) {
+            (KeyCode::Char('h') | KeyCode::Left, _) => {
+                self.level = Level::Playlists;
+                let index = self.names.iter().position(|n| *n == name);
+                self.state.select(index);
+                Ok(())
+            },
+            (KeyCode::Char('d'), Some(i)) => self.store.remove_entry(&name, i),
+            (KeyCode::Char('J'), Some(i)) if i + 1 < len => {
+                self.state.select(Some(i + 1));
+                self.store.move_entry(&name, i, i + 1)
+            },
+            (KeyCode::Char('K'), Some(i)) if i > 0 => {
+                self.state.select(Some(i - 1));
+                self.store.move_entry(&name, i, i - 1)
+            },
+            _ => return
+        };
+
+        self.report(res);
+    }
+
+    fn items(&self) -> Vec<ListItem<'static>> {
+        match self.level {
+            Level::Playlists => self.names
+                .iter()
+                .map(|name| ListItem::new(name.clone()))
+                .chain(self.smart.iter().map(|playlist| {
+                    let count = match self.songs.get(&playlist.name) {
+                        Some(Ok(songs)) => Span::styled(
+                            format!(" ({})", songs.len()),
+                            Style::default().fg(Color::DarkGray)
+                        ),
+                        Some(Err(_)) => Span::styled(" (查询有误)", Style::default().fg(Color::Red)),
+                        None => Span::raw("")
+                    };
+                    ListItem::new(Spans::from(vec![
+                        Span::styled("智能 ", Style::default().fg(Color::Cyan)),
+                        Span::raw(playlist.name.clone()),
+                        count
+                    ]))
+                }))
+                .collect(),
+            Level::Smart(ref name) => self.smart_songs(name)
+                .iter()
+                .map(|path| ListItem::new(display_name(path)))
+                .collect(),
+            Level::Entries(_) => self.entries
+                .iter()
+                .map(|entry| ListItem::new(Spans::from(vec![
+                    Span::raw(entry.title.clone().unwrap_or_else(|| display_name(&entry.path))),
+                    Span::styled(
+                        match playable(&entry.path) {
+                            true => "",
+                            false => " (缺失)"
+                        },
+                        Style::default().fg(Color::Red)
+                    )
+                ])))
+                .collect()
+        }
+    }
+}
+
+impl Component for PlaylistView {
+    fn query(&self, q: Query) -> QueryResponse {
+        match q {
+            Query::Title => QueryResponse::Title(Some(String::from("歌单"))),
+            Query::Constraint =>
+                QueryResponse::Constraint(self.constraint),
+            Query::UpdateDuration =>
+                QueryResponse::UpdateDuration(Some(std::time::Duration::from_millis(500)))
+        }
+    }
+
+    fn set_attr(&mut self, attr: Attribution) -> Option<CompState> {
+        match attr {
+            Attribution::Area(area) => {
+                self.area = Some(area);
+                None
+            },
+            Attribution::Mode(mode) => self.alter_mode(mode)
+        }
+    }
+
+    fn feed_event(&mut self, event: Event) -> CompState {
+        let code = match event {
+            Event::Key(key_event) => key_event.code,
+            _ => return CompState::Stay
+        };
+
+        if self.input.is_some() {
+            self.feed_input(code);
+            return CompState::Stay;
+        }
+
+        let len = self.len();
+        match code {
+            KeyCode::Esc | KeyCode::Char('h') | KeyCode::Left if matches!(self.level, Level::Smart(_)) => {
+                if let Level::Smart(name) = std::mem::replace(&mut self.level, Level::Playlists) {
+                    let index = self.smart.iter().position(|p| p.name == name);
+                    self.state.select(index.map(|i| i + self.names.len()));
+                }
+            },
+            KeyCode::Esc => match self.level {
+                Level::Playlists | Level::Smart(_) => return CompState::Exit,
+                Level::Entries(ref name) => {
+                    let name = name.clone();
+                    self.feed_entries(KeyCode::Char('h'), name);
+                }
+            },
+            KeyCode::Down | KeyCode::Char('j') if len > 0 => {
+                let selected = self.state.selected()
+                    .map(|i| std::cmp::min(len - 1, i + 1))
+                    .unwrap_or(0);
+                self.state.select(Some(selected));
+            },
+            KeyCode::Up | KeyCode::Char('k') if len > 0 => {
+                let selected = self.state.selected()
+                    .map(|i| std::cmp::max(1, i) - 1)
+                    .unwrap_or(0);
+                self.state.select(Some(selected));
+            },
+            code => match self.level {
+                Level::Playlists => self.feed_playlists(code),
+                Level::Smart(_) => {},
+                Level::Entries(ref name) => {
+                    let name = name.clone();
+                    self.feed_entries(code, name);
+                }
+            }
+        }
+
+        CompState::Stay
+    }
+
+    fn render(&mut self, buffer: &mut tui::buffer::Buffer) {
+        if self.refreshed.is_none_or(|at| at.elapsed() >= REFRESH_INTERVAL) {
+            self.refresh();
+        }
+
+        let area = match self.area {
+            None => return,
+            Some(area) => area
+        };
+
+        // the name of the opened playlist goes at the top
+        let area = match self.level {
+            Level::Entries(ref name) | Level::Smart(ref name) if area.height > 1 => {
+                let header = Span::styled(
+                    format!("< {}", name),
+                    Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)
+                );
+                buffer.set_span(area.x, area.y, &header, area.width);
+                Rect { y: area.y + 1, height: area.height - 1, ..area }
+            },
+            _ => area
+        };
+
+        // the input line and the last error go at the bottom
+        let footer = match (&self.input, &self.error) {
+            (Some(Input::Create(text)), _) =>
+                Some(Span::raw(format!("新建歌单: {}|", text))),
+            (Some(Input::Rename(old, text)), _) =>
+                Some(Span::raw(format!("重命名 {}: {}|", old, text))),
+            (Some(Input::Save(text)), _) =>
+                Some(Span::raw(format!("保存队列为歌单: {}|", text))),
+            (Some(Input::Smart(_, text)), Some(error)) => Some(Span::styled(
+                format!("{} {}|", error, text),
+                Style::default().fg(Color::Red)
+            )),
+            (Some(Input::Smart(_, text)), None) =>
+                Some(Span::raw(format!("智能歌单 (名称 = 查询): {}|", text))),
+            (None, Some(error)) =>
+                Some(Span::styled(error.clone(), Style::default().fg(Color::Red))),
+            (None, None) => None
+        };
+
+        let list_area = match footer {
+            None => area,
+            Some(footer) if area.height > 1 => {
+                let line = Rect::new(area.x, area.bottom() - 1, area.width, 1);
+                buffer.set_span(line.x, line.y, &footer, line.width);
+                Rect { height: area.height - 1, ..area }
+            },
+            Some(_) => area
+        };
+
+        let highlight = match self.mode {
+            CompMode::Enter => Style::default().add_modifier(Modifier::REVERSED),
+            _ => Style::default()
+        };
+
+        List::new(self.items())
+            .highlight_style(highlight)
+            .render(list_area, buffer, &mut self.state);
+    }
+
+    fn alter_mode(&mut self, mode: CompMode) -> Option<CompState> {
+        self.mode = mode;
+        if let CompMode::Enter = mode {
+            self.refresh();
+        }
+        self.fix_selection();
+        None
+    }
+}
@@ -2,46 +2,226 @@
 // Mail: lunar_ubuntu@qq.com
 // Author: https://github.com/xiaoqixian
 
+use std::{
+    sync::{Arc, RwLock},
+    time::Duration
+};
+
+use crossterm::event::{Event, KeyCode};
+
 use tui::{
-    layout::{Constraint, Direction, Rect},
-    widgets::{Widget, Gauge, Block, BorderType, Borders},
-    buffer::Buffer, style::{Style, Color}
+    layout::{Constraint, Rect},
+    widgets::{Widget, Gauge},
+    style::{Style, Color},
+    text::Span
+};
+
+use kanal::{Receiver, Sender};
+
+use crate::{
+    playback::{
+        Playback,
+        Notice,
+        Command,
+        TrackInfo,
+        chapters::{self, Chapter}
+    },
+    library::stats::{StatsBook, MAX_RATING}
 };
 
-use super::component::{
-    CompState, 
-    Component, 
-    CompMode,
-    Query,
-    QueryResponse,
-    Attribution
+use super::{
+    bookmark_view::format_secs,
+    component::{
+        CompState,
+        Component,
+        CompMode,
+        Query,
+        QueryResponse,
+        Attribution
+    }
 };
 
+/// How far the left and right keys seek.
+const SEEK_STEP: Duration = Duration::from_secs(10);
+
+/// How much the +/- keys change the volume,
+/// which goes from 0 up to the original volume.
+const VOLUME_STEP: f32 = 0.1;
+
+/// Where the player is, sent by the ui loop every round
+/// as the progress changes without any notice.
+#[derive(Debug, Clone, Default)]
+pub struct Progress {
+    pub song: Option<TrackInfo>,
+    pub position: Option<Duration>,
+    pub total: Option<Duration>,
+    pub paused: bool,
+    pub repeat: bool,
+    pub shuffle: bool,
+    pub volume: f32,
+    /// why the last command failed, shown for a while
+    pub failure: Option<String>
+}
+
+impl Progress {
+    pub fn of<P: Playback>(player: &P) -> Self {
+        Self {
+            song: player.get_song(),
+            position: player.progress(),
+            total: player.total_duration(),
+            paused: player.paused(),
+            repeat: player.repeat(),
+            shuffle: player.shuffle(),
+            volume: player.volume(),
+            failure: None
+        }
+    }
+}
+
+/// ProgressBar shows how far the current song is played,
+/// with a tick at the start of every chapter and the name
+/// of the current chapter, and how the song is rated. When
+/// entered, left/right seeks, `[`/`]` goes to the previous/
+/// next chapter, `n` skips the song, `b` goes back to the
+/// song played before and `a` picks a playlist to add the
+/// current song to. Space pauses or resumes, `r` and `s`
+/// turn repeat and shuffle on or off, and `+`/`-` change
+/// the volume. `1` to `5` rate the current song, `0` takes the
+/// rating away and `f` loves it or not anymore.
+///
+/// A command that failed is shown in place of the label
+/// for a few seconds.
 pub struct ProgressBar {
     constraint: Constraint,
-    title: Option<String>,
     area: Option<Rect>,
-    comp_mode: CompMode
+    comp_mode: CompMode,
+    progress_rx: Receiver<Progress>,
+    notices: Receiver<Notice>,
+    commands: Sender<Command>,
+    picker: Sender<String>,
+    stats: Arc<RwLock<StatsBook>>,
+    progress: Progress,
+    chapters: Vec<Chapter>
 }
 
 impl ProgressBar {
-    pub fn new(c: Constraint) -> Self {
+    pub fn new(
+        c: Constraint,
+        chapters: Vec<Chapter>,
+        progress_rx: Receiver<Progress>,
+        notices: Receiver<Notice>,
+        commands: Sender<Command>,
+        picker: Sender<String>,
+        stats: Arc<RwLock<StatsBook>>
+    ) -> Self {
         Self {
             constraint: c,
-            title: None,
             area: None,
-            comp_mode: CompMode::Leave
+            comp_mode: CompMode::Leave,
+            progress_rx,
+            notices,
+            commands,
+            picker,
+            stats,
+            progress: Progress::default(),
+            chapters
         }
     }
+
+    fn drain(&mut self) {
+        while let Ok(Some(progress)) = self.progress_rx.try_recv() {
+            self.progress = progress;
+        }
+
+        while let Ok(Some(notice)) = self.notices.try_recv() {
+            if let Notice::ChaptersChanged(chapters) = notice {
+                self.chapters = chapters;
+            }
+        }
+    }
+
+    fn label(&self) -> String {
+        let song = match self.progress.song {
+            None => return String::new(),
+            Some(ref song) => song.name()
+        };
+
+        let position = self.progress.position.unwrap_or_default();
+        let chapter = chapters::chapter_at(&self.chapters, position)
+            .map(|i| format!(" · {}", self.chapters[i].title))
+            .unwrap_or_default();
+
+        let time = match self.progress.total {
+            None => format_secs(position.as_secs()),
+            Some(total) => format!("{}/{}", format_secs(position.as_secs()), format_secs(total.as_secs()))
+        };
+
+        format!("{}{}{}  {}{}", song, chapter, self.rating(), time, self.modes())
+    }
+
+    /// The modes that are not as they start out.
+    fn modes(&self) -> String {
+        let flags = [
+            self.progress.paused.then_some(" [暂停]"),
+            self.progress.repeat.then_some(" [循环]"),
+            self.progress.shuffle.then_some(" [随机]")
+        ];
+        let volume = match (self.progress.volume * 100.0).round() as u32 {
+            100 => String::new(),
+            percent => format!(" 音量 {}%", percent)
+        };
+        format!("{}{}", flags.into_iter().flatten().collect::<String>(), volume)
+    }
+
+    /// Stars and a heart after the name, if there are any.
+    fn rating(&self) -> String {
+        let stats = self.progress.song.as_ref()
+            .and_then(|song| self.stats.try_read().ok()?.get(&song.path).copied())
+            .unwrap_or_default();
+
+        let stars = match stats.rating {
+            0 => String::new(),
+            rating => format!(" {}{}",
+                "★".repeat(rating as usize),
+                "☆".repeat((MAX_RATING - rating) as usize))
+        };
+        format!("{}{}", stars, if stats.loved { " ♥" } else { "" })
+    }
+
+    /// Change what is kept about the current song.
+    fn edit_stats(&self, edit: impl FnOnce(&mut StatsBook, &str)) {
+        if let Some(ref song) = self.progress.song {
+            let mut stats = self.stats.write().unwrap();
+            edit(&mut stats, &song.path);
+            let _ = stats.save();
+        }
+    }
+
+    fn seek_by(&self, forward: bool) {
+        let position = match self.progress.position {
+            None => return,
+            Some(position) => position
+        };
+
+        let target = match forward {
+            true => position + SEEK_STEP,
+            false => position.saturating_sub(SEEK_STEP)
+        };
+        let target = match self.progress.total {
+            Some(total) if target > total => total,
+            _ => target
+        };
+        let _ = self.commands.send(Command::Seek(target));
+    }
 }
 
 impl Component for ProgressBar {
     fn query(&self, q: Query) -> QueryResponse {
         match q {
             Query::Title => QueryResponse::Title(None),
-            Query::Constraint => 
-                QueryResponse::Constraint(self.constraint.clone()),
-            Query::UpdateDuration => 
+            Query::Constraint =>
+                QueryResponse::Constraint(self.constraint),
+            Query::UpdateDuration =>
                 QueryResponse::UpdateDuration(Some(std::time::Duration::from_secs(1)))
         }
     }
@@ -56,33 +236,109 @@ impl Component for ProgressBar {
         }
     }
 
-    /// Currently, ProgressBar don't accept any event
-    /// its not even enterable.
-    #[inline]
-    fn feed_event(&mut self, _: crossterm::event::Event) -> CompState {
-        CompState::Exit
+    fn feed_event(&mut self, event: Event) -> CompState {
+        if let Event::Key(key_event) = event {
+            match key_event.code {
+                KeyCode::Esc => return CompState::Exit,
+                KeyCode::Left | KeyCode::Char('h') => self.seek_by(false),
+                KeyCode::Right | KeyCode::Char('l') => self.seek_by(true),
+                KeyCode::Char('[') => {
+                    let _ = self.commands.send(Command::PrevChapter);
+                },
+                KeyCode::Char(']') => {
+                    let _ = self.commands.send(Command::NextChapter);
+                },
+                KeyCode::Char('n') => {
+                    let _ = self.commands.send(Command::Next);
+                },
+                KeyCode::Char('b') => {
+                    let _ = self.commands.send(Command::Prev);
+                },
+                KeyCode::Char(' ') => {
+                    let _ = self.commands.send(Command::SetPaused(!self.progress.paused));
+                },
+                KeyCode::Char('r') => {
+                    let _ = self.commands.send(Command::SetRepeat(!self.progress.repeat));
+                },
+                KeyCode::Char('s') => {
+                    let _ = self.commands.send(Command::SetShuffle(!self.progress.shuffle));
+                },
+                KeyCode::Char('+') | KeyCode::Char('=') => {
+                    let volume = (self.progress.volume + VOLUME_STEP).min(1.0);
+                    let _ = self.commands.send(Command::SetVolume(volume));
+                },
+                KeyCode::Char('-') => {
+                    let volume = (self.progress.volume - VOLUME_STEP).max(0.0);
+                    let _ = self.commands.send(Command::SetVolume(volume));
+                },
+                KeyCode::Char('a') => {
+                    if let Some(ref song) = self.progress.song {
+                        let _ = self.picker.send(song.path.clone());
+                    }
+                },
+                KeyCode::Char(c @ '0'..='5') => {
+                    let rating = c as u8 - b'0';
+                    self.edit_stats(|stats, path| stats.set_rating(path, rating));
+                },
+                KeyCode::Char('f') => self.edit_stats(|stats, path| {
+                    stats.toggle_loved(path);
+                }),
+                _ => {}
+            }
+        }
+
+        CompState::Stay
     }
 
     fn render(&mut self, buffer: &mut tui::buffer::Buffer) {
+        self.drain();
+
         let area = match self.area {
             None => return,
             Some(area) => area
         };
 
+        let ratio = match (self.progress.position, self.progress.total) {
+            (Some(position), Some(total)) if !total.is_zero() =>
+                (position.as_secs_f64() / total.as_secs_f64()).min(1.0),
+            _ => 0.0
+        };
+
+        let label = match self.progress.failure {
+            None => Span::raw(self.label()),
+            Some(ref failure) => Span::styled(failure.clone(), Style::default().fg(Color::Red))
+        };
+        let label_width = label.width() as u16;
+
         Gauge::default()
-            .style(Style::default().fg(Color::Yellow))
-            .percent(50)
+            .gauge_style(Style::default().fg(Color::Yellow))
+            .ratio(ratio)
+            .label(label)
             .render(area, buffer);
+
+        // a tick at the start of every chapter but the first,
+        // the label in the middle is left readable
+        let total = match self.progress.total {
+            Some(total) if !total.is_zero() && area.width > 0 => total,
+            _ => return
+        };
+        let y = area.y + area.height / 2;
+        let label_start = area.width.saturating_sub(label_width) / 2;
+        let label_range = label_start..label_start + label_width;
+
+        self.chapters
+            .iter()
+            .filter(|chapter| !chapter.start.is_zero() && chapter.start < total)
+            .map(|chapter| (chapter.start.as_secs_f64() / total.as_secs_f64() * area.width as f64) as u16)
+            .filter(|offset| !label_range.contains(offset))
+            .for_each(|offset| {
+                buffer.get_mut(area.x + offset, y).set_symbol("┃");
+            });
     }
 
     #[inline]
     fn alter_mode(&mut self, mode: CompMode) -> Option<CompState> {
-        match mode {
-            CompMode::Enter => Some(CompState::Exit),
-            m => {
-                self.comp_mode = m;
-                None
-            }
-        }
+        self.comp_mode = mode;
+        None
     }
 }
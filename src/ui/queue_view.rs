@@ -0,0 +1,344 @@
+// Date: Thu Nov  2 22:40:17 2023
+// Mail: lunar_ubuntu@qq.com
+// Author: https://github.com/xiaoqixian
+
+use std::{
+    collections::HashSet,
+    time::{Duration, Instant}
+};
+
+use crossterm::event::{Event, KeyCode};
+
+use tui::{
+    layout::{Constraint, Rect},
+    widgets::{List, ListItem, ListState, StatefulWidget},
+    style::{Style, Color, Modifier},
+    text::Span
+};
+
+use kanal::{Receiver, Sender};
+
+use crate::playback::{Command, Lane, Notice, QueueSnapshot, display_name};
+
+use super::component::{
+    CompState,
+    Component,
+    CompMode,
+    Query,
+    QueryResponse,
+    Attribution
+};
+
+/// How long a report of missing playlist entries stays.
+const REPORT_DURATION: Duration = Duration::from_secs(10);
+
+/// QueueView shows both lanes of the play queue,
+/// the user lane on top of the context lane.
+/// It keeps its own snapshot of the queue, which is
+/// refreshed by the QueueChanged notices.
+///
+/// When entered, j/k moves over the songs, Enter/l plays the
+/// selected one right away, `d` removes it, J/K moves it
+/// down/up within its lane, `c` clears its lane and `u`
+/// removes the repeated songs. Space marks the selected
+/// song and `e` edits the tags of the marked songs, or of
+/// the selected one if none is marked. `y` copies those
+/// songs the same way and `p` inserts the copies below the
+/// selected song.
+pub struct QueueView {
+    constraint: Constraint,
+    area: Option<Rect>,
+    mode: CompMode,
+    notices: Receiver<Notice>,
+    commands: Sender<Command>,
+    editor: Sender<Vec<String>>,
+    snapshot: QueueSnapshot,
+    /// index of the selected song, over both lanes
+    selected: Option<usize>,
+    marked: HashSet<String>,
+    /// the songs copied by `y`
+    yanked: Vec<String>,
+    /// the last report of missing entries and when it came
+    report: Option<(String, Instant)>
+}
+
+impl QueueView {
+    pub fn new(
+        c: Constraint,
+        snapshot: QueueSnapshot,
+        notices: Receiver<Notice>,
+        commands: Sender<Command>,
+        editor: Sender<Vec<String>>
+    ) -> Self {
+        Self {
+            constraint: c,
+            area: None,
+            mode: CompMode::Leave,
+            notices,
+            commands,
+            editor,
+            snapshot,
+            selected: None,
+            marked: HashSet::new(),
+            yanked: Vec::new(),
+            report: None
+        }
+    }
+
+    #[inline]
+    fn songs(&self) -> impl Iterator<Item = &String> {
+        self.snapshot.user.iter().chain(self.snapshot.context.iter())
+    }
+
+    #[inline]
+    fn len(&self) -> usize {
+        self.snapshot.user.len() + self.snapshot.context.len()
+    }
+
+    /// The lane of a song and its index in the lane.
+    fn locate(&self, i: usize) -> Option<(Lane, usize)> {
+        let user_len = self.snapshot.user.len();
+        match i < user_len {
+            true => Some((Lane::User, i)),
+            false if i < self.len() => Some((Lane::Context, i - user_len)),
+            false => None
+        }
+    }
+
+    /// Move the selected song one down or up its lane,
+    /// the selection follows it.
+    fn move_selected(&mut self, down: bool) {
+        let (lane, index) = match self.selected.and_then(|i| self.locate(i)) {
+            None => return,
+            Some(located) => located
+        };
+        let lane_len = match lane {
+            Lane::User => self.snapshot.user.len(),
+            Lane::Context => self.snapshot.context.len()
+        };
+
+        let to = match down {
+            true if index + 1 < lane_len => index + 1,
+            false if index > 0 => index - 1,
+            _ => return
+        };
+        let _ = self.commands.send(Command::Move { lane, from: index, to });
+        self.selected = self.selected.map(|i| i + to - index);
+    }
+
+    /// Insert the copied songs below the selected one, in
+    /// the lane of the selected song. They go to the context
+    /// if the queue is empty.
+    fn paste(&mut self) {
+        let (lane, index) = match self.selected.and_then(|i| self.locate(i)) {
+            None => (Lane::Context, 0),
+            Some((lane, index)) => (lane, index + 1)
+        };
+
+        // each song pushes the ones inserted before it down
+        self.yanked.iter().rev().for_each(|path| {
+            let _ = self.commands.send(Command::Insert {
+                lane,
+                index,
+                path: path.clone()
+            });
+        });
+    }
+
+    fn fix_selection(&mut self) {
+        let len = self.len();
+        self.selected = match self.selected {
+            _ if len == 0 => None,
+            Some(i) => Some(std::cmp::min(i, len - 1)),
+            None if matches!(self.mode, CompMode::Enter) => Some(0),
+            None => None
+        };
+    }
+
+    /// The marked songs in the order of the queue,
+    /// or the selected one.
+    fn picked(&self) -> Vec<String> {
+        let marked = self.songs()
+            .filter(|song| self.marked.contains(*song))
+            .cloned()
+            .collect::<Vec<String>>();
+
+        match marked.is_empty() {
+            false => marked,
+            true => self.selected
+                .and_then(|i| self.songs().nth(i))
+                .cloned()
+                .into_iter()
+                .collect()
+        }
+    }
+
+    fn drain_notices(&mut self) {
+        while let Ok(Some(notice)) = self.notices.try_recv() {
+            match notice {
+                Notice::QueueChanged(snapshot) => {
+                    self.snapshot = snapshot;
+                    self.fix_selection();
+                },
+                Notice::MissingEntries { playlist, missing } => {
+                    let report = format!("{} 中 {} 首歌曲缺失", display_name(&playlist), missing.len());
+                    self.report = Some((report, Instant::now()));
+                },
+                Notice::Unplayable { entry, error } => {
+                    let report = format!("已跳过 {}: {}", display_name(&entry), super::describe(&error));
+                    self.report = Some((report, Instant::now()));
+                },
+                _ => {}
+            }
+        }
+    }
+
+    fn header<'a>(title: &str, len: usize) -> ListItem<'a> {
+        ListItem::new(Span::styled(
+            format!("{} ({})", title, len),
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD)
+        ))
+    }
+
+    fn song<'a>(&self, path: &str) -> ListItem<'a> {
+        match self.marked.contains(path) {
+            true => ListItem::new(Span::styled(
+                format!("* {}", display_name(path)),
+                Style::default().fg(Color::Green)
+            )),
+            false => ListItem::new(format!("  {}", display_name(path)))
+        }
+    }
+}
+
+impl Component for QueueView {
+    fn query(&self, q: Query) -> QueryResponse {
+        match q {
+            Query::Title => QueryResponse::Title(Some(String::from("播放队列"))),
+            Query::Constraint =>
+                QueryResponse::Constraint(self.constraint),
+            // notices are only checked when rendered
+            Query::UpdateDuration =>
+                QueryResponse::UpdateDuration(Some(std::time::Duration::from_millis(500)))
+        }
+    }
+
+    fn set_attr(&mut self, attr: Attribution) -> Option<CompState> {
+        match attr {
+            Attribution::Area(area) => {
+                self.area = Some(area);
+                None
+            },
+            Attribution::Mode(mode) => self.alter_mode(mode)
+        }
+    }
+
+    fn feed_event(&mut self, event: Event) -> CompState {
+        let code = match event {
+            Event::Key(key_event) => key_event.code,
+            _ => return CompState::Stay
+        };
+
+        let len = self.len();
+        match (code, self.selected) {
+            (KeyCode::Esc, _) => return CompState::Exit,
+            (KeyCode::Down | KeyCode::Char('j'), Some(i)) =>
+                self.selected = Some(std::cmp::min(len - 1, i + 1)),
+            (KeyCode::Up | KeyCode::Char('k'), Some(i)) =>
+                self.selected = Some(std::cmp::max(1, i) - 1),
+            (KeyCode::Char(' '), Some(i)) => {
+                let song = self.songs().nth(i).cloned();
+                if let Some(song) = song {
+                    if !self.marked.remove(&song) {
+                        self.marked.insert(song);
+                    }
+                }
+            },
+            (KeyCode::Enter | KeyCode::Char('l'), Some(i)) => {
+                if let Some((lane, index)) = self.locate(i) {
+                    let _ = self.commands.send(Command::JumpTo { lane, index });
+                }
+            },
+            (KeyCode::Char('d'), Some(i)) => {
+                if let Some((lane, index)) = self.locate(i) {
+                    let _ = self.commands.send(Command::Remove { lane, index });
+                }
+            },
+            (KeyCode::Char('J'), _) => self.move_selected(true),
+            (KeyCode::Char('K'), _) => self.move_selected(false),
+            (KeyCode::Char('c'), Some(i)) => {
+                if let Some((lane, _)) = self.locate(i) {
+                    let _ = self.commands.send(Command::Clear(lane));
+                }
+            },
+            (KeyCode::Char('u'), _) => {
+                let _ = self.commands.send(Command::Dedupe);
+            },
+            (KeyCode::Char('y'), _) => {
+                self.yanked = self.picked();
+                self.marked.clear();
+            },
+            (KeyCode::Char('p'), _) => self.paste(),
+            (KeyCode::Char('e'), _) => {
+                let songs = self.picked();
+                if !songs.is_empty() {
+                    let _ = self.editor.send(songs);
+                    self.marked.clear();
+                }
+            },
+            _ => {}
+        }
+        CompState::Stay
+    }
+
+    fn render(&mut self, buffer: &mut tui::buffer::Buffer) {
+        self.drain_notices();
+
+        let area = match self.area {
+            None => return,
+            Some(area) => area
+        };
+
+        let mut items = Vec::new();
+        if let Some((ref report, at)) = self.report {
+            match at.elapsed() < REPORT_DURATION {
+                true => items.push(ListItem::new(Span::styled(
+                    report.clone(),
+                    Style::default().fg(Color::Red)
+                ))),
+                false => self.report = None
+            }
+        }
+
+        // the selected song is counted past the headers above it
+        let user_len = self.snapshot.user.len();
+        let selected = self.selected.map(|i| match i < user_len {
+            true => items.len() + 1 + i,
+            false => items.len() + 2 + i
+        });
+
+        items.push(Self::header("待播", user_len));
+        items.extend(self.snapshot.user.iter().map(|path| self.song(path)));
+        items.push(Self::header("当前歌单", self.snapshot.context.len()));
+        items.extend(self.snapshot.context.iter().map(|path| self.song(path)));
+
+        let highlight = match self.mode {
+            CompMode::Enter => Style::default().add_modifier(Modifier::REVERSED),
+            _ => Style::default()
+        };
+
+        let mut state = ListState::default();
+        state.select(selected);
+        List::new(items)
+            .highlight_style(highlight)
+            .render(area, buffer, &mut state);
+    }
+
+    fn alter_mode(&mut self, mode: CompMode) -> Option<CompState> {
+        self.mode = mode;
+        self.fix_selection();
+        None
+    }
+}
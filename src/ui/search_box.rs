@@ -5,10 +5,15 @@
 use tui::{
     layout::{Constraint, Rect}, 
     widgets::{Paragraph, Widget},
-    text::Span
+    style::{Style, Color, Modifier},
+    text::{Span, Spans}
 };
 
-use crossterm::event::{Event, KeyCode};
+use crossterm::event::{Event, KeyCode, KeyModifiers};
+
+use kanal::Sender;
+
+use crate::library::search::query::{self, ParseError};
 
 use super::component::{
     CompState, 
@@ -19,31 +24,122 @@ use super::component::{
     Attribution
 };
 
+/// What the search box tells the search results.
+#[derive(Debug, Clone)]
+pub enum SearchEvent {
+    /// the input changed
+    Input(String),
+    /// move the selection of the results
+    Select(isize),
+    Play,
+    Enqueue
+}
+
+/// SearchBox is a line of input, every edit is sent to
+/// the search results. Up/Down move over the results,
+/// Enter plays the selected one and Tab enqueues it.
+/// The input is a query, see library/search/query.rs, and
+/// if it does not parse, the error is shown under it.
+///
+/// Besides the arrows, Home/End, Ctrl-A/Ctrl-E move the
+/// cursor, Ctrl-W and Alt-Backspace delete a word back,
+/// Ctrl-U and Ctrl-K delete to the start and to the end.
 pub struct SearchBox {
     constraint: Constraint,
     area: Rect,
     mode: CompMode,
     input: String,
+    /// the cursor as a char index into input
+    cursor: usize,
+    events: Sender<SearchEvent>,
+    error: Option<ParseError>,
     blink: bool
 }
 
 impl SearchBox {
-    pub fn new(c: Constraint) -> Self {
+    pub fn new(c: Constraint, events: Sender<SearchEvent>) -> Self {
         Self {
             constraint: c,
             area: Rect::default(),
             mode: CompMode::Leave,
             input: String::new(),
+            cursor: 0,
+            events,
+            error: None,
             blink: false
         }
     }
+
+    /// The byte offset of a char index.
+    #[inline]
+    fn offset(&self, index: usize) -> usize {
+        self.input
+            .char_indices()
+            .nth(index)
+            .map_or(self.input.len(), |(offset, _)| offset)
+    }
+
+    #[inline]
+    fn len(&self) -> usize {
+        self.input.chars().count()
+    }
+
+    /// Where the word before the cursor starts, the spaces
+    /// right before the cursor belong to the word.
+    fn word_start(&self) -> usize {
+        let chars = self.input.chars().take(self.cursor).collect::<Vec<char>>();
+        let mut start = chars.len();
+
+        while start > 0 && chars[start - 1].is_whitespace() {
+            start -= 1;
+        }
+        while start > 0 && !chars[start - 1].is_whitespace() {
+            start -= 1;
+        }
+        start
+    }
+
+    /// Delete the chars in [from, to).
+    fn delete(&mut self, from: usize, to: usize) {
+        let (from_offset, to_offset) = (self.offset(from), self.offset(to));
+        self.input.replace_range(from_offset..to_offset, "");
+        self.cursor = from;
+    }
+
+    fn edit(&mut self, code: KeyCode, modifiers: KeyModifiers) -> bool {
+        let ctrl = modifiers.contains(KeyModifiers::CONTROL);
+        let alt = modifiers.contains(KeyModifiers::ALT);
+
+        match code {
+            KeyCode::Char('w') if ctrl => self.delete(self.word_start(), self.cursor),
+            KeyCode::Backspace if alt => self.delete(self.word_start(), self.cursor),
+            KeyCode::Char('u') if ctrl => self.delete(0, self.cursor),
+            KeyCode::Char('k') if ctrl => self.delete(self.cursor, self.len()),
+            KeyCode::Char('a') if ctrl => self.cursor = 0,
+            KeyCode::Char('e') if ctrl => self.cursor = self.len(),
+            KeyCode::Char(_) if ctrl || alt => return false,
+            KeyCode::Char(c) => {
+                let offset = self.offset(self.cursor);
+                self.input.insert(offset, c);
+                self.cursor += 1;
+            },
+            KeyCode::Backspace if self.cursor > 0 => self.delete(self.cursor - 1, self.cursor),
+            KeyCode::Delete if self.cursor < self.len() => self.delete(self.cursor, self.cursor + 1),
+            KeyCode::Left => self.cursor = self.cursor.saturating_sub(1),
+            KeyCode::Right => self.cursor = std::cmp::min(self.len(), self.cursor + 1),
+            KeyCode::Home => self.cursor = 0,
+            KeyCode::End => self.cursor = self.len(),
+            _ => return false
+        }
+        true
+    }
 }
 
 impl Component for SearchBox {
     fn query(&self, q: Query) -> QueryResponse {
         match q {
             Query::Title => QueryResponse::Title(Some(String::from("搜索栏"))),
-            Query::Constraint => QueryResponse::Constraint(self.constraint.clone()),
+            Query::Constraint => QueryResponse::Constraint(self.constraint),
             Query::UpdateDuration => QueryResponse::UpdateDuration(self.update_duration())
         }
     }
@@ -57,35 +153,76 @@ impl Component for SearchBox {
     }
 
     fn render(&mut self, buffer: &mut tui::buffer::Buffer) {
-        let text = Span::raw(
-            match self.mode {
-                CompMode::Enter => {
-                    self.blink = !self.blink;
-                    let mut display_str = self.input.clone();
-                    
-                    if self.blink {
-                        display_str.push('|');
-                    }
-
-                    display_str
-                },
-                _ => String::new()
+        let mut lines = match self.mode {
+            CompMode::Enter if self.input.is_empty() => {
+                self.blink = !self.blink;
+                vec![Spans::from(if self.blink { "|" } else { "" })]
+            },
+            _ => {
+                // the char under the cursor is reversed,
+                // and the one a parse error points at is red
+                let cursor = match self.mode {
+                    CompMode::Enter => Some(self.cursor),
+                    _ => None
+                };
+                let error = self.error.as_ref().map(|error| error.position);
+
+                let mut spans = self.input
+                    .chars()
+                    .chain(std::iter::once(' '))
+                    .enumerate()
+                    .map(|(i, c)| {
+                        let mut style = Style::default();
+                        if Some(i) == error {
+                            style = style.fg(Color::Red).add_modifier(Modifier::UNDERLINED);
+                        }
+                        if Some(i) == cursor {
+                            style = style.add_modifier(Modifier::REVERSED);
+                        }
+                        Span::styled(c.to_string(), style)
+                    })
+                    .collect::<Vec<Span>>();
+                if cursor.is_none() && error != Some(self.len()) {
+                    spans.pop();
+                }
+                vec![Spans::from(spans)]
             }
-        );
+        };
 
-        Paragraph::new(text)
+        if let Some(error) = self.error.as_ref() {
+            lines.push(Spans::from(Span::styled(
+                error.to_string(),
+                Style::default().fg(Color::Red)
+            )));
+        }
+
+        Paragraph::new(lines)
             .render(self.area, buffer);
     }
 
     fn feed_event(&mut self, event: crossterm::event::Event) -> CompState {
-        match event {
-            Event::Key(key_event) => match key_event.code {
-                KeyCode::Esc => return CompState::Exit,
-                _ => {}
-            },
-            _ => {}
-        }
+        let key_event = match event {
+            Event::Key(key_event) => key_event,
+            _ => return CompState::Stay
+        };
+
+        let event = match key_event.code {
+            KeyCode::Esc => return CompState::Exit,
+            KeyCode::Up => SearchEvent::Select(-1),
+            KeyCode::Down => SearchEvent::Select(1),
+            KeyCode::Enter => SearchEvent::Play,
+            KeyCode::Tab => SearchEvent::Enqueue,
+            code => {
+                let input = self.input.clone();
+                if !self.edit(code, key_event.modifiers) || self.input == input {
+                    return CompState::Stay;
+                }
+                self.error = query::parse(&self.input).err();
+                SearchEvent::Input(self.input.clone())
+            }
+        };
 
+        let _ = self.events.send(event);
         CompState::Stay
     }
 
@@ -103,3 +240,47 @@ impl Component for SearchBox {
     }
 }
 
+#[test]
+fn test_edit_multibyte() {
+    use crossterm::event::KeyEvent;
+
+    let (tx, rx) = kanal::unbounded();
+    let mut search_box = SearchBox::new(Constraint::Length(1), tx);
+    let mut press = |code, modifiers| {
+        search_box.feed_event(Event::Key(KeyEvent::new(code, modifiers)));
+        (search_box.input.clone(), search_box.cursor)
+    };
+
+    "周杰伦 七里香".chars().for_each(|c| {
+        press(KeyCode::Char(c), KeyModifiers::NONE);
+    });
+    assert_eq!(press(KeyCode::Left, KeyModifiers::NONE), (String::from("周杰伦 七里香"), 6));
+    assert_eq!(press(KeyCode::Backspace, KeyModifiers::NONE), (String::from("周杰伦 七香"), 5));
+    assert_eq!(press(KeyCode::Char('é'), KeyModifiers::NONE), (String::from("周杰伦 七é香"), 6));
+    assert_eq!(press(KeyCode::Delete, KeyModifiers::NONE), (String::from("周杰伦 七é"), 6));
+    assert_eq!(press(KeyCode::Right, KeyModifiers::NONE).1, 6);
+
+    // a word back takes the spaces before the cursor along
+    assert_eq!(press(KeyCode::Char('w'), KeyModifiers::CONTROL), (String::from("周杰伦 "), 4));
+    assert_eq!(press(KeyCode::Backspace, KeyModifiers::ALT), (String::new(), 0));
+
+    "晴天 夜曲".chars().for_each(|c| {
+        press(KeyCode::Char(c), KeyModifiers::NONE);
+    });
+    assert_eq!(press(KeyCode::Home, KeyModifiers::NONE).1, 0);
+    assert_eq!(press(KeyCode::Right, KeyModifiers::NONE).1, 1);
+    assert_eq!(press(KeyCode::Char('k'), KeyModifiers::CONTROL), (String::from("晴"), 1));
+    assert_eq!(press(KeyCode::Char('a'), KeyModifiers::CONTROL).1, 0);
+    assert_eq!(press(KeyCode::Char('e'), KeyModifiers::CONTROL).1, 1);
+    assert_eq!(press(KeyCode::Char('u'), KeyModifiers::CONTROL), (String::new(), 0));
+
+    // every edit is sent, the moves of the cursor are not
+    let inputs = std::iter::from_fn(|| rx.try_recv().ok().flatten())
+        .filter_map(|event| match event {
+            SearchEvent::Input(input) => Some(input),
+            _ => None
+        })
+        .collect::<Vec<String>>();
+    assert_eq!(inputs.len(), 7 + 3 + 2 + 5 + 2);
+    assert_eq!(inputs.last().map(String::as_str), Some(""));
+}
@@ -0,0 +1,281 @@
+// Date: Sun Nov 26 14:08:51 2023
+// Mail: lunar_ubuntu@qq.com
+// Author: https://github.com/xiaoqixian
+
+use std::sync::{Arc, RwLock};
+
+use crossterm::event::{Event, KeyCode};
+
+use tui::{
+    layout::{Constraint, Rect},
+    widgets::{List, ListItem, ListState, Paragraph, StatefulWidget, Widget},
+    style::{Style, Color, Modifier},
+    text::{Span, Spans}
+};
+
+use kanal::{Receiver, Sender};
+
+use crate::{
+    playback::Command,
+    library::{Library, stats::StatsBook, search::{Hit, SearchIndex}}
+};
+
+use super::{
+    search_box::SearchEvent,
+    component::{
+        CompState,
+        Component,
+        CompMode,
+        Query,
+        QueryResponse,
+        Attribution
+    }
+};
+
+/// No more results are shown than this.
+const MAX_RESULTS: usize = 200;
+
+/// SearchView shows the results of what is typed into the
+/// search box, the best match on top and the matched chars
+/// highlighted. The results are searched again when the
+/// library or the play statistics change.
+///
+/// When entered, j/k moves the selection, Enter plays the
+/// selected song and `a` enqueues it.
+pub struct SearchView {
+    constraint: Constraint,
+    area: Option<Rect>,
+    mode: CompMode,
+    events: Receiver<SearchEvent>,
+    commands: Sender<Command>,
+    library: Arc<RwLock<Library>>,
+    stats: Arc<RwLock<StatsBook>>,
+    /// built at the first search
+    index: Option<SearchIndex>,
+    /// the generation of the stats last searched with
+    stats_generation: u64,
+    input: String,
+    hits: Vec<Hit>,
+    state: ListState
+}
+
+impl SearchView {
+    pub fn new(
+        c: Constraint,
+        events: Receiver<SearchEvent>,
+        commands: Sender<Command>,
+        library: Arc<RwLock<Library>>,
+        stats: Arc<RwLock<StatsBook>>
+    ) -> Self {
+        Self {
+            constraint: c,
+            area: None,
+            mode: CompMode::Leave,
+            events,
+            commands,
+            library,
+            stats,
+            index: None,
+            stats_generation: 0,
+            input: String::new(),
+            hits: Vec::new(),
+            state: ListState::default()
+        }
+    }
+
+    fn search(&mut self) {
+        if self.input.trim().is_empty() {
+            self.hits.clear();
+            self.state.select(None);
+            return;
+        }
+
+        // the library is only being written for a moment,
+        // the results are refreshed at a later round then.
+        let library = match self.library.try_read() {
+            Ok(library) => library,
+            Err(_) => return
+        };
+        if self.index.as_ref().is_none_or(|index| index.generation() != library.generation()) {
+            self.index = Some(SearchIndex::build(&library));
+        }
+        drop(library);
+
+        let stats = self.stats.read().unwrap();
+        self.stats_generation = stats.generation();
+
+        // the search box shows what is wrong with the query,
+        // the results of the last good one stay meanwhile
+        let hits = match self.index.as_ref().unwrap().search(&self.input, &stats, MAX_RESULTS) {
+            Ok(hits) => hits,
+            Err(_) => return
+        };
+        drop(stats);
+
+        // the selection stays on the same song if it is still there
+        let selected = self.state.selected()
+            .and_then(|i| self.hits.get(i))
+            .and_then(|hit| hits.iter().position(|h| h.path == hit.path));
+        self.hits = hits;
+        self.state.select(match self.hits.is_empty() {
+            true => None,
+            false => Some(selected.unwrap_or(0))
+        });
+    }
+
+    fn select(&mut self, step: isize) {
+        if self.hits.is_empty() {
+            return;
+        }
+        let selected = self.state.selected().unwrap_or(0) as isize + step;
+        self.state.select(Some(selected.clamp(0, self.hits.len() as isize - 1) as usize));
+    }
+
+    fn send_selected<F>(&self, command: F)
+    where F: Fn(String) -> Command
+    {
+        if let Some(hit) = self.state.selected().and_then(|i| self.hits.get(i)) {
+            let _ = self.commands.send(command(hit.path.clone()));
+        }
+    }
+
+    fn drain_events(&mut self) {
+        // only the last input of a burst is searched, but
+        // before the results are acted on
+        let mut pending = false;
+
+        while let Ok(Some(event)) = self.events.try_recv() {
+            // a new input starts over from the best match
+            if let SearchEvent::Input(input) = event {
+                self.input = input;
+                self.state.select(None);
+                pending = true;
+                continue;
+            }
+            if pending {
+                self.search();
+                pending = false;
+            }
+
+            match event {
+                SearchEvent::Select(step) => self.select(step),
+                SearchEvent::Play => self.send_selected(Command::Play),
+                SearchEvent::Enqueue => self.send_selected(Command::PlayNext),
+                SearchEvent::Input(_) => {}
+            }
+        }
+
+        let stale = self.index.as_ref().is_some_and(|index| {
+            self.library.try_read().is_ok_and(|library| library.generation() != index.generation())
+                || self.stats.read().unwrap().generation() != self.stats_generation
+        });
+        if pending || stale {
+            self.search();
+        }
+    }
+
+    fn item<'a>(hit: &'a Hit) -> ListItem<'a> {
+        let highlight = Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD);
+
+        // group the chars into runs of matched and not matched
+        let mut spans = Vec::new();
+        let mut run = String::new();
+        let mut matched = false;
+        let mut positions = hit.positions.iter().peekable();
+
+        for (i, c) in hit.text.chars().enumerate() {
+            let is_match = positions.next_if(|position| **position == i).is_some();
+            if is_match != matched && !run.is_empty() {
+                spans.push(match matched {
+                    true => Span::styled(std::mem::take(&mut run), highlight),
+                    false => Span::raw(std::mem::take(&mut run))
+                });
+            }
+            matched = is_match;
+            run.push(c);
+        }
+        if !run.is_empty() {
+            spans.push(match matched {
+                true => Span::styled(run, highlight),
+                false => Span::raw(run)
+            });
+        }
+
+        ListItem::new(Spans::from(spans))
+    }
+}
+
+impl Component for SearchView {
+    fn query(&self, q: Query) -> QueryResponse {
+        match q {
+            Query::Title => QueryResponse::Title(Some(String::from("搜索结果"))),
+            Query::Constraint =>
+                QueryResponse::Constraint(self.constraint),
+            Query::UpdateDuration =>
+                QueryResponse::UpdateDuration(Some(std::time::Duration::from_secs(1)))
+        }
+    }
+
+    fn set_attr(&mut self, attr: Attribution) -> Option<CompState> {
+        match attr {
+            Attribution::Area(area) => {
+                self.area = Some(area);
+                None
+            },
+            Attribution::Mode(mode) => self.alter_mode(mode)
+        }
+    }
+
+    fn feed_event(&mut self, event: Event) -> CompState {
+        if let Event::Key(key_event) = event {
+            match key_event.code {
+                KeyCode::Esc => return CompState::Exit,
+                KeyCode::Down | KeyCode::Char('j') => self.select(1),
+                KeyCode::Up | KeyCode::Char('k') => self.select(-1),
+                KeyCode::Enter => self.send_selected(Command::Play),
+                KeyCode::Char('a') => self.send_selected(Command::PlayNext),
+                _ => {}
+            }
+        }
+
+        CompState::Stay
+    }
+
+    fn render(&mut self, buffer: &mut tui::buffer::Buffer) {
+        self.drain_events();
+
+        let area = match self.area {
+            None => return,
+            Some(area) => area
+        };
+
+        if self.hits.is_empty() {
+            let hint = match self.input.trim().is_empty() {
+                true => "在搜索栏输入歌名、歌手或专辑，支持拼音",
+                false => "没有找到"
+            };
+            Paragraph::new(Span::styled(hint, Style::default().fg(Color::DarkGray)))
+                .render(area, buffer);
+            return;
+        }
+
+        let items = self.hits
+            .iter()
+            .map(|hit| Self::item(hit))
+            .collect::<Vec<ListItem>>();
+
+        // the selection is moved from the search box as well
+        let highlight = match self.mode {
+            CompMode::Enter => Style::default().add_modifier(Modifier::REVERSED),
+            _ => Style::default().bg(Color::DarkGray)
+        };
+
+        let list = List::new(items).highlight_style(highlight);
+        StatefulWidget::render(list, area, buffer, &mut self.state);
+    }
+
+    fn alter_mode(&mut self, mode: CompMode) -> Option<CompState> {
+        self.mode = mode;
+        None
+    }
+}
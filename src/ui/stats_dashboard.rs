@@ -0,0 +1,241 @@
+// Date: Mon Dec  4 21:35:52 2023
+// Mail: lunar_ubuntu@qq.com
+// Author: https://github.com/xiaoqixian
+
+use std::sync::{Arc, RwLock};
+
+use crossterm::event::{Event, KeyCode};
+
+use tui::{
+    buffer::Buffer,
+    layout::{Constraint, Direction, Layout, Rect},
+    widgets::{Block, Borders, BorderType, BarChart, Clear, List, ListItem, Widget},
+    style::{Style, Color, Modifier},
+    text::Span
+};
+
+use kanal::Receiver;
+
+use crate::{
+    config::config,
+    playback::{Notice, history::{HistoryEntry, unix_now}},
+    library::{
+        Library,
+        report::{self, Period, Report, Share, Top}
+    }
+};
+
+use super::popup::centered_rect;
+
+/// The tops and their borders.
+const TOP_HEIGHT: u16 = report::TOP as u16 + 2;
+
+const WEEKDAYS: [&str; 7] = ["一", "二", "三", "四", "五", "六", "日"];
+
+fn period_title(period: Period) -> &'static str {
+    match period {
+        Period::Week => "近一周",
+        Period::Month => "近一月",
+        Period::Year => "近一年",
+        Period::All => "全部"
+    }
+}
+
+/// Like 3h05m, or 5m under an hour.
+fn format_listened(secs: u64) -> String {
+    match secs / 3600 {
+        0 => format!("{}m", secs / 60),
+        hours => format!("{}h{:02}m", hours, secs / 60 % 60)
+    }
+}
+
+fn block(title: &str) -> Block<'_> {
+    Block::default()
+        .title(Span::styled(title, Style::default().fg(Color::Cyan)))
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+}
+
+/// A bar chart of the bars that fit in the area,
+/// the bars are as wide as the area lets them be.
+fn bar_chart(title: &str, bars: &[(String, u64)], area: Rect, buffer: &mut Buffer) {
+    // a bar takes at least a column and the gap after it
+    let inner = area.width.saturating_sub(2);
+    let data = bars.iter()
+        .take(std::cmp::max(1, inner as usize / 2))
+        .map(|(label, value)| (label.as_str(), *value))
+        .collect::<Vec<(&str, u64)>>();
+    let width = match data.len() {
+        0 => 1,
+        n => std::cmp::max(2, inner / n as u16) - 1
+    };
+
+    BarChart::default()
+        .block(block(title))
+        .data(&data)
+        .bar_width(width)
+        .bar_gap(1)
+        .bar_style(Style::default().fg(Color::Yellow))
+        .value_style(Style::default().fg(Color::Black).bg(Color::Yellow))
+        .label_style(Style::default().fg(Color::DarkGray))
+        .render(area, buffer);
+}
+
+fn top_list(title: &str, tops: &[Top], area: Rect, buffer: &mut Buffer) {
+    let items = match tops.is_empty() {
+        true => vec![ListItem::new(Span::styled("还没有播放完的歌", Style::default().fg(Color::DarkGray)))],
+        false => tops.iter()
+            .enumerate()
+            .map(|(i, top)| ListItem::new(format!("{:>2}. {} ({})", i + 1, top.name, top.plays)))
+            .collect()
+    };
+    Widget::render(List::new(items).block(block(title)), area, buffer);
+}
+
+fn shares(shares: &[Share]) -> Vec<(String, u64)> {
+    shares.iter().map(|share| (share.name.clone(), share.tracks as u64)).collect()
+}
+
+/// StatsDashboard pops up over the whole ui with the
+/// listening statistics of the history: the top artists,
+/// albums and tracks of a period, when the songs were
+/// listened to, in minutes, and what the library is made
+/// of. It is opened through its channel, Tab goes to the
+/// next period and Esc closes it.
+///
+/// Like PlaylistPicker, the ui loop renders it last.
+pub struct StatsDashboard {
+    requests: Receiver<()>,
+    notices: Receiver<Notice>,
+    library: Arc<RwLock<Library>>,
+    entries: Vec<HistoryEntry>,
+    period: Period,
+    /// Some while it is open
+    report: Option<Report>
+}
+
+impl StatsDashboard {
+    pub fn new(
+        requests: Receiver<()>,
+        entries: Vec<HistoryEntry>,
+        notices: Receiver<Notice>,
+        library: Arc<RwLock<Library>>
+    ) -> Self {
+        Self {
+            requests,
+            notices,
+            library,
+            entries,
+            period: Period::Week,
+            report: None
+        }
+    }
+
+    #[inline]
+    pub fn is_open(&self) -> bool {
+        self.report.is_some()
+    }
+
+    fn refresh(&mut self) {
+        let offset = config().stats.utc_offset_minutes as i64 * 60;
+        let library = self.library.read().unwrap();
+        self.report = Some(Report::new(self.period, &self.entries, &library, unix_now(), offset));
+    }
+
+    pub fn feed_event(&mut self, event: Event) {
+        let code = match event {
+            Event::Key(key_event) => key_event.code,
+            _ => return
+        };
+
+        match code {
+            KeyCode::Esc | KeyCode::Char('q') => self.report = None,
+            KeyCode::Tab => {
+                self.period = self.period.next();
+                self.refresh();
+            },
+            _ => {}
+        }
+    }
+
+    pub fn render(&mut self, buffer: &mut Buffer) {
+        let mut played = false;
+        while let Ok(Some(notice)) = self.notices.try_recv() {
+            if let Notice::Played(entry) = notice {
+                self.entries.push(entry);
+                played = true;
+            }
+        }
+        let opened = matches!(self.requests.try_recv(), Ok(Some(_)));
+        if opened || (played && self.is_open()) {
+            self.refresh();
+        }
+
+        let report = match self.report {
+            None => return,
+            Some(ref report) => report
+        };
+
+        let title = format!(
+            "听歌统计 · {}  (Tab 切换, Esc 关闭)",
+            period_title(report.period)
+        );
+        let outer = Block::default()
+            .title(title)
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded)
+            .border_style(Style::default().fg(Color::Blue));
+
+        // laid out like a Popup, with the charts inside
+        let area = centered_rect(90, 90, buffer.area);
+        Clear.render(area, buffer);
+        let inner = outer.inner(area);
+        outer.render(area, buffer);
+        let area = inner;
+
+        let rows = Layout::default()
+            .constraints([
+                Constraint::Length(1),
+                Constraint::Length(TOP_HEIGHT),
+                Constraint::Percentage(50),
+                Constraint::Percentage(50)
+            ])
+            .split(area);
+
+        let summary = format!(
+            "播放 {} 首, 跳过 {} 首, 共听了 {}",
+            report.plays,
+            report.skips,
+            format_listened(report.listened_secs)
+        );
+        let summary = Span::styled(summary, Style::default().add_modifier(Modifier::BOLD));
+        buffer.set_span(rows[0].x, rows[0].y, &summary, rows[0].width);
+
+        let columns = |area: Rect, percentages: &[u16]| Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints(percentages.iter().map(|p| Constraint::Percentage(*p)).collect::<Vec<_>>())
+            .split(area);
+
+        let tops = columns(rows[1], &[33, 34, 33]);
+        top_list("艺术家", &report.top_artists, tops[0], buffer);
+        top_list("专辑", &report.top_albums, tops[1], buffer);
+        top_list("曲目", &report.top_tracks, tops[2], buffer);
+
+        let times = columns(rows[2], &[70, 30]);
+        let hours = report.by_hour.iter()
+            .enumerate()
+            .map(|(hour, secs)| (hour.to_string(), secs / 60))
+            .collect::<Vec<(String, u64)>>();
+        bar_chart("时段 (分钟)", &hours, times[0], buffer);
+        let weekdays = WEEKDAYS.iter()
+            .zip(report.by_weekday)
+            .map(|(day, secs)| (day.to_string(), secs / 60))
+            .collect::<Vec<(String, u64)>>();
+        bar_chart("星期 (分钟)", &weekdays, times[1], buffer);
+
+        let library = columns(rows[3], &[30, 30, 40]);
+        bar_chart("格式", &shares(&report.formats), library[0], buffer);
+        bar_chart("码率 (kbps)", &shares(&report.bitrates), library[1], buffer);
+        bar_chart("流派", &shares(&report.genres), library[2], buffer);
+    }
+}
@@ -0,0 +1,276 @@
+// Date: Thu Nov 23 20:14:37 2023
+// Mail: lunar_ubuntu@qq.com
+// Author: https://github.com/xiaoqixian
+
+use std::{
+    path::Path,
+    sync::{Arc, RwLock}
+};
+
+use crossterm::event::{Event, KeyCode};
+
+use tui::{
+    layout::Rect,
+    widgets::{Block, Borders, BorderType, List, ListItem, Widget},
+    style::{Style, Color, Modifier},
+    text::{Span, Spans}
+};
+
+use kanal::Receiver;
+
+use crate::{
+    library::Library,
+    playback::{
+        PlayerError,
+        display_name,
+        metadata::{
+            track_info,
+            edit::{Field, Edit, write_tags}
+        }
+    }
+};
+
+use super::popup::Popup;
+
+/// What the songs being edited have in a field.
+enum Value {
+    Same(Option<String>),
+    Mixed
+}
+
+struct Row {
+    field: Field,
+    value: Value,
+    /// the new text, empty to clear the field
+    edited: Option<String>
+}
+
+fn label(field: Field) -> &'static str {
+    match field {
+        Field::Title => "标题",
+        Field::Artist => "艺术家",
+        Field::Album => "专辑",
+        Field::AlbumArtist => "专辑艺术家",
+        Field::Track => "音轨",
+        Field::Disc => "碟片",
+        Field::Year => "年份",
+        Field::Genre => "流派",
+        Field::Composer => "作曲",
+        Field::Comment => "备注"
+    }
+}
+
+/// TagEditor pops up over the whole ui to edit the tags of
+/// the songs sent through its channel. When several songs
+/// are edited at once, the fields they do not agree on are
+/// shown as such, and are left alone unless changed.
+///
+/// j/k moves between the fields, Enter edits the selected
+/// one and Enter again keeps the change, `w` writes the
+/// changes to every song and Esc closes without writing.
+///
+/// Like the PlaylistPicker, the ui loop renders it last and
+/// it takes all key events while it is open.
+pub struct TagEditor {
+    requests: Receiver<Vec<String>>,
+    library: Arc<RwLock<Library>>,
+    songs: Vec<String>,
+    rows: Vec<Row>,
+    selected: usize,
+    input: Option<String>,
+    error: Option<String>
+}
+
+impl TagEditor {
+    pub fn new(requests: Receiver<Vec<String>>, library: Arc<RwLock<Library>>) -> Self {
+        Self {
+            requests,
+            library,
+            songs: Vec::new(),
+            rows: Vec::new(),
+            selected: 0,
+            input: None,
+            error: None
+        }
+    }
+
+    #[inline]
+    pub fn is_open(&self) -> bool {
+        !self.songs.is_empty()
+    }
+
+    fn open(&mut self, songs: Vec<String>) {
+        let tags = songs.iter()
+            .map(|song| track_info(song).tags)
+            .collect::<Vec<_>>();
+
+        self.rows = Field::ALL
+            .into_iter()
+            .map(|field| {
+                let mut values = tags.iter().map(|tags| field.get(tags));
+                let first = values.next().flatten();
+                let value = match values.all(|value| value == first) {
+                    true => Value::Same(first),
+                    false => Value::Mixed
+                };
+                Row { field, value, edited: None }
+            })
+            .collect();
+
+        self.songs = songs;
+        self.selected = 0;
+        self.input = None;
+        self.error = None;
+    }
+
+    fn close(&mut self) {
+        self.songs.clear();
+        self.rows.clear();
+    }
+
+    /// Write the changed fields to every song, the songs
+    /// written are probed again into the library.
+    fn write(&mut self) {
+        let edits = self.rows
+            .iter()
+            .filter_map(|row| row.edited
+                .as_ref()
+                .map(|text| (row.field, Some(text.clone()).filter(|text| !text.is_empty()))))
+            .collect::<Vec<Edit>>();
+        if edits.is_empty() {
+            return self.close();
+        }
+
+        let mut failed: Vec<(String, PlayerError)> = Vec::new();
+        let mut library = self.library.write().unwrap();
+        for song in self.songs.iter() {
+            match write_tags(Path::new(song), &edits) {
+                Ok(_) => {
+                    let _ = library.refresh(Path::new(song));
+                },
+                Err(e) => failed.push((song.clone(), e))
+            }
+        }
+        let _ = library.save();
+        drop(library);
+
+        match failed.first() {
+            None => self.close(),
+            Some((song, e)) => {
+                // keep only what is left to write
+                self.error = Some(format!("{} 首写入失败, {}: {:?}", failed.len(), display_name(song), e));
+                self.songs = failed.into_iter().map(|(song, _)| song).collect();
+            }
+        }
+    }
+
+    fn feed_input(&mut self, code: KeyCode) {
+        let Some(ref mut text) = self.input else {
+            return;
+        };
+
+        match code {
+            KeyCode::Esc => self.input = None,
+            KeyCode::Char(c) => text.push(c),
+            KeyCode::Backspace => {
+                let _ = text.pop();
+            },
+            KeyCode::Enter => {
+                let text = self.input.take().unwrap();
+                let row = &mut self.rows[self.selected];
+                row.edited = match row.value {
+                    Value::Same(ref value) if value.as_deref().unwrap_or_default() == text => None,
+                    _ => Some(text)
+                };
+            },
+            _ => {}
+        }
+    }
+
+    pub fn feed_event(&mut self, event: Event) {
+        let code = match event {
+            Event::Key(key_event) => key_event.code,
+            _ => return
+        };
+
+        if self.input.is_some() {
+            return self.feed_input(code);
+        }
+
+        match code {
+            KeyCode::Esc => self.close(),
+            KeyCode::Down | KeyCode::Char('j') =>
+                self.selected = std::cmp::min(self.rows.len() - 1, self.selected + 1),
+            KeyCode::Up | KeyCode::Char('k') =>
+                self.selected = std::cmp::max(1, self.selected) - 1,
+            KeyCode::Enter => {
+                let row = &self.rows[self.selected];
+                self.input = Some(match (&row.edited, &row.value) {
+                    (Some(text), _) => text.clone(),
+                    (None, Value::Same(value)) => value.clone().unwrap_or_default(),
+                    (None, Value::Mixed) => String::new()
+                });
+            },
+            KeyCode::Char('w') => self.write(),
+            _ => {}
+        }
+    }
+
+    fn item(&self, i: usize, row: &Row) -> ListItem<'static> {
+        let label = Span::styled(
+            format!("{:<8}", label(row.field)),
+            Style::default().fg(Color::Yellow)
+        );
+
+        let value = match (i == self.selected, &self.input, &row.edited, &row.value) {
+            (true, Some(text), _, _) => Span::raw(format!("{}|", text)),
+            (_, _, Some(text), _) => Span::styled(text.clone(), Style::default().fg(Color::Green)),
+            (_, _, None, Value::Same(value)) => Span::raw(value.clone().unwrap_or_default()),
+            (_, _, None, Value::Mixed) =>
+                Span::styled("<多个值>", Style::default().fg(Color::DarkGray))
+        };
+
+        let style = match i == self.selected && self.input.is_none() {
+            true => Style::default().add_modifier(Modifier::REVERSED),
+            false => Style::default()
+        };
+        ListItem::new(Spans::from(vec![label, value])).style(style)
+    }
+
+    pub fn render(&mut self, buffer: &mut tui::buffer::Buffer) {
+        if let Ok(Some(songs)) = self.requests.try_recv() {
+            if !songs.is_empty() {
+                self.open(songs);
+            }
+        }
+
+        if !self.is_open() {
+            return;
+        }
+
+        let title = match self.songs.len() {
+            1 => format!("编辑标签: {}", display_name(&self.songs[0])),
+            n => format!("编辑标签: {} 首歌曲", n)
+        };
+
+        let mut items = self.rows
+            .iter()
+            .enumerate()
+            .map(|(i, row)| self.item(i, row))
+            .collect::<Vec<ListItem>>();
+        items.push(ListItem::new(""));
+        items.push(ListItem::new(match self.error {
+            Some(ref error) => Span::styled(error.clone(), Style::default().fg(Color::Red)),
+            None => Span::styled("Enter 编辑  w 写入  Esc 取消", Style::default().fg(Color::DarkGray))
+        }));
+
+        let list = List::new(items).block(Block::default()
+            .title(title)
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded)
+            .border_style(Style::default().fg(Color::Blue)));
+
+        let area: Rect = buffer.area;
+        Popup::new(list, 60, 60).render(area, buffer);
+    }
+}
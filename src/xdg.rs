@@ -0,0 +1,54 @@
+// Date: Sat Nov  4 15:22:09 2023
+// Mail: lunar_ubuntu@qq.com
+// Author: https://github.com/xiaoqixian
+
+/*
+ * Provide the XDG base directories of tmusic.
+ * Each directory is created if it does not exist yet,
+ * None is returned if neither the XDG variable nor
+ * $HOME is set, or the directory cannot be created.
+ */
+
+use std::{
+    env,
+    fs,
+    path::PathBuf
+};
+
+const APP_NAME: &str = "tmusic";
+
+fn app_dir(xdg_var: &str, fallback: &str) -> Option<PathBuf> {
+    let base = match env::var_os(xdg_var) {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(fallback)
+    };
+
+    let dir = base.join(APP_NAME);
+    match fs::create_dir_all(&dir) {
+        Ok(_) => Some(dir),
+        Err(_) => None
+    }
+}
+
+/// $XDG_STATE_HOME/tmusic, for history and session data.
+#[inline]
+pub fn state_dir() -> Option<PathBuf> {
+    app_dir("XDG_STATE_HOME", ".local/state")
+}
+
+/// $XDG_CONFIG_HOME/tmusic
+#[inline]
+pub fn config_dir() -> Option<PathBuf> {
+    app_dir("XDG_CONFIG_HOME", ".config")
+}
+
+/// $XDG_DATA_HOME/tmusic, for the library and playlists.
+#[inline]
+pub fn data_dir() -> Option<PathBuf> {
+    app_dir("XDG_DATA_HOME", ".local/share")
+}
+
+#[inline]
+pub fn state_file(name: &str) -> Option<PathBuf> {
+    state_dir().map(|dir| dir.join(name))
+}